@@ -1,18 +1,19 @@
-use html5ever::parse_document;
-use html5ever::tendril::TendrilSink;
-use markup5ever_rcdom::RcDom;
-
 pub mod node;
+mod parser;
+mod selector;
 pub mod serializer;
+mod xpath;
 
+pub use html5ever::tree_builder::QuirksMode;
 pub use markup5ever_rcdom;
-pub use node::DomNode;
-
-/// Parse string input to a html tree, return the root node.
-pub fn parse(html: String) -> DomNode {
-  let mut parser = parse_document(RcDom::default(), Default::default());
-  parser.process(html.into());
-  let dom = parser.finish();
-
-  DomNode(dom.document)
-}
+pub use node::{DomNode, MimeType, SendableDocument, SourcePosition, SourceRange, SourceSpan};
+pub use parser::{
+  parse, parse_bytes, parse_bytes_with_limits, parse_bytes_with_options, parse_fragment,
+  parse_fragment_with_limits, parse_fragment_with_options, parse_from_string,
+  parse_from_string_with_limits, parse_with_diagnostics, parse_with_limits, parse_with_options,
+  parse_xml, parse_xml_with_limits, DocumentParser, FragmentContext, LimitError, ParseError,
+  ParseLimits, ParseOptions, ParseResult,
+};
+pub use selector::{NamespaceMap, Selector, SelectorError, SelectorMatch, Specificity};
+pub use serializer::{CharacterReferences, SerializeOptions};
+pub use xpath::{XPathError, XPathNode, XPathValue};
@@ -0,0 +1,128 @@
+use super::side_table::NodeTable;
+use super::DomNode;
+use crate::ParseLimits;
+use encoding_rs::{Encoding, UTF_8};
+use html5ever::tree_builder::QuirksMode;
+use markup5ever_rcdom::Handle;
+use std::cell::RefCell;
+
+/// The types of document that can be parsed, which are those `DOMParser` accepts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MimeType {
+  #[default]
+  TextHtml,
+  TextXml,
+  ApplicationXml,
+  ApplicationXhtmlXml,
+  ImageSvgXml,
+}
+
+impl MimeType {
+  /// Reads a MIME type, returning `None` for types that cannot be parsed.
+  pub fn from_mime(mime_type: &str) -> Option<MimeType> {
+    match mime_type {
+      "text/html" => Some(MimeType::TextHtml),
+      "text/xml" => Some(MimeType::TextXml),
+      "application/xml" => Some(MimeType::ApplicationXml),
+      "application/xhtml+xml" => Some(MimeType::ApplicationXhtmlXml),
+      "image/svg+xml" => Some(MimeType::ImageSvgXml),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      MimeType::TextHtml => "text/html",
+      MimeType::TextXml => "text/xml",
+      MimeType::ApplicationXml => "application/xml",
+      MimeType::ApplicationXhtmlXml => "application/xhtml+xml",
+      MimeType::ImageSvgXml => "image/svg+xml",
+    }
+  }
+
+  /// Whether documents of this type are HTML documents, which are parsed with the
+  /// HTML parser and match HTML names case-insensitively.
+  pub fn is_html(&self) -> bool {
+    *self == MimeType::TextHtml
+  }
+}
+
+/// What the parser knew about a document that the tree itself does not hold.
+#[derive(Clone, Copy)]
+pub(crate) struct DocumentInfo {
+  pub(crate) encoding: &'static Encoding,
+  pub(crate) content_type: MimeType,
+  pub(crate) limits: ParseLimits,
+  pub(crate) quirks_mode: QuirksMode,
+}
+
+impl Default for DocumentInfo {
+  fn default() -> Self {
+    DocumentInfo {
+      encoding: UTF_8,
+      content_type: MimeType::TextHtml,
+      limits: ParseLimits::default(),
+      quirks_mode: QuirksMode::NoQuirks,
+    }
+  }
+}
+
+thread_local! {
+  static DOCUMENTS: RefCell<NodeTable<DocumentInfo>> = RefCell::new(NodeTable::default());
+}
+
+pub(crate) fn set_document_info(document: &Handle, info: DocumentInfo) {
+  DOCUMENTS.with(|table| table.borrow_mut().insert(document, info));
+}
+
+pub(super) fn has_document_info(document: &Handle) -> bool {
+  DOCUMENTS.with(|table| table.borrow_mut().contains(document))
+}
+
+pub(super) fn take_document_info(document: &Handle) -> Option<DocumentInfo> {
+  DOCUMENTS.with(|table| table.borrow_mut().remove(document))
+}
+
+impl DomNode {
+  /// The information recorded for the document this node belongs to, or the defaults.
+  pub(crate) fn document_info(&self) -> DocumentInfo {
+    let root = self.get_root_node();
+    DOCUMENTS.with(|table| table.borrow_mut().get(&root.0).copied().unwrap_or_default())
+  }
+
+  /// The name of the encoding the document this node belongs to was decoded from,
+  /// such as `"UTF-8"` or `"Shift_JIS"`. Documents parsed from a string are UTF-8.
+  pub fn character_set(&self) -> String {
+    self.document_info().encoding.name().to_string()
+  }
+
+  /// The MIME type of the document this node belongs to, such as `"text/html"` or
+  /// `"image/svg+xml"`.
+  pub fn content_type(&self) -> String {
+    self.document_info().content_type.as_str().to_string()
+  }
+
+  /// The quirks mode the parser put the document this node belongs to in, which its
+  /// doctype decides unless [`ParseOptions::quirks_mode`](crate::ParseOptions) forces
+  /// one. XML documents and nodes outside a parsed document are never in quirks mode.
+  pub fn quirks_mode(&self) -> QuirksMode {
+    self.document_info().quirks_mode
+  }
+
+  /// The rendering mode of the document this node belongs to, as `document.compatMode`
+  /// reports it: `"BackCompat"` in quirks mode and `"CSS1Compat"` otherwise, limited
+  /// quirks mode included.
+  pub fn compat_mode(&self) -> String {
+    match self.quirks_mode() {
+      QuirksMode::Quirks => "BackCompat",
+      QuirksMode::LimitedQuirks | QuirksMode::NoQuirks => "CSS1Compat",
+    }
+    .to_string()
+  }
+
+  /// Whether the document this node belongs to is an HTML document rather than an
+  /// XML one. Nodes outside a parsed document count as HTML.
+  pub(crate) fn is_html_document(&self) -> bool {
+    self.document_info().content_type.is_html()
+  }
+}
@@ -0,0 +1,92 @@
+use super::side_table::NodeTable;
+use super::DomNode;
+use markup5ever_rcdom::Handle;
+use std::cell::RefCell;
+
+/// A position in the parsed source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourcePosition {
+  /// Offset in bytes from the start of the input.
+  pub offset: usize,
+  /// Offset in UTF-16 code units, which is the index into a JavaScript string.
+  pub utf16_offset: usize,
+  /// 1-based line. A CR LF pair counts as a single line break.
+  pub line: u64,
+  /// 1-based column, in characters.
+  pub column: u64,
+}
+
+impl Default for SourcePosition {
+  fn default() -> Self {
+    SourcePosition {
+      offset: 0,
+      utf16_offset: 0,
+      line: 1,
+      column: 1,
+    }
+  }
+}
+
+impl SourcePosition {
+  /// The position after `c`, given the character following it.
+  pub(crate) fn advance(&self, c: char, following: Option<char>) -> SourcePosition {
+    let line_break = c == '\n' || c == '\r' && following != Some('\n');
+    SourcePosition {
+      offset: self.offset + c.len_utf8(),
+      utf16_offset: self.utf16_offset + c.len_utf16(),
+      line: if line_break { self.line + 1 } else { self.line },
+      column: if line_break { 1 } else { self.column + 1 },
+    }
+  }
+}
+
+/// The source between two positions, the end being exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceSpan {
+  pub start: SourcePosition,
+  pub end: SourcePosition,
+}
+
+/// Where a node came from in the parsed source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceRange {
+  pub start: SourcePosition,
+  pub end: SourcePosition,
+  /// The start tag of an element.
+  pub start_tag: Option<SourceSpan>,
+  /// The end tag of an element, if it was closed by one rather than implied.
+  pub end_tag: Option<SourceSpan>,
+}
+
+thread_local! {
+  static LOCATIONS: RefCell<NodeTable<SourceRange>> = RefCell::new(NodeTable::default());
+}
+
+/// Remembers where the given nodes were parsed from.
+pub(crate) fn record_locations(locations: Vec<(Handle, SourceRange)>) {
+  LOCATIONS.with(|table| {
+    let mut table = table.borrow_mut();
+    for (node, range) in locations {
+      table.insert(&node, range);
+    }
+  });
+}
+
+pub(super) fn has_location(node: &Handle) -> bool {
+  LOCATIONS.with(|table| table.borrow_mut().contains(node))
+}
+
+pub(super) fn take_location(node: &Handle) -> Option<SourceRange> {
+  LOCATIONS.with(|table| table.borrow_mut().remove(node))
+}
+
+impl DomNode {
+  /// Where this node was found in the source, for documents parsed with
+  /// [`ParseOptions::source_locations`](crate::ParseOptions::source_locations).
+  ///
+  /// Elements, text and comments created by the parser have a range. Nodes the
+  /// parser implied, such as a missing `<body>`, and nodes created afterwards do not.
+  pub fn source_range(&self) -> Option<SourceRange> {
+    LOCATIONS.with(|table| table.borrow_mut().get(&self.0).copied())
+  }
+}
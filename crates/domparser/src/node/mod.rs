@@ -2,9 +2,22 @@ use markup5ever_rcdom::{Handle, Node, NodeData};
 use std::cell::RefCell;
 use std::rc::Rc;
 
+mod document;
+mod location;
 mod modify;
 mod properties;
 mod query;
+mod send;
+mod side_table;
+mod traversal;
+
+pub use document::MimeType;
+pub(crate) use document::{set_document_info, DocumentInfo};
+pub(crate) use location::record_locations;
+pub use location::{SourcePosition, SourceRange, SourceSpan};
+pub(crate) use modify::new_document_fragment;
+pub use send::SendableDocument;
+pub(crate) use traversal::descendants;
 
 pub(crate) fn get_parent(node: &Handle) -> Option<Handle> {
   let parent_weak = node.parent.take();
@@ -74,10 +87,11 @@ fn clone_node_data(data: &NodeData) -> NodeData {
       mathml_annotation_xml_integration_point,
     } => {
       let new_attrs = attrs.borrow().clone();
+      // The contents are cloned along with the children, by `clone_tree`.
       let new_template_contents = template_contents
         .borrow()
         .as_ref()
-        .map(clone_handle_recursive);
+        .map(|contents| Node::new(clone_node_data(&contents.data)));
       NodeData::Element {
         name: name.clone(),
         attrs: RefCell::new(new_attrs),
@@ -93,11 +107,42 @@ fn clone_node_data(data: &NodeData) -> NodeData {
 }
 
 fn clone_handle_recursive(handle: &Handle) -> Handle {
-  let new_node = Node::new(clone_node_data(&handle.data));
-  for child in handle.children.borrow().iter() {
-    let new_child = clone_handle_recursive(child);
-    new_node.children.borrow_mut().push(new_child.clone());
-    new_child.parent.set(Some(Rc::downgrade(&new_node)));
+  clone_tree(handle, clone_node_data)
+}
+
+/// Clones a node and everything below it, including the contents of templates, with
+/// an explicit stack so that deep trees cannot overflow the call stack. `clone_data`
+/// copies the data of each node; for elements it leaves the template contents empty,
+/// to be filled in here.
+fn clone_tree(handle: &Handle, clone_data: fn(&NodeData) -> NodeData) -> Handle {
+  let root = Node::new(clone_data(&handle.data));
+  // Each source node is paired with the clone its children are cloned into.
+  let mut stack = vec![(handle.clone(), root.clone())];
+  while let Some((source, clone)) = stack.pop() {
+    if let (
+      NodeData::Element {
+        template_contents: source_contents,
+        ..
+      },
+      NodeData::Element {
+        template_contents: clone_contents,
+        ..
+      },
+    ) = (&source.data, &clone.data)
+    {
+      if let (Some(source_contents), Some(clone_contents)) = (
+        source_contents.borrow().as_ref(),
+        clone_contents.borrow().as_ref(),
+      ) {
+        stack.push((source_contents.clone(), clone_contents.clone()));
+      }
+    }
+    for child in source.children.borrow().iter() {
+      let new_child = Node::new(clone_data(&child.data));
+      clone.children.borrow_mut().push(new_child.clone());
+      new_child.parent.set(Some(Rc::downgrade(&clone)));
+      stack.push((child.clone(), new_child));
+    }
   }
-  new_node
+  root
 }
@@ -3,7 +3,19 @@ use markup5ever_rcdom::{Handle, Node, NodeData};
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use super::DomNode;
+use super::{DomNode, MimeType};
+
+/// Creates an empty document fragment, which is an element named `#document-fragment`
+/// in no namespace.
+pub(crate) fn new_document_fragment() -> Handle {
+  let qual_name = QualName::new(None, ns!(), LocalName::from("#document-fragment"));
+  Node::new(NodeData::Element {
+    name: qual_name,
+    attrs: RefCell::new(vec![]),
+    template_contents: RefCell::new(None),
+    mathml_annotation_xml_integration_point: false,
+  })
+}
 
 impl DomNode {
   fn detach_node(node: &Handle) {
@@ -209,7 +221,15 @@ impl DomNode {
   }
 
   pub fn create_element(&self, tag_name: String) -> DomNode {
-    let qual_name = QualName::new(None, ns!(html), LocalName::from(tag_name.to_lowercase()));
+    // XML documents other than XHTML create elements in no namespace, as written.
+    let content_type = self.document_info().content_type;
+    let qual_name = if content_type.is_html() {
+      QualName::new(None, ns!(html), LocalName::from(tag_name.to_lowercase()))
+    } else if content_type == MimeType::ApplicationXhtmlXml {
+      QualName::new(None, ns!(html), LocalName::from(tag_name))
+    } else {
+      QualName::new(None, ns!(), LocalName::from(tag_name))
+    };
     let node = Node::new(NodeData::Element {
       name: qual_name,
       attrs: RefCell::new(vec![]),
@@ -234,14 +254,7 @@ impl DomNode {
   }
 
   pub fn create_document_fragment(&self) -> DomNode {
-    let qual_name = QualName::new(None, ns!(), LocalName::from("#document-fragment"));
-    let node = Node::new(NodeData::Element {
-      name: qual_name,
-      attrs: RefCell::new(vec![]),
-      template_contents: RefCell::new(None),
-      mathml_annotation_xml_integration_point: false,
-    });
-    DomNode(node)
+    DomNode(new_document_fragment())
   }
 
   pub fn create_processing_instruction(&self, target: String, data: String) -> DomNode {
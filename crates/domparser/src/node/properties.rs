@@ -1,9 +1,9 @@
 use super::DomNode;
-use html5ever::parse_fragment;
-use html5ever::serialize::{serialize, SerializeOpts, TraversalScope};
-use html5ever::tendril::TendrilSink;
-use html5ever::{local_name, namespace_url, ns, QualName};
-use markup5ever_rcdom::{Handle, Node, NodeData, RcDom, SerializableHandle};
+use crate::parser::parse_fragment_at_depth;
+use crate::serializer::{serialize_text_only, SerializeOptions};
+use crate::{FragmentContext, LimitError, ParseOptions};
+use html5ever::QualName;
+use markup5ever_rcdom::{Handle, Node, NodeData};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -32,7 +32,7 @@ impl DomNode {
         if name.local.as_ref() == "#document-fragment" {
           "#document-fragment".to_string()
         } else {
-          name.local.to_string().to_uppercase()
+          self.element_tag_name(name)
         }
       }
       NodeData::Text { .. } => "#text".to_string(),
@@ -45,11 +45,23 @@ impl DomNode {
 
   pub fn tag_name(&self) -> Option<String> {
     match &self.0.data {
-      NodeData::Element { name, .. } => Some(name.local.to_string().to_uppercase()),
+      NodeData::Element { name, .. } => Some(self.element_tag_name(name)),
       _ => None,
     }
   }
 
+  /// Element names are uppercased in HTML documents. XML documents keep the qualified
+  /// name as written.
+  fn element_tag_name(&self, name: &QualName) -> String {
+    if self.is_html_document() {
+      return name.local.to_uppercase();
+    }
+    match &name.prefix {
+      Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, name.local),
+      _ => name.local.to_string(),
+    }
+  }
+
   pub fn namespace_uri(&self) -> Option<String> {
     match &self.0.data {
       NodeData::Element { name, .. } => Some(name.ns.to_string()),
@@ -280,16 +292,9 @@ impl DomNode {
   }
 
   pub fn text_content_getter(&self) -> String {
-    fn get_text(handle: &Handle) -> String {
-      match &handle.data {
-        NodeData::Text { contents } => contents.borrow().to_string(),
-        NodeData::Element { .. } | NodeData::Document => {
-          handle.children.borrow().iter().map(get_text).collect()
-        }
-        _ => "".to_string(),
-      }
-    }
-    get_text(&self.0)
+    let mut bytes = Vec::new();
+    serialize_text_only(&self.0, &mut bytes).unwrap();
+    String::from_utf8(bytes).unwrap()
   }
 
   pub fn set_text_content(&self, text: String) {
@@ -307,19 +312,7 @@ impl DomNode {
   }
 
   pub fn inner_html_getter(&self) -> String {
-    let mut bytes = Vec::new();
-    let serializable = SerializableHandle::from(self.0.clone());
-    serialize(
-      &mut bytes,
-      &serializable,
-      SerializeOpts {
-        scripting_enabled: false,
-        traversal_scope: TraversalScope::ChildrenOnly(None),
-        create_missing_parent: false,
-      },
-    )
-    .unwrap();
-    String::from_utf8(bytes).unwrap()
+    self.inner_html_with_options(&SerializeOptions::default())
   }
 
   pub fn length(&self) -> u32 {
@@ -330,136 +323,45 @@ impl DomNode {
     }
   }
 
-  pub fn set_inner_html(&self, html: String) {
-    self.0.children.borrow_mut().clear();
-
-    let context_name = match &self.0.data {
-      NodeData::Element { name, .. } => name.clone(),
-      _ => QualName::new(None, ns!(html), local_name!("body")),
-    };
-
-    let dom = parse_fragment(
-      RcDom::default(),
-      Default::default(),
-      context_name.clone(),
-      vec![],
-    )
-    .from_utf8()
-    .read_from(&mut html.as_bytes())
-    .unwrap();
-
-    let mut new_children = dom.document.children.borrow_mut();
-
-    // If the fragment parsed into a full html structure (html -> body -> ...), unwrap it.
-    // This happens if the context requires it or if html5ever forces it.
-    // We want the content that was inside the context.
-
-    // Heuristic: if result is <html><head></head><body>...</body></html>, we probably want the content of body (if context was body).
-    // But if context was div, we want content of div.
-
-    // Let's try to flatten: if child is html, take its children.
-    // If child is head or body, and context matches, take its children?
-
-    let mut nodes_to_append = Vec::new();
-
-    for child in new_children.drain(..) {
-      nodes_to_append.push(child);
-    }
-
-    // Unwrap html if it's the only child
-    if nodes_to_append.len() == 1 {
-      if let NodeData::Element { name, .. } = &nodes_to_append[0].data {
-        if name.local.as_ref() == "html" {
-          let html_node = nodes_to_append.pop().unwrap();
-          let mut html_children = html_node.children.borrow_mut();
-          for child in html_children.drain(..) {
-            nodes_to_append.push(child);
-          }
-        }
-      }
-    }
-
-    // Unwrap body if context is body and we have body
-    if context_name.local.as_ref() == "body" {
-      // If we have head and body, we probably want body's children?
-      // Or if we just have body.
-      // If we have head (empty) and body.
-
-      // Let's look for body element in nodes_to_append
-      let body_index = nodes_to_append.iter().position(|n| {
-        if let NodeData::Element { name, .. } = &n.data {
-          name.local.as_ref() == "body"
-        } else {
-          false
-        }
-      });
-
-      if let Some(idx) = body_index {
-        let body_node = nodes_to_append.remove(idx);
-        // We discard other nodes (like head) if we found body?
-        // Or we keep them?
-        // Usually innerHTML on body replaces body content.
-        // If I parse "<span>foo</span>", I get html->head, body->span.
-        // I want span.
-        // So I should take children of body.
-
-        let mut body_children = body_node.children.borrow_mut();
-        nodes_to_append.clear(); // Discard head etc.
-        for child in body_children.drain(..) {
-          nodes_to_append.push(child);
-        }
-      }
+  /// Parses markup for insertion below `parent`, within the limits of the document.
+  fn parse_markup<'a>(
+    &self,
+    html: String,
+    context: impl Into<FragmentContext<'a>>,
+    parent: &DomNode,
+  ) -> Result<DomNode, LimitError> {
+    let limits = self.document_info().limits;
+    let mut depth = 1;
+    let mut current = super::get_parent(&parent.0);
+    while let Some(ancestor) = current {
+      depth += 1;
+      current = super::get_parent(&ancestor);
     }
+    let options = ParseOptions::default();
+    parse_fragment_at_depth(html, context, &options, limits, depth)
+  }
 
-    for child in nodes_to_append {
-      self.0.children.borrow_mut().push(child.clone());
-      child.parent.set(Some(Rc::downgrade(&self.0)));
+  pub fn set_inner_html(&self, html: String) -> Result<(), LimitError> {
+    let fragment = self.parse_markup(html, self, self)?;
+    let old_children = std::mem::take(&mut *self.0.children.borrow_mut());
+    for child in old_children {
+      child.parent.set(None);
     }
+    self.insert_adjacent_nodes("beforeend", fragment.0.children.take());
+    Ok(())
   }
 
   pub fn outer_html_getter(&self) -> String {
-    let mut bytes = Vec::new();
-    let serializable = SerializableHandle::from(self.0.clone());
-    let traversal_scope = if let NodeData::Document = self.0.data {
-      TraversalScope::ChildrenOnly(None)
-    } else {
-      TraversalScope::IncludeNode
-    };
-    serialize(
-      &mut bytes,
-      &serializable,
-      SerializeOpts {
-        scripting_enabled: false,
-        traversal_scope,
-        create_missing_parent: false,
-      },
-    )
-    .unwrap();
-    String::from_utf8(bytes).unwrap()
+    self.outer_html_with_options(&SerializeOptions::default())
   }
 
-  pub fn set_outer_html(&self, html: String) {
-    if let Some(parent) = super::get_parent(&self.0) {
-      let context_name = match &parent.data {
-        NodeData::Element { name, .. } => name.clone(),
-        _ => QualName::new(None, ns!(html), local_name!("body")),
-      };
-
-      let dom = parse_fragment(RcDom::default(), Default::default(), context_name, vec![])
-        .from_utf8()
-        .read_from(&mut html.as_bytes())
-        .unwrap();
-
-      let mut parent_children = parent.children.borrow_mut();
-      if let Some(pos) = parent_children.iter().position(|x| Rc::ptr_eq(x, &self.0)) {
-        parent_children.remove(pos);
-        let mut new_children = dom.document.children.borrow_mut();
-        for (i, child) in new_children.drain(..).enumerate() {
-          parent_children.insert(pos + i, child.clone());
-          child.parent.set(Some(Rc::downgrade(&parent)));
-        }
-      }
+  pub fn set_outer_html(&self, html: String) -> Result<(), LimitError> {
+    if let Some(parent) = super::get_parent(&self.0).map(DomNode) {
+      let fragment = self.parse_markup(html, &parent, &parent)?;
+      self.insert_adjacent_nodes("beforebegin", fragment.0.children.take());
+      let _ = parent.remove_child(self);
     }
+    Ok(())
   }
 
   pub fn owner_document(&self) -> Option<DomNode> {
@@ -632,61 +534,23 @@ impl DomNode {
     }
   }
 
-  pub fn insert_adjacent_html(&self, position: String, html: String) {
-    let context_name = match &self.0.data {
-      NodeData::Element { name, .. } => name.clone(),
-      _ => QualName::new(None, ns!(html), local_name!("body")),
+  pub fn insert_adjacent_html(&self, position: String, html: String) -> Result<(), LimitError> {
+    // Markup placed next to the element is read as the content of its parent.
+    let parent = match position.to_lowercase().as_str() {
+      "beforebegin" | "afterend" => match super::get_parent(&self.0) {
+        Some(parent) => DomNode(parent),
+        None => return Ok(()),
+      },
+      _ => self.clone(),
     };
-
-    let dom = parse_fragment(
-      RcDom::default(),
-      Default::default(),
-      context_name.clone(),
-      vec![],
-    )
-    .from_utf8()
-    .read_from(&mut html.as_bytes())
-    .unwrap();
-
-    let mut new_children = dom.document.children.borrow_mut();
-    let mut nodes_to_append = Vec::new();
-
-    for child in new_children.drain(..) {
-      nodes_to_append.push(child);
-    }
-
-    if nodes_to_append.len() == 1 {
-      if let NodeData::Element { name, .. } = &nodes_to_append[0].data {
-        if name.local.as_ref() == "html" {
-          let html_node = nodes_to_append.pop().unwrap();
-          let mut html_children = html_node.children.borrow_mut();
-          for child in html_children.drain(..) {
-            nodes_to_append.push(child);
-          }
-        }
-      }
-    }
-
-    if context_name.local.as_ref() == "body" {
-      let body_index = nodes_to_append.iter().position(|n| {
-        if let NodeData::Element { name, .. } = &n.data {
-          name.local.as_ref() == "body"
-        } else {
-          false
-        }
-      });
-
-      if let Some(idx) = body_index {
-        let body_node = nodes_to_append.remove(idx);
-        let mut body_children = body_node.children.borrow_mut();
-        nodes_to_append.clear();
-        for child in body_children.drain(..) {
-          nodes_to_append.push(child);
-        }
-      }
-    }
-
-    self.insert_adjacent_nodes(&position, nodes_to_append);
+    let fragment =
+      if !parent.is_same_node(self) && matches!(parent.0.data, NodeData::Element { .. }) {
+        self.parse_markup(html, &parent, &parent)?
+      } else {
+        self.parse_markup(html, self, &parent)?
+      };
+    self.insert_adjacent_nodes(&position, fragment.0.children.take());
+    Ok(())
   }
 
   pub fn insert_adjacent_text(&self, position: String, text: String) {
@@ -743,7 +607,16 @@ impl DomNode {
   }
 
   pub fn normalize(&self) {
-    let mut children = self.0.children.borrow_mut();
+    let mut stack = vec![self.0.clone()];
+    while let Some(node) = stack.pop() {
+      Self::normalize_children(&node, &mut stack);
+    }
+  }
+
+  /// Merges and drops the text children of `node`, queueing its other children to be
+  /// normalized in turn.
+  fn normalize_children(node: &Handle, queue: &mut Vec<Handle>) {
+    let mut children = node.children.borrow_mut();
     let mut i = 0;
     while i < children.len() {
       let is_text = matches!(children[i].data, NodeData::Text { .. });
@@ -773,10 +646,7 @@ impl DomNode {
           continue;
         }
       } else {
-        let child_handle = children[i].clone();
-        drop(children);
-        DomNode(child_handle).normalize();
-        children = self.0.children.borrow_mut();
+        queue.push(children[i].clone());
       }
       i += 1;
     }
@@ -1,41 +1,38 @@
-use crate::serializer::serialize_text_only;
-use html5ever::serialize::{self, serialize, SerializeOpts};
+use crate::selector::{MatchingContext, Selector, SelectorError};
+use crate::serializer::{serialize_html, serialize_text_only, serialize_xml, SerializeOptions};
+use crate::xpath::{self, Expr, XPathError, XPathValue};
+use html5ever::tree_builder::QuirksMode;
 use html5ever::{namespace_url, ns, LocalName};
-use markup5ever_rcdom::{Handle, NodeData, SerializableHandle};
+use markup5ever_rcdom::NodeData;
+use std::io::{self, Write};
 use std::rc::Rc;
 
-use super::DomNode;
+use super::{descendants, DomNode};
 
 impl DomNode {
-  pub fn select(&self, selectors: String) -> Option<DomNode> {
-    fn find(node: &DomNode, selectors: &str) -> Option<DomNode> {
-      for child in node.0.children.borrow().iter() {
-        let child_dom = DomNode(child.clone());
-        if child_dom.matches(selectors.to_string()) {
-          return Some(child_dom);
-        }
-        if let Some(found) = find(&child_dom, selectors) {
-          return Some(found);
-        }
-      }
-      None
-    }
-    find(self, &selectors)
+  pub fn select(&self, selectors: String) -> Result<Option<DomNode>, SelectorError> {
+    let selector = Selector::parse(&selectors)?;
+    Ok(self.select_compiled(&selector))
   }
 
-  pub fn select_all(&self, selectors: String) -> Vec<DomNode> {
-    fn find_all(node: &DomNode, selectors: &str, results: &mut Vec<DomNode>) {
-      for child in node.0.children.borrow().iter() {
-        let child_dom = DomNode(child.clone());
-        if child_dom.matches(selectors.to_string()) {
-          results.push(child_dom.clone());
-        }
-        find_all(&child_dom, selectors, results);
-      }
-    }
-    let mut results = Vec::new();
-    find_all(self, &selectors, &mut results);
-    results
+  pub fn select_all(&self, selectors: String) -> Result<Vec<DomNode>, SelectorError> {
+    let selector = Selector::parse(&selectors)?;
+    Ok(self.select_all_compiled(&selector))
+  }
+
+  pub fn select_compiled(&self, selector: &Selector) -> Option<DomNode> {
+    let context = MatchingContext::new(self);
+    descendants(&self.0)
+      .map(DomNode)
+      .find(|node| selector.0.matches(node, &context))
+  }
+
+  pub fn select_all_compiled(&self, selector: &Selector) -> Vec<DomNode> {
+    let context = MatchingContext::new(self);
+    descendants(&self.0)
+      .map(DomNode)
+      .filter(|node| selector.0.matches(node, &context))
+      .collect()
   }
 
   pub fn get_attribute(&self, name: String) -> Option<String> {
@@ -94,35 +91,62 @@ impl DomNode {
   }
 
   pub fn outer_html(&self) -> String {
-    let mut u8_vec = Vec::new();
-    let serializable = SerializableHandle::from(self.0.clone());
-    serialize(
-      &mut u8_vec,
-      &serializable,
-      SerializeOpts {
-        traversal_scope: serialize::TraversalScope::IncludeNode,
-        create_missing_parent: false,
-        scripting_enabled: true,
-      },
-    )
-    .unwrap();
-    unsafe { String::from_utf8_unchecked(u8_vec) }
+    self.outer_html_with_options(&SerializeOptions {
+      scripting_enabled: true,
+      ..Default::default()
+    })
   }
 
   pub fn inner_html(&self) -> String {
-    let mut buf = Vec::<u8>::new();
-    let serializable = SerializableHandle::from(self.0.clone());
-    serialize(
-      &mut buf,
-      &serializable,
-      SerializeOpts {
-        traversal_scope: serialize::TraversalScope::ChildrenOnly(None),
-        create_missing_parent: false,
-        scripting_enabled: true,
-      },
-    )
-    .unwrap();
-    unsafe { String::from_utf8_unchecked(buf) }
+    self.inner_html_with_options(&SerializeOptions {
+      scripting_enabled: true,
+      ..Default::default()
+    })
+  }
+
+  /// Writes the node and its descendants as HTML, formatted or minified as `options`
+  /// ask. A document is written as its children.
+  pub fn outer_html_with_options(&self, options: &SerializeOptions) -> String {
+    let mut bytes = Vec::new();
+    self.serialize_to(&mut bytes, options).unwrap();
+    String::from_utf8(bytes).unwrap()
+  }
+
+  /// Writes the node and its descendants as HTML to `writer` as it goes, like
+  /// `outer_html_with_options`, so that a large document can be sent to a file or socket
+  /// without holding its serialization in memory. Wrap unbuffered writers in a
+  /// `BufWriter`, as the markup is written in many small pieces.
+  ///
+  /// ```
+  /// use domparser::{parse, SerializeOptions};
+  ///
+  /// let root = parse("<p>Hello</p>".to_string());
+  /// let mut out = Vec::new();
+  /// root.serialize_to(&mut out, &SerializeOptions::default()).unwrap();
+  /// assert_eq!(out, b"<html><head></head><body><p>Hello</p></body></html>");
+  /// ```
+  pub fn serialize_to<W: Write>(
+    &self,
+    mut writer: W,
+    options: &SerializeOptions,
+  ) -> io::Result<()> {
+    serialize_html(&mut writer, &self.0, true, options)
+  }
+
+  /// Writes the descendants of the node as HTML, formatted or minified as `options`
+  /// ask.
+  pub fn inner_html_with_options(&self, options: &SerializeOptions) -> String {
+    let mut bytes = Vec::new();
+    serialize_html(&mut bytes, &self.0, false, options).unwrap();
+    String::from_utf8(bytes).unwrap()
+  }
+
+  /// Writes the node and its descendants as well-formed XML, declaring the namespaces
+  /// they are in. A document is written as its children.
+  pub fn outer_xml(&self) -> String {
+    let mut bytes = Vec::new();
+    serialize_xml(&mut bytes, &self.0, true).unwrap();
+    String::from_utf8(bytes).unwrap()
   }
 
   pub fn text(&self) -> String {
@@ -131,46 +155,12 @@ impl DomNode {
     unsafe { String::from_utf8_unchecked(buf) }
   }
 
-  pub fn query_selector(&self, selectors: String) -> Option<DomNode> {
-    let selectors = selectors.trim();
-    if let Some(stripped) = selectors.strip_prefix('#') {
-      self.get_element_by_id(stripped.to_string())
-    } else if let Some(stripped) = selectors.strip_prefix('.') {
-      self
-        .get_elements_by_class_name(stripped.to_string())
-        .first()
-        .cloned()
-    } else {
-      self
-        .get_elements_by_tag_name(selectors.to_string())
-        .first()
-        .cloned()
-    }
+  pub fn query_selector(&self, selectors: String) -> Result<Option<DomNode>, SelectorError> {
+    self.select(selectors)
   }
 
-  pub fn query_selector_all(&self, selectors: String) -> Vec<DomNode> {
-    let selectors = selectors.trim();
-    if let Some(stripped) = selectors.strip_prefix('#') {
-      self
-        .get_element_by_id(stripped.to_string())
-        .map(|n| vec![n])
-        .unwrap_or_default()
-    } else if let Some(stripped) = selectors.strip_prefix('.') {
-      self.get_elements_by_class_name(stripped.to_string())
-    } else if selectors == "body>*" {
-      if let Some(body) = self.body() {
-        let mut results = Vec::new();
-        for child in body.0.children.borrow().iter() {
-          if let NodeData::Element { .. } = &child.data {
-            results.push(DomNode(child.clone()));
-          }
-        }
-        return results;
-      }
-      vec![]
-    } else {
-      self.get_elements_by_tag_name(selectors.to_string())
-    }
+  pub fn query_selector_all(&self, selectors: String) -> Result<Vec<DomNode>, SelectorError> {
+    self.select_all(selectors)
   }
 
   pub fn has_attribute(&self, name: String) -> bool {
@@ -216,26 +206,17 @@ impl DomNode {
   }
 
   pub fn get_element_by_id(&self, id: String) -> Option<DomNode> {
-    fn find_id(handle: &Handle, id: &str) -> Option<Handle> {
-      if let NodeData::Element { attrs, .. } = &handle.data {
-        if let Some(attr) = attrs
-          .borrow()
-          .iter()
-          .find(|a| a.name.local.as_ref() == "id")
-        {
-          if attr.value.as_ref() == id {
-            return Some(handle.clone());
-          }
-        }
-      }
-      for child in handle.children.borrow().iter() {
-        if let Some(found) = find_id(child, id) {
-          return Some(found);
-        }
-      }
-      None
-    }
-    find_id(&self.0, &id).map(DomNode)
+    std::iter::once(self.0.clone())
+      .chain(descendants(&self.0))
+      .find(|handle| {
+        let NodeData::Element { attrs, .. } = &handle.data else {
+          return false;
+        };
+        let attrs = attrs.borrow();
+        let attr = attrs.iter().find(|a| a.name.local.as_ref() == "id");
+        attr.is_some_and(|attr| attr.value.as_ref() == id)
+      })
+      .map(DomNode)
   }
 
   pub fn get_elements_by_class_name(&self, class_names: String) -> Vec<DomNode> {
@@ -243,9 +224,10 @@ impl DomNode {
     if classes.is_empty() {
       return vec![];
     }
+    // Quirks mode documents match classes case-insensitively.
+    let quirks = self.quirks_mode() == QuirksMode::Quirks;
     let mut results = Vec::new();
-
-    fn find_classes(handle: &Handle, classes: &[&str], results: &mut Vec<DomNode>) {
+    for handle in descendants(&self.0) {
       if let NodeData::Element { attrs, .. } = &handle.data {
         if let Some(attr) = attrs
           .borrow()
@@ -253,40 +235,43 @@ impl DomNode {
           .find(|a| a.name.local.as_ref() == "class")
         {
           let node_classes: Vec<&str> = attr.value.split_whitespace().collect();
-          if classes.iter().all(|c| node_classes.contains(c)) {
+          let has_class = |class: &&str| {
+            node_classes
+              .iter()
+              .any(|c| c == class || quirks && c.eq_ignore_ascii_case(class))
+          };
+          if classes.iter().all(has_class) {
             results.push(DomNode(handle.clone()));
           }
         }
       }
-      for child in handle.children.borrow().iter() {
-        find_classes(child, classes, results);
-      }
-    }
-
-    for child in self.0.children.borrow().iter() {
-      find_classes(child, &classes, &mut results);
     }
     results
   }
 
   pub fn get_elements_by_tag_name(&self, tag_name: String) -> Vec<DomNode> {
     let mut results = Vec::new();
-    let tag_upper = tag_name.to_uppercase();
+    // HTML elements match a lowercased name, but only in HTML documents.
+    let tag_lower = if self.is_html_document() {
+      tag_name.to_ascii_lowercase()
+    } else {
+      tag_name.clone()
+    };
     let is_wildcard = tag_name == "*";
 
-    fn find_tags(handle: &Handle, tag_upper: &str, is_wildcard: bool, results: &mut Vec<DomNode>) {
+    for handle in descendants(&self.0) {
       if let NodeData::Element { name, .. } = &handle.data {
-        if is_wildcard || name.local.to_string().to_uppercase() == tag_upper {
+        let matched = match &name.prefix {
+          _ if name.local.as_ref() == "#document-fragment" => false,
+          _ if is_wildcard => true,
+          Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, name.local) == tag_name,
+          _ if name.ns == ns!(html) => name.local.as_ref() == tag_lower,
+          _ => name.local.as_ref() == tag_name,
+        };
+        if matched {
           results.push(DomNode(handle.clone()));
         }
       }
-      for child in handle.children.borrow().iter() {
-        find_tags(child, tag_upper, is_wildcard, results);
-      }
-    }
-
-    for child in self.0.children.borrow().iter() {
-      find_tags(child, &tag_upper, is_wildcard, &mut results);
     }
     results
   }
@@ -376,63 +361,57 @@ impl DomNode {
     }
   }
 
-  fn matches_simple_selector(&self, selector: &str) -> bool {
-    if let Some(id) = selector.strip_prefix('#') {
-      return self.get_attribute("id".to_string()).as_deref() == Some(id);
-    }
-    if let Some(class) = selector.strip_prefix('.') {
-      if let Some(cls) = self.get_attribute("class".to_string()) {
-        return cls.split_whitespace().any(|c| c == class);
-      }
-      return false;
-    }
-    // Tag name
-    if let NodeData::Element { name, .. } = &self.0.data {
-      return name.local.to_string().eq_ignore_ascii_case(selector);
-    }
-    false
+  pub fn matches(&self, selectors: String) -> Result<bool, SelectorError> {
+    let selector = Selector::parse(&selectors)?;
+    Ok(self.matches_compiled(&selector))
   }
 
-  pub fn matches(&self, selectors: String) -> bool {
-    let selectors = selectors.trim();
-    let parts: Vec<&str> = selectors.split_whitespace().collect();
-
-    if parts.is_empty() {
-      return false;
-    }
-
-    if !self.matches_simple_selector(parts.last().unwrap()) {
-      return false;
-    }
-
-    if parts.len() == 1 {
-      return true;
-    }
-
-    let mut current_ancestor = super::get_parent(&self.0).map(DomNode);
-    let mut part_idx = parts.len() - 2;
-
-    while let Some(node) = current_ancestor {
-      if node.matches_simple_selector(parts[part_idx]) {
-        if part_idx == 0 {
-          return true;
-        }
-        part_idx -= 1;
-      }
-      current_ancestor = super::get_parent(&node.0).map(DomNode);
-    }
+  pub fn matches_compiled(&self, selector: &Selector) -> bool {
+    let context = MatchingContext::new(self);
+    selector.0.matches(self, &context)
+  }
 
-    false
+  pub fn closest(&self, selectors: String) -> Result<Option<DomNode>, SelectorError> {
+    let selector = Selector::parse(&selectors)?;
+    Ok(self.closest_compiled(&selector))
   }
 
-  pub fn closest(&self, selectors: String) -> Option<DomNode> {
+  pub fn closest_compiled(&self, selector: &Selector) -> Option<DomNode> {
+    let context = MatchingContext::new(self);
     let mut current = Some(DomNode(self.0.clone()));
     while let Some(node) = current {
-      if node.matches(selectors.clone()) {
+      if selector.0.matches(&node, &context) {
         return Some(node);
       }
       current = super::get_parent(&node.0).map(DomNode);
     }
     None
   }
+
+  /// Evaluates an XPath 1.0 expression with this node as the context node.
+  ///
+  /// Unprefixed name tests match elements in any namespace, case-insensitively for HTML
+  /// elements. The `html`, `svg`, `math` and `xlink` prefixes are predefined.
+  ///
+  /// ```
+  /// use domparser::parse;
+  ///
+  /// let root = parse(r#"<a href="/x">x</a><a href="/y">y</a>"#.to_string());
+  /// let href = root.evaluate_xpath("//a[2]/@href").unwrap();
+  /// assert_eq!(href.to_string_value(), "/y");
+  /// ```
+  pub fn evaluate_xpath(&self, expression: &str) -> Result<XPathValue, XPathError> {
+    self.evaluate_xpath_with_resolver(expression, |_| None)
+  }
+
+  /// Like [`DomNode::evaluate_xpath`], resolving namespace prefixes with `resolver`
+  /// before falling back to the predefined ones.
+  pub fn evaluate_xpath_with_resolver(
+    &self,
+    expression: &str,
+    resolver: impl Fn(&str) -> Option<String>,
+  ) -> Result<XPathValue, XPathError> {
+    let expr = Expr::parse(expression)?;
+    xpath::evaluate(&expr, self, &resolver)
+  }
 }
@@ -0,0 +1,283 @@
+use html5ever::tendril::StrTendril;
+use html5ever::Attribute;
+use markup5ever_rcdom::{Handle, Node, NodeData};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::document::{has_document_info, set_document_info, take_document_info, DocumentInfo};
+use super::location::{has_location, record_locations, take_location};
+use super::{clone_tree, get_parent, DomNode, SourceRange};
+
+/// A tree detached from the thread it was built on, so that it can be moved to another
+/// thread and turned back into a [`DomNode`] there.
+///
+/// Nodes count their references without atomics, and what the parser learned about a
+/// document is kept in tables local to the thread. A tree can therefore only change
+/// threads whole, with nothing outside it pointing in, which is what building one
+/// checks.
+///
+/// ```
+/// use domparser::parse;
+///
+/// let document = parse("<p>Hello</p>".to_string()).into_sendable().ok().unwrap();
+/// let text = std::thread::spawn(move || document.into_node().text_content_getter())
+///   .join()
+///   .unwrap();
+/// assert_eq!(text, "Hello");
+/// ```
+pub struct SendableDocument {
+  root: Handle,
+  info: Option<DocumentInfo>,
+  /// The source ranges of the nodes, by their index in the order `walk` visits them.
+  locations: Vec<(usize, SourceRange)>,
+}
+
+// SAFETY: A `SendableDocument` is only built from a tree that nothing outside it refers
+// to: every node is held by its parent alone, its only weak references are from its own
+// children, and the root has no parent. The entries of the thread's tables are removed
+// and text buffers shared with anything else are copied. Moving it therefore moves every
+// reference to its nodes and buffers, and none is left behind on the old thread.
+unsafe impl Send for SendableDocument {}
+
+impl DomNode {
+  /// Detaches this node and everything below it from the current thread, to be moved
+  /// to another.
+  ///
+  /// Fails, giving the node back, if it has a parent or anything outside the tree still
+  /// refers to one of its nodes, such as another `DomNode`.
+  pub fn into_sendable(self) -> Result<SendableDocument, DomNode> {
+    if !is_self_contained(&self.0) {
+      return Err(self);
+    }
+    let mut info = None;
+    let mut locations = Vec::new();
+    walk(&self.0, |index, node| {
+      let node_info = take_document_info(node);
+      if index == 0 {
+        info = node_info;
+      }
+      if let Some(range) = take_location(node) {
+        locations.push((index, range));
+      }
+      unshare_text(node);
+    });
+    // A leaf at the root has no parent to replace it in, so it is replaced here.
+    let root = match unshared_leaf_data(&self.0.data) {
+      Some(data) => Node::new(data),
+      None => self.0,
+    };
+    Ok(SendableDocument {
+      root,
+      info,
+      locations,
+    })
+  }
+
+  /// Copies this node and everything below it into a tree that can be moved to another
+  /// thread, leaving this one in place.
+  ///
+  /// The copy keeps the type and quirks mode of this node's document, so that selectors
+  /// match it as they match this tree. Source locations are not copied.
+  pub fn to_sendable(&self) -> SendableDocument {
+    let info = self.document_info();
+    // Every buffer is copied, as clones share theirs with the nodes they were cloned from.
+    match DomNode(clone_tree(&self.0, owned_node_data)).into_sendable() {
+      Ok(copy) => SendableDocument {
+        info: Some(info),
+        ..copy
+      },
+      Err(_) => unreachable!("nothing outside a new copy refers to it"),
+    }
+  }
+}
+
+impl SendableDocument {
+  /// Attaches the tree to the current thread.
+  pub fn into_node(self) -> DomNode {
+    if let Some(info) = self.info {
+      set_document_info(&self.root, info);
+    }
+    if !self.locations.is_empty() {
+      let mut ranges = self.locations.into_iter().peekable();
+      let mut located = Vec::new();
+      walk(&self.root, |index, node| {
+        if let Some((_, range)) = ranges.next_if(|(at, _)| *at == index) {
+          located.push((node.clone(), range));
+        }
+      });
+      record_locations(located);
+    }
+    DomNode(self.root)
+  }
+}
+
+/// Visits every node of the tree, the contents of templates included, numbering them
+/// in the order visited.
+fn walk(root: &Handle, mut visit: impl FnMut(usize, &Handle)) {
+  let mut stack = vec![root.clone()];
+  let mut index = 0;
+  while let Some(node) = stack.pop() {
+    visit(index, &node);
+    index += 1;
+    stack.extend(node.children.borrow().iter().rev().cloned());
+    if let NodeData::Element {
+      template_contents, ..
+    } = &node.data
+    {
+      stack.extend(template_contents.borrow().iter().cloned());
+    }
+  }
+}
+
+/// Whether the only references to the nodes of the tree are those of the tree itself,
+/// and of the thread's tables, which detaching removes.
+fn is_self_contained(root: &Handle) -> bool {
+  if has_parent_link(root) {
+    return false;
+  }
+  let mut contained = true;
+  walk(root, |_, node| {
+    // The node is held by its parent, or the caller for the root, and by `walk`.
+    let children = node.children.borrow();
+    let table_entries = has_document_info(node) as usize + has_location(node) as usize;
+    contained &= Rc::strong_count(node) == 2
+      && Rc::weak_count(node) == children.len() + table_entries
+      && children
+        .iter()
+        .all(|child| get_parent(child).is_some_and(|parent| Rc::ptr_eq(&parent, node)));
+    // The contents of a template have no parent link.
+    if let NodeData::Element {
+      template_contents, ..
+    } = &node.data
+    {
+      contained &= template_contents
+        .borrow()
+        .as_ref()
+        .is_none_or(|contents| !has_parent_link(contents));
+    }
+  });
+  contained
+}
+
+/// Whether the node points to a parent, even one that has been freed.
+fn has_parent_link(node: &Handle) -> bool {
+  let parent = node.parent.take();
+  let linked = parent.is_some();
+  node.parent.set(parent);
+  linked
+}
+
+/// Copies the text of `node` that shares a buffer with other text, as that of a clone
+/// does. Comments, doctypes and processing instructions hold their text immutably, so
+/// children of those kinds are replaced with copies instead.
+fn unshare_text(node: &Handle) {
+  match &node.data {
+    NodeData::Text { contents } => {
+      let mut contents = contents.borrow_mut();
+      if contents.is_shared() {
+        *contents = StrTendril::from_slice(&contents);
+      }
+    }
+    NodeData::Element { attrs, .. } => {
+      for attr in attrs.borrow_mut().iter_mut() {
+        if attr.value.is_shared() {
+          attr.value = StrTendril::from_slice(&attr.value);
+        }
+      }
+    }
+    _ => {}
+  }
+  for child in node.children.borrow_mut().iter_mut() {
+    if let Some(data) = unshared_leaf_data(&child.data) {
+      let copy = Node::new(data);
+      copy.parent.set(Some(Rc::downgrade(node)));
+      if let Some(range) = take_location(child) {
+        record_locations(vec![(copy.clone(), range)]);
+      }
+      *child = copy;
+    }
+  }
+}
+
+/// A copy of `data` with buffers of its own, and for elements no template contents.
+fn owned_node_data(data: &NodeData) -> NodeData {
+  let copy = |text: &StrTendril| StrTendril::from_slice(text);
+  match data {
+    NodeData::Document => NodeData::Document,
+    NodeData::Doctype {
+      name,
+      public_id,
+      system_id,
+    } => NodeData::Doctype {
+      name: copy(name),
+      public_id: copy(public_id),
+      system_id: copy(system_id),
+    },
+    NodeData::Text { contents } => NodeData::Text {
+      contents: RefCell::new(copy(&contents.borrow())),
+    },
+    NodeData::Comment { contents } => NodeData::Comment {
+      contents: copy(contents),
+    },
+    NodeData::Element {
+      name,
+      attrs,
+      template_contents,
+      mathml_annotation_xml_integration_point,
+    } => NodeData::Element {
+      name: name.clone(),
+      attrs: RefCell::new(
+        attrs
+          .borrow()
+          .iter()
+          .map(|attr| Attribute {
+            name: attr.name.clone(),
+            value: copy(&attr.value),
+          })
+          .collect(),
+      ),
+      template_contents: RefCell::new(
+        template_contents
+          .borrow()
+          .as_ref()
+          .map(|contents| Node::new(owned_node_data(&contents.data))),
+      ),
+      mathml_annotation_xml_integration_point: *mathml_annotation_xml_integration_point,
+    },
+    NodeData::ProcessingInstruction { target, contents } => NodeData::ProcessingInstruction {
+      target: copy(target),
+      contents: copy(contents),
+    },
+  }
+}
+
+/// A copy of the data of a comment, doctype or processing instruction whose text is
+/// shared, with buffers of its own.
+fn unshared_leaf_data(data: &NodeData) -> Option<NodeData> {
+  let copy = |text: &StrTendril| StrTendril::from_slice(text);
+  match data {
+    NodeData::Comment { contents } if contents.is_shared() => Some(NodeData::Comment {
+      contents: copy(contents),
+    }),
+    NodeData::Doctype {
+      name,
+      public_id,
+      system_id,
+    } if name.is_shared() || public_id.is_shared() || system_id.is_shared() => {
+      Some(NodeData::Doctype {
+        name: copy(name),
+        public_id: copy(public_id),
+        system_id: copy(system_id),
+      })
+    }
+    NodeData::ProcessingInstruction { target, contents }
+      if target.is_shared() || contents.is_shared() =>
+    {
+      Some(NodeData::ProcessingInstruction {
+        target: copy(target),
+        contents: copy(contents),
+      })
+    }
+    _ => None,
+  }
+}
@@ -0,0 +1,64 @@
+use markup5ever_rcdom::{Handle, Node};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// Values attached to nodes from outside the tree, keyed by node address.
+///
+/// The weak reference keeps the allocation, and so the address, from being reused
+/// while the entry exists.
+pub(crate) struct NodeTable<T> {
+  entries: HashMap<*const Node, (Weak<Node>, T)>,
+  /// Inserts and lookups since the entries of freed nodes were last dropped.
+  operations: usize,
+}
+
+impl<T> Default for NodeTable<T> {
+  fn default() -> Self {
+    NodeTable {
+      entries: HashMap::new(),
+      operations: 0,
+    }
+  }
+}
+
+impl<T> NodeTable<T> {
+  pub(crate) fn insert(&mut self, node: &Handle, value: T) {
+    self.sweep();
+    self
+      .entries
+      .insert(Rc::as_ptr(node), (Rc::downgrade(node), value));
+  }
+
+  /// Whether there is an entry for `node`, which holds a weak reference to it.
+  pub(crate) fn contains(&mut self, node: &Handle) -> bool {
+    self.sweep();
+    self.entries.contains_key(&Rc::as_ptr(node))
+  }
+
+  pub(crate) fn remove(&mut self, node: &Handle) -> Option<T> {
+    self
+      .entries
+      .remove(&Rc::as_ptr(node))
+      .map(|(_, value)| value)
+  }
+
+  pub(crate) fn get(&mut self, node: &Handle) -> Option<&T> {
+    self.sweep();
+    self
+      .entries
+      .get(&Rc::as_ptr(node))
+      .filter(|(node, _)| node.strong_count() > 0)
+      .map(|(_, value)| value)
+  }
+
+  /// Drops the entries of freed nodes once there have been as many operations as
+  /// entries, lookups included, so that they go even when nothing is inserted any
+  /// more, at a constant cost per operation.
+  fn sweep(&mut self) {
+    self.operations += 1;
+    if self.operations > self.entries.len().max(1024) {
+      self.entries.retain(|_, (node, _)| node.strong_count() > 0);
+      self.operations = 0;
+    }
+  }
+}
@@ -0,0 +1,39 @@
+use markup5ever_rcdom::Handle;
+
+/// The descendants of a node in tree order.
+///
+/// The walk keeps its own stack rather than recursing, so that trees nested far
+/// deeper than the call stack allows, which the parser readily builds from a run of
+/// unclosed `<div>`s, can still be walked.
+pub(crate) struct Descendants {
+  /// The nodes whose children are being walked, each with the index of the next one,
+  /// so that a walk stopped early does not copy the children it never reached.
+  stack: Vec<(Handle, usize)>,
+}
+
+impl Iterator for Descendants {
+  type Item = Handle;
+
+  fn next(&mut self) -> Option<Handle> {
+    loop {
+      let (parent, index) = self.stack.last_mut()?;
+      let child = parent.children.borrow().get(*index).cloned();
+      match child {
+        Some(child) => {
+          *index += 1;
+          self.stack.push((child.clone(), 0));
+          return Some(child);
+        }
+        None => {
+          self.stack.pop();
+        }
+      }
+    }
+  }
+}
+
+pub(crate) fn descendants(node: &Handle) -> Descendants {
+  Descendants {
+    stack: vec![(node.clone(), 0)],
+  }
+}
@@ -0,0 +1,224 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+/// How many bytes the `<meta>` prescan looks at.
+pub(super) const PRESCAN_LIMIT: usize = 1024;
+
+/// Picks the encoding of an HTML byte stream as the spec's encoding sniffing
+/// algorithm does: a byte order mark, then the transport layer charset, then a
+/// `<meta>` charset near the start, then a guess from the content.
+///
+/// Returns the encoding and the length of the byte order mark to skip.
+pub(super) fn sniff(bytes: &[u8], transport_charset: Option<&str>) -> (&'static Encoding, usize) {
+  if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+    return (encoding, bom_length);
+  }
+  if let Some(encoding) = transport_charset.and_then(|label| Encoding::for_label(label.as_bytes()))
+  {
+    return (encoding, 0);
+  }
+  if let Some(encoding) = prescan(&bytes[..bytes.len().min(PRESCAN_LIMIT)]) {
+    return (encoding, 0);
+  }
+  let mut detector = EncodingDetector::new();
+  detector.feed(bytes, true);
+  (detector.guess(None, true), 0)
+}
+
+fn is_space(byte: u8) -> bool {
+  matches!(byte, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+  bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// Finds `needle` in `bytes` at or after `from`, returning its position.
+fn find(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+  bytes
+    .get(from..)?
+    .windows(needle.len())
+    .position(|window| window == needle)
+    .map(|index| from + index)
+}
+
+/// Looks for a `<meta>` declaring the encoding, skipping comments and other tags.
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+  let mut position = 0;
+  while position < bytes.len() {
+    let rest = &bytes[position..];
+    if rest.starts_with(b"<!--") {
+      // `<!-->` closes the comment it opens.
+      position = find(bytes, position + 2, b"-->")? + 3;
+      continue;
+    }
+    if starts_with_ignore_case(rest, b"<meta")
+      && rest
+        .get(5)
+        .is_some_and(|&byte| is_space(byte) || byte == b'/')
+    {
+      position += 5;
+      if let Some(encoding) = prescan_meta(bytes, &mut position)? {
+        return Some(encoding);
+      }
+    } else if rest.len() >= 2
+      && rest[0] == b'<'
+      && (rest[1].is_ascii_alphabetic()
+        || rest[1] == b'/' && rest.get(2).is_some_and(u8::is_ascii_alphabetic))
+    {
+      position += rest[1..]
+        .iter()
+        .position(|&byte| is_space(byte) || byte == b'>')
+        .map_or(rest.len(), |index| index + 1);
+      while get_attribute(bytes, &mut position)?.is_some() {}
+    } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+      position = find(bytes, position + 1, b">")?;
+    }
+    position += 1;
+  }
+  None
+}
+
+/// Reads the attributes of a `<meta>` and returns the encoding it declares, if any.
+///
+/// The outer `None` means the input ran out, which ends the prescan.
+fn prescan_meta(bytes: &[u8], position: &mut usize) -> Option<Option<&'static Encoding>> {
+  let mut seen: Vec<Vec<u8>> = Vec::new();
+  let mut got_pragma = false;
+  let mut need_pragma = None;
+  let mut charset = None;
+
+  while let Some((name, value)) = get_attribute(bytes, position)? {
+    if seen.contains(&name) {
+      continue;
+    }
+    match name.as_slice() {
+      b"http-equiv" => got_pragma |= value == b"content-type",
+      b"content" if charset.is_none() => {
+        if let Some(label) = charset_from_content(&value) {
+          charset = Encoding::for_label(label);
+          need_pragma = Some(true);
+        }
+      }
+      b"charset" => {
+        charset = Encoding::for_label(&value);
+        need_pragma = Some(false);
+      }
+      _ => {}
+    }
+    seen.push(name);
+  }
+
+  let declared = match need_pragma {
+    Some(false) => charset,
+    Some(true) if got_pragma => charset,
+    _ => None,
+  };
+  Some(declared.map(|encoding| {
+    if encoding == UTF_16BE || encoding == UTF_16LE {
+      UTF_8
+    } else if encoding == X_USER_DEFINED {
+      WINDOWS_1252
+    } else {
+      encoding
+    }
+  }))
+}
+
+/// Reads an attribute of a tag as the prescan does, lowercasing its name and value.
+///
+/// Returns `Some(None)` at the end of the tag and `None` when the input runs out.
+fn get_attribute(bytes: &[u8], position: &mut usize) -> Option<Option<(Vec<u8>, Vec<u8>)>> {
+  let byte_at = |position: usize| bytes.get(position).copied();
+
+  while is_space(byte_at(*position)?) || byte_at(*position)? == b'/' {
+    *position += 1;
+  }
+  if byte_at(*position)? == b'>' {
+    return Some(None);
+  }
+
+  let mut name = Vec::new();
+  let mut value = Vec::new();
+  loop {
+    match byte_at(*position)? {
+      b'=' if !name.is_empty() => {
+        *position += 1;
+        break;
+      }
+      byte if is_space(byte) => {
+        while is_space(byte_at(*position)?) {
+          *position += 1;
+        }
+        if byte_at(*position)? != b'=' {
+          return Some(Some((name, value)));
+        }
+        *position += 1;
+        break;
+      }
+      b'/' | b'>' => return Some(Some((name, value))),
+      byte => name.push(byte.to_ascii_lowercase()),
+    }
+    *position += 1;
+  }
+
+  while is_space(byte_at(*position)?) {
+    *position += 1;
+  }
+  match byte_at(*position)? {
+    quote @ (b'"' | b'\'') => loop {
+      *position += 1;
+      match byte_at(*position)? {
+        byte if byte == quote => {
+          *position += 1;
+          return Some(Some((name, value)));
+        }
+        byte => value.push(byte.to_ascii_lowercase()),
+      }
+    },
+    b'>' => return Some(Some((name, value))),
+    byte => {
+      value.push(byte.to_ascii_lowercase());
+      *position += 1;
+    }
+  }
+  loop {
+    match byte_at(*position)? {
+      byte if is_space(byte) || byte == b'>' => return Some(Some((name, value))),
+      byte => value.push(byte.to_ascii_lowercase()),
+    }
+    *position += 1;
+  }
+}
+
+/// Extracts the charset label from a `<meta http-equiv=content-type>` content value.
+fn charset_from_content(content: &[u8]) -> Option<&[u8]> {
+  let mut position = 0;
+  loop {
+    position = find(content, position, b"charset")? + b"charset".len();
+    while content.get(position).copied().is_some_and(is_space) {
+      position += 1;
+    }
+    if content.get(position) != Some(&b'=') {
+      continue;
+    }
+    position += 1;
+    while content.get(position).copied().is_some_and(is_space) {
+      position += 1;
+    }
+    return match content.get(position)? {
+      &quote @ (b'"' | b'\'') => {
+        let end = find(content, position + 1, &[quote])?;
+        Some(&content[position + 1..end])
+      }
+      _ => {
+        let rest = &content[position..];
+        let end = rest
+          .iter()
+          .position(|&byte| is_space(byte) || byte == b';')
+          .unwrap_or(rest.len());
+        Some(&rest[..end]).filter(|label| !label.is_empty())
+      }
+    };
+  }
+}
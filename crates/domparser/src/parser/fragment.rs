@@ -0,0 +1,207 @@
+use html5ever::driver::parse_fragment_for_element;
+use html5ever::tendril::TendrilSink;
+use html5ever::tree_builder::QuirksMode;
+use html5ever::{local_name, namespace_url, ns, LocalName, QualName};
+use markup5ever_rcdom::{Handle, Node, NodeData};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::limits::{chunks, LimitedSink};
+use super::tracking::TrackedParser;
+use super::{LimitError, ParseLimits, ParseOptions};
+use crate::node::{get_parent, new_document_fragment};
+use crate::DomNode;
+
+/// The element a fragment is parsed in, which decides how its markup is read: a
+/// `<tr>` in a `tbody` context, `<option>`s in a `select` or raw text in a `title`.
+pub enum FragmentContext<'a> {
+  /// An element of a document. Its form ancestor also becomes the form of any
+  /// controls in the fragment.
+  Element(&'a DomNode),
+  /// An element with the given name, detached from any document.
+  Name(QualName),
+}
+
+impl<'a> From<&'a DomNode> for FragmentContext<'a> {
+  fn from(node: &'a DomNode) -> Self {
+    FragmentContext::Element(node)
+  }
+}
+
+impl From<QualName> for FragmentContext<'_> {
+  fn from(name: QualName) -> Self {
+    FragmentContext::Name(name)
+  }
+}
+
+/// A tag name, which is an HTML element unless it is `svg` or `math`.
+impl From<&str> for FragmentContext<'_> {
+  fn from(tag_name: &str) -> Self {
+    let local = LocalName::from(tag_name.to_ascii_lowercase());
+    let ns = match local {
+      local_name!("svg") => ns!(svg),
+      local_name!("math") => ns!(mathml),
+      _ => ns!(html),
+    };
+    FragmentContext::Name(QualName::new(None, ns, local))
+  }
+}
+
+impl FragmentContext<'_> {
+  /// The context element to give the tree builder and its form element pointer.
+  ///
+  /// Contexts that are not elements, such as documents and document fragments,
+  /// parse as a `<body>`.
+  fn resolve(&self) -> (Handle, Option<Handle>) {
+    let node = match self {
+      FragmentContext::Element(node) => &node.0,
+      FragmentContext::Name(name) => return (new_element(name.clone()), None),
+    };
+    let is_element = match &node.data {
+      NodeData::Element { name, .. } => name.ns != ns!() || &*name.local != "#document-fragment",
+      _ => false,
+    };
+    if !is_element {
+      return (
+        new_element(QualName::new(None, ns!(html), local_name!("body"))),
+        None,
+      );
+    }
+    let mut form = Some(node.clone());
+    while let Some(ancestor) = form {
+      if matches!(&ancestor.data, NodeData::Element { name, .. } if name.ns == ns!(html) && name.local == local_name!("form"))
+      {
+        return (node.clone(), Some(ancestor));
+      }
+      form = get_parent(&ancestor);
+    }
+    (node.clone(), None)
+  }
+
+  /// The quirks mode of the document the context element belongs to, which the
+  /// fragment is parsed in.
+  fn quirks_mode(&self) -> Option<QuirksMode> {
+    match self {
+      FragmentContext::Element(node) => Some(node.quirks_mode()),
+      FragmentContext::Name(_) => None,
+    }
+  }
+}
+
+fn new_element(name: QualName) -> Handle {
+  Node::new(NodeData::Element {
+    name,
+    attrs: RefCell::new(vec![]),
+    template_contents: RefCell::new(None),
+    mathml_annotation_xml_integration_point: false,
+  })
+}
+
+/// Parses `html` as the content of the given context element, returning a document
+/// fragment holding the nodes.
+///
+/// The context is an element or a tag name, and the markup is read the way
+/// `innerHTML` reads it for that element.
+///
+/// ```
+/// use domparser::parse_fragment;
+///
+/// let fragment = parse_fragment("<td>a<td>b".to_string(), "tr");
+/// assert_eq!(fragment.node_type(), 11);
+/// assert_eq!(fragment.select_all("td".to_string()).unwrap().len(), 2);
+/// ```
+pub fn parse_fragment<'a>(html: String, context: impl Into<FragmentContext<'a>>) -> DomNode {
+  parse_fragment_with_options(html, context, &ParseOptions::default())
+}
+
+/// Parses a fragment like [`parse_fragment`], with the given options.
+pub fn parse_fragment_with_options<'a>(
+  html: String,
+  context: impl Into<FragmentContext<'a>>,
+  options: &ParseOptions,
+) -> DomNode {
+  let Ok(fragment) = parse_fragment_at_depth(html, context, options, ParseLimits::default(), 1)
+  else {
+    unreachable!("a parse without limits cannot exceed them");
+  };
+  fragment
+}
+
+/// Parses a fragment like [`parse_fragment_with_options`] unless it exceeds `limits`.
+/// The top-level nodes of the fragment count as having one ancestor.
+///
+/// ```
+/// use domparser::{parse_fragment_with_limits, LimitError, ParseLimits, ParseOptions};
+///
+/// let limits = ParseLimits {
+///   max_attributes: Some(2),
+///   ..Default::default()
+/// };
+/// let html = r#"<p a b c>"#.to_string();
+/// let result = parse_fragment_with_limits(html, "div", &ParseOptions::default(), limits);
+/// assert_eq!(result.err(), Some(LimitError::TooManyAttributes { limit: 2 }));
+/// ```
+pub fn parse_fragment_with_limits<'a>(
+  html: String,
+  context: impl Into<FragmentContext<'a>>,
+  options: &ParseOptions,
+  limits: ParseLimits,
+) -> Result<DomNode, LimitError> {
+  parse_fragment_at_depth(html, context, options, limits, 1)
+}
+
+/// Parses a fragment within `limits`, its top-level nodes having `depth` ancestors
+/// where they end up.
+pub(crate) fn parse_fragment_at_depth<'a>(
+  html: String,
+  context: impl Into<FragmentContext<'a>>,
+  options: &ParseOptions,
+  limits: ParseLimits,
+  depth: usize,
+) -> Result<DomNode, LimitError> {
+  limits.check_input_size(html.len())?;
+  let context = context.into();
+  let options = ParseOptions {
+    quirks_mode: options.quirks_mode.or(context.quirks_mode()),
+    ..options.clone()
+  };
+  let (context, form) = context.resolve();
+  let document = if options.source_locations {
+    let mut parser = TrackedParser::new_for_fragment(&options, context, form, limits, depth);
+    for chunk in chunks(html.into()) {
+      if parser.limit_error().is_some() {
+        break;
+      }
+      parser.feed(chunk);
+    }
+    parser.finish()?.0 .0
+  } else {
+    let sink = LimitedSink::for_fragment(options.to_dom(), limits, depth);
+    let mut parser = parse_fragment_for_element(sink, options.to_html5ever(), context, form);
+    for chunk in chunks(html.into()) {
+      if parser.tokenizer.sink.sink.error().is_some() {
+        break;
+      }
+      parser.process(chunk);
+    }
+    match parser.finish() {
+      (_, Some(error)) => return Err(error),
+      (dom, None) => dom.document,
+    }
+  };
+
+  // The tree builder puts the fragment in an `<html>` root standing in for the context.
+  let fragment = new_document_fragment();
+  let root = document.children.borrow_mut().pop();
+  if let Some(root) = root {
+    let children = std::mem::take(&mut *root.children.borrow_mut());
+    for child in &children {
+      child.parent.set(Some(Rc::downgrade(&fragment)));
+    }
+    *fragment.children.borrow_mut() = children;
+  }
+  let fragment = DomNode(fragment);
+  // Nodes moved deeper once inserted are only found in the finished tree.
+  limits.check_tree(&fragment, depth)?;
+  Ok(fragment)
+}
@@ -0,0 +1,426 @@
+use html5ever::tendril::StrTendril;
+use html5ever::tree_builder::{ElementFlags, NextParserState, NodeOrText, QuirksMode, TreeSink};
+use html5ever::{Attribute, ExpandedName, QualName};
+use markup5ever_rcdom::{Handle, Node, NodeData};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use crate::node::get_parent;
+use crate::DomNode;
+
+/// How much input is given to the tokenizer at a time, so that a parse stops soon after
+/// it exceeds a limit.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Limits on what a parse may produce, for documents from untrusted sources.
+///
+/// A document parsed with [`parse_with_limits`](crate::parse_with_limits) keeps its
+/// limits, and `set_inner_html`, `set_outer_html` and `insert_adjacent_html` on its
+/// nodes apply them to the markup they parse. Every limit is off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ParseLimits {
+  /// The most ancestors a node may have, counting the document.
+  pub max_depth: Option<usize>,
+  /// The most nodes a single parse may create.
+  pub max_nodes: Option<usize>,
+  /// The most attributes an element may have.
+  pub max_attributes: Option<usize>,
+  /// The longest input, in bytes, that is parsed at all.
+  pub max_input_size: Option<usize>,
+}
+
+/// The limit a parse exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+  InputTooLarge { limit: usize },
+  TooDeep { limit: usize },
+  TooManyNodes { limit: usize },
+  TooManyAttributes { limit: usize },
+}
+
+impl fmt::Display for LimitError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LimitError::InputTooLarge { limit } => write!(f, "Input is larger than {} bytes", limit),
+      LimitError::TooDeep { limit } => write!(f, "Nodes are nested more than {} deep", limit),
+      LimitError::TooManyNodes { limit } => write!(f, "Input has more than {} nodes", limit),
+      LimitError::TooManyAttributes { limit } => {
+        write!(f, "An element has more than {} attributes", limit)
+      }
+    }
+  }
+}
+
+impl std::error::Error for LimitError {}
+
+impl ParseLimits {
+  /// Rejects input of `size` bytes if it is too large to parse.
+  pub(crate) fn check_input_size(&self, size: usize) -> Result<(), LimitError> {
+    match self.max_input_size {
+      Some(limit) if size > limit => Err(LimitError::InputTooLarge { limit }),
+      _ => Ok(()),
+    }
+  }
+
+  /// Checks the nodes below `root` once parsed, `depth` being the number of ancestors
+  /// the children of `root` have where they end up.
+  pub(crate) fn check_tree(&self, root: &DomNode, depth: usize) -> Result<(), LimitError> {
+    if self.max_depth.is_none() && self.max_nodes.is_none() && self.max_attributes.is_none() {
+      return Ok(());
+    }
+    let mut nodes = 0;
+    let mut stack: Vec<(Handle, usize)> = root
+      .0
+      .children
+      .borrow()
+      .iter()
+      .map(|child| (child.clone(), depth))
+      .collect();
+    while let Some((node, depth)) = stack.pop() {
+      nodes += 1;
+      if let Some(limit) = self.max_nodes.filter(|&limit| nodes > limit) {
+        return Err(LimitError::TooManyNodes { limit });
+      }
+      if let Some(limit) = self.max_depth.filter(|&limit| depth > limit) {
+        return Err(LimitError::TooDeep { limit });
+      }
+      if let NodeData::Element {
+        attrs,
+        template_contents,
+        ..
+      } = &node.data
+      {
+        if let Some(limit) = self
+          .max_attributes
+          .filter(|&limit| attrs.borrow().len() > limit)
+        {
+          return Err(LimitError::TooManyAttributes { limit });
+        }
+        // The parser puts the content of a `<template>` in a separate document.
+        if let Some(contents) = template_contents.borrow().as_ref() {
+          let children = contents.children.borrow();
+          stack.extend(children.iter().map(|child| (child.clone(), depth + 1)));
+        }
+      }
+      let children = node.children.borrow();
+      stack.extend(children.iter().map(|child| (child.clone(), depth + 1)));
+    }
+    Ok(())
+  }
+}
+
+/// A tree sink that counts what the parser creates as it goes, so that a parse can
+/// stop as soon as it exceeds a limit rather than once the whole tree is built.
+///
+/// Nodes moved deeper after they were inserted, as misnested formatting elements are,
+/// are not counted again; `ParseLimits::check_tree` checks the finished tree for those.
+pub(super) struct LimitedSink<S> {
+  sink: S,
+  limits: ParseLimits,
+  /// The number of ancestors of the tree's document node where the tree ends up. It is
+  /// below zero for a fragment, whose nodes the tree builder puts below an `<html>`
+  /// root of a document of its own.
+  document_depth: isize,
+  nodes: usize,
+  /// The depth of each node inserted so far, while `max_depth` is set.
+  depths: HashMap<*const Node, isize>,
+  /// The template each template contents belongs to.
+  templates: HashMap<*const Node, Handle>,
+  error: Option<LimitError>,
+}
+
+impl<S> LimitedSink<S> {
+  /// Wraps `sink` for a document, whose children have one ancestor.
+  pub(super) fn new(sink: S, limits: ParseLimits) -> Self {
+    Self::at_depth(sink, limits, 0)
+  }
+
+  /// Wraps `sink` for a fragment whose top-level nodes end up with `depth` ancestors.
+  /// The tree builder puts them below an `<html>` root.
+  pub(super) fn for_fragment(sink: S, limits: ParseLimits, depth: usize) -> Self {
+    Self::at_depth(sink, limits, depth as isize - 2)
+  }
+
+  fn at_depth(sink: S, limits: ParseLimits, document_depth: isize) -> Self {
+    LimitedSink {
+      sink,
+      limits,
+      document_depth,
+      nodes: 0,
+      depths: HashMap::new(),
+      templates: HashMap::new(),
+      error: None,
+    }
+  }
+
+  /// The first limit the parse exceeded, after which the rest of the input is not
+  /// worth parsing.
+  pub(super) fn error(&self) -> Option<LimitError> {
+    self.error
+  }
+
+  fn fail(&mut self, error: LimitError) {
+    self.error.get_or_insert(error);
+  }
+
+  fn count_node(&mut self) {
+    self.nodes += 1;
+    if let Some(limit) = self.limits.max_nodes.filter(|&limit| self.nodes > limit) {
+      self.fail(LimitError::TooManyNodes { limit });
+    }
+  }
+
+  fn check_attributes(&mut self, count: usize) {
+    if let Some(limit) = self.limits.max_attributes.filter(|&limit| count > limit) {
+      self.fail(LimitError::TooManyAttributes { limit });
+    }
+  }
+
+  /// Records the depth of `child`, just inserted below `parent`.
+  fn inserted(&mut self, parent: &Handle, child: &Handle) {
+    let Some(limit) = self.limits.max_depth else {
+      return;
+    };
+    let depth = self.depth_of(parent) + 1;
+    self.depths.insert(Rc::as_ptr(child), depth);
+    if depth > limit as isize {
+      self.fail(LimitError::TooDeep { limit });
+    }
+  }
+
+  /// The depth of `node`: that of its parent plus one, with the contents of a template
+  /// at the depth of the template.
+  fn depth_of(&self, node: &Handle) -> isize {
+    let mut above = 0;
+    let mut current = node.clone();
+    loop {
+      if let Some(&depth) = self.depths.get(&Rc::as_ptr(&current)) {
+        return depth + above;
+      }
+      current = match &current.data {
+        NodeData::Document => match self.templates.get(&Rc::as_ptr(&current)) {
+          Some(template) => template.clone(),
+          None => return self.document_depth + above,
+        },
+        _ => match get_parent(&current) {
+          Some(parent) => {
+            above += 1;
+            parent
+          }
+          None => return self.document_depth + 1 + above,
+        },
+      };
+    }
+  }
+}
+
+impl<S> Deref for LimitedSink<S> {
+  type Target = S;
+
+  fn deref(&self) -> &S {
+    &self.sink
+  }
+}
+
+impl<S> DerefMut for LimitedSink<S> {
+  fn deref_mut(&mut self) -> &mut S {
+    &mut self.sink
+  }
+}
+
+impl<S: TreeSink<Handle = Handle>> TreeSink for LimitedSink<S> {
+  type Handle = Handle;
+  type Output = (S::Output, Option<LimitError>);
+
+  fn finish(self) -> Self::Output {
+    (self.sink.finish(), self.error)
+  }
+
+  fn parse_error(&mut self, msg: Cow<'static, str>) {
+    self.sink.parse_error(msg);
+  }
+
+  fn get_document(&mut self) -> Handle {
+    self.sink.get_document()
+  }
+
+  fn elem_name<'a>(&'a self, target: &'a Handle) -> ExpandedName<'a> {
+    self.sink.elem_name(target)
+  }
+
+  fn create_element(
+    &mut self,
+    name: QualName,
+    attrs: Vec<Attribute>,
+    flags: ElementFlags,
+  ) -> Handle {
+    self.count_node();
+    self.check_attributes(attrs.len());
+    self.sink.create_element(name, attrs, flags)
+  }
+
+  fn create_comment(&mut self, text: StrTendril) -> Handle {
+    self.count_node();
+    self.sink.create_comment(text)
+  }
+
+  fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle {
+    self.count_node();
+    self.sink.create_pi(target, data)
+  }
+
+  fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+    let before = parent.children.borrow().len();
+    let node = match &child {
+      NodeOrText::AppendNode(node) => Some(node.clone()),
+      NodeOrText::AppendText(_) => None,
+    };
+    self.sink.append(parent, child);
+    let last = parent.children.borrow().last().cloned();
+    match (node, last) {
+      (Some(node), _) => self.inserted(parent, &node),
+      // Text joins text before it, and only otherwise makes a node.
+      (None, Some(text)) if parent.children.borrow().len() > before => {
+        self.count_node();
+        self.inserted(parent, &text);
+      }
+      _ => {}
+    }
+  }
+
+  fn append_based_on_parent_node(
+    &mut self,
+    element: &Handle,
+    prev_element: &Handle,
+    child: NodeOrText<Handle>,
+  ) {
+    if get_parent(element).is_some() {
+      self.append_before_sibling(element, child);
+    } else {
+      self.append(prev_element, child);
+    }
+  }
+
+  fn append_doctype_to_document(
+    &mut self,
+    name: StrTendril,
+    public_id: StrTendril,
+    system_id: StrTendril,
+  ) {
+    self.count_node();
+    self
+      .sink
+      .append_doctype_to_document(name, public_id, system_id);
+  }
+
+  fn mark_script_already_started(&mut self, node: &Handle) {
+    self.sink.mark_script_already_started(node);
+  }
+
+  fn pop(&mut self, node: &Handle) {
+    self.sink.pop(node);
+  }
+
+  fn get_template_contents(&mut self, target: &Handle) -> Handle {
+    let contents = self.sink.get_template_contents(target);
+    if self.limits.max_depth.is_some() {
+      self.templates.insert(Rc::as_ptr(&contents), target.clone());
+    }
+    contents
+  }
+
+  fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+    self.sink.same_node(x, y)
+  }
+
+  fn set_quirks_mode(&mut self, mode: QuirksMode) {
+    self.sink.set_quirks_mode(mode);
+  }
+
+  fn append_before_sibling(&mut self, sibling: &Handle, new_node: NodeOrText<Handle>) {
+    let Some(parent) = get_parent(sibling) else {
+      return self.sink.append_before_sibling(sibling, new_node);
+    };
+    let before = parent.children.borrow().len();
+    let node = match &new_node {
+      NodeOrText::AppendNode(node) => Some(node.clone()),
+      NodeOrText::AppendText(_) => None,
+    };
+    self.sink.append_before_sibling(sibling, new_node);
+    match node {
+      Some(node) => self.inserted(&parent, &node),
+      None if parent.children.borrow().len() > before => {
+        self.count_node();
+        let text = {
+          let children = parent.children.borrow();
+          children
+            .iter()
+            .position(|child| Rc::ptr_eq(child, sibling))
+            .and_then(|index| index.checked_sub(1))
+            .map(|index| children[index].clone())
+        };
+        if let Some(text) = text {
+          self.inserted(&parent, &text);
+        }
+      }
+      None => {}
+    }
+  }
+
+  fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+    self.sink.add_attrs_if_missing(target, attrs);
+    if let NodeData::Element { attrs, .. } = &target.data {
+      let count = attrs.borrow().len();
+      self.check_attributes(count);
+    }
+  }
+
+  fn associate_with_form(
+    &mut self,
+    target: &Handle,
+    form: &Handle,
+    nodes: (&Handle, Option<&Handle>),
+  ) {
+    self.sink.associate_with_form(target, form, nodes);
+  }
+
+  fn remove_from_parent(&mut self, target: &Handle) {
+    self.sink.remove_from_parent(target);
+  }
+
+  fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+    self.sink.reparent_children(node, new_parent);
+  }
+
+  fn is_mathml_annotation_xml_integration_point(&self, handle: &Handle) -> bool {
+    self.sink.is_mathml_annotation_xml_integration_point(handle)
+  }
+
+  fn set_current_line(&mut self, line_number: u64) {
+    self.sink.set_current_line(line_number);
+  }
+
+  fn complete_script(&mut self, node: &Handle) -> NextParserState {
+    self.sink.complete_script(node)
+  }
+}
+
+/// Splits `input` into chunks of about `CHUNK_SIZE` bytes, on character boundaries.
+pub(super) fn chunks(input: StrTendril) -> impl Iterator<Item = StrTendril> {
+  let mut offset = 0;
+  std::iter::from_fn(move || {
+    if offset >= input.len() {
+      return None;
+    }
+    let mut end = (offset + CHUNK_SIZE).min(input.len());
+    while !input.is_char_boundary(end) {
+      end += 1;
+    }
+    let chunk = input.subtendril(offset as u32, (end - offset) as u32);
+    offset = end;
+    Some(chunk)
+  })
+}
@@ -0,0 +1,320 @@
+use encoding_rs::Encoding;
+use html5ever::tree_builder::{QuirksMode, TreeBuilderOpts};
+use html5ever::ParseOpts;
+use markup5ever_rcdom::RcDom;
+use std::fmt;
+
+use crate::node::{set_document_info, MimeType};
+use crate::DomNode;
+
+mod encoding;
+mod fragment;
+mod limits;
+mod streaming;
+mod tracking;
+mod xml;
+
+pub(crate) use fragment::parse_fragment_at_depth;
+pub use fragment::{
+  parse_fragment, parse_fragment_with_limits, parse_fragment_with_options, FragmentContext,
+};
+pub use limits::{LimitError, ParseLimits};
+pub use streaming::DocumentParser;
+
+/// Options for [`parse_with_options`].
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+  /// Parse as if scripting were enabled, which makes `<noscript>` content raw text.
+  /// Disable it to parse `<noscript>` content as markup, as a browser without
+  /// JavaScript does. Defaults to `true`.
+  pub scripting_enabled: bool,
+  /// Parse the document as the `srcdoc` of an `iframe`, which never puts it in quirks
+  /// mode for a missing doctype. Defaults to `false`.
+  pub iframe_srcdoc: bool,
+  /// Forces the document into this quirks mode, whatever its doctype says, for how
+  /// selectors match and what [`DomNode::quirks_mode`] reports. The tree builder also
+  /// starts in it, which decides how an `iframe` `srcdoc` without a doctype is built;
+  /// other documents are still built as their doctype, or the lack of one, asks.
+  pub quirks_mode: Option<QuirksMode>,
+  /// Report every parse error the spec describes, with more detailed messages, at
+  /// some cost in speed. Defaults to `false`.
+  pub exact_errors: bool,
+  /// Record where each element, text, comment and doctype came from in the input, for
+  /// [`DomNode::source_range`]. The input is then fed to the tokenizer a word or markup
+  /// character at a time, which is slower. Defaults to `false`.
+  pub source_locations: bool,
+}
+
+impl Default for ParseOptions {
+  fn default() -> Self {
+    ParseOptions {
+      scripting_enabled: true,
+      iframe_srcdoc: false,
+      quirks_mode: None,
+      exact_errors: false,
+      source_locations: false,
+    }
+  }
+}
+
+impl ParseOptions {
+  pub(crate) fn to_html5ever(&self) -> ParseOpts {
+    let mut opts = ParseOpts {
+      tree_builder: TreeBuilderOpts {
+        scripting_enabled: self.scripting_enabled,
+        iframe_srcdoc: self.iframe_srcdoc,
+        exact_errors: self.exact_errors,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    opts.tokenizer.exact_errors = self.exact_errors;
+    if let Some(quirks_mode) = self.quirks_mode {
+      opts.tree_builder.quirks_mode = quirks_mode;
+    }
+    opts
+  }
+
+  /// The tree the parser builds into. It starts in the same quirks mode as the tree
+  /// builder, which only reports a change of mode.
+  pub(crate) fn to_dom(&self) -> RcDom {
+    RcDom {
+      quirks_mode: self.quirks_mode.unwrap_or(QuirksMode::NoQuirks),
+      ..Default::default()
+    }
+  }
+}
+
+/// Parse string input to a html tree, return the root node.
+pub fn parse(html: String) -> DomNode {
+  parse_with_options(html, &ParseOptions::default())
+}
+
+/// Parses a document like [`parse`], with the given options.
+///
+/// ```
+/// use domparser::{parse_with_options, ParseOptions};
+///
+/// let options = ParseOptions {
+///   scripting_enabled: false,
+///   ..Default::default()
+/// };
+/// let html = "<p>Hi</p><noscript><img src=a.png></noscript>".to_string();
+/// let root = parse_with_options(html, &options);
+/// assert!(root.select("noscript > img".to_string()).unwrap().is_some());
+/// ```
+pub fn parse_with_options(html: String, options: &ParseOptions) -> DomNode {
+  let mut parser = DocumentParser::new(options);
+  parser.feed_tendril(html.into());
+  parser.finish()
+}
+
+/// Parses a document like [`parse_with_options`] unless it exceeds `limits`, which
+/// then also apply to markup later parsed into the document.
+///
+/// ```
+/// use domparser::{parse_with_limits, LimitError, ParseLimits, ParseOptions};
+///
+/// let limits = ParseLimits {
+///   max_depth: Some(64),
+///   ..Default::default()
+/// };
+/// let html = "<div>".repeat(100);
+/// let result = parse_with_limits(html, &ParseOptions::default(), limits);
+/// assert_eq!(result.err(), Some(LimitError::TooDeep { limit: 64 }));
+/// ```
+pub fn parse_with_limits(
+  html: String,
+  options: &ParseOptions,
+  limits: ParseLimits,
+) -> Result<DomNode, LimitError> {
+  limits.check_input_size(html.len())?;
+  parse_within_limits(html, options, limits)
+}
+
+/// Parses a document from bytes like [`parse_bytes_with_options`], with the limits of
+/// [`parse_with_limits`].
+pub fn parse_bytes_with_limits(
+  bytes: &[u8],
+  transport_charset: Option<&str>,
+  options: &ParseOptions,
+  limits: ParseLimits,
+) -> Result<DomNode, LimitError> {
+  // The size limit is on the input as given. Decoding may make it longer.
+  limits.check_input_size(bytes.len())?;
+  let (html, encoding) = decode(bytes, transport_charset);
+  let document = parse_within_limits(html, options, limits)?;
+  set_encoding(&document, encoding);
+  Ok(document)
+}
+
+/// Parses a document within the tree limits of `limits`, its size already checked.
+fn parse_within_limits(
+  html: String,
+  options: &ParseOptions,
+  limits: ParseLimits,
+) -> Result<DomNode, LimitError> {
+  let mut parser = DocumentParser::with_limits(options, limits);
+  parser.feed_tendril(html.into());
+  parser.try_finish()
+}
+
+/// Parses an XML document, such as an SVG image or an RSS feed, with namespaces and
+/// prefixes as written.
+///
+/// A document that is not well-formed becomes a document holding a single
+/// `<parsererror>` that describes the first error, as browsers do.
+///
+/// ```
+/// use domparser::parse_xml;
+///
+/// let root = parse_xml(r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>News</title></feed>"#.to_string());
+/// let title = root.select("title".to_string()).unwrap().unwrap();
+/// assert_eq!(title.namespace_uri().unwrap(), "http://www.w3.org/2005/Atom");
+///
+/// let broken = parse_xml("<a><b></a>".to_string());
+/// assert_eq!(broken.document_element().unwrap().tag_name().unwrap(), "parsererror");
+/// ```
+pub fn parse_xml(xml: String) -> DomNode {
+  match xml::parse(xml, MimeType::ApplicationXml, ParseLimits::default()) {
+    Ok(document) => document,
+    Err(_) => unreachable!("a parse without limits cannot exceed them"),
+  }
+}
+
+/// Parses an XML document like [`parse_xml`] unless it exceeds `limits`, which then
+/// also apply to markup later parsed into the document.
+///
+/// ```
+/// use domparser::{parse_xml_with_limits, LimitError, ParseLimits};
+///
+/// let limits = ParseLimits {
+///   max_nodes: Some(100),
+///   ..Default::default()
+/// };
+/// let xml = format!("<feed>{}</feed>", "<entry/>".repeat(1000));
+/// let result = parse_xml_with_limits(xml, limits);
+/// assert_eq!(result.err(), Some(LimitError::TooManyNodes { limit: 100 }));
+/// ```
+pub fn parse_xml_with_limits(xml: String, limits: ParseLimits) -> Result<DomNode, LimitError> {
+  xml::parse(xml, MimeType::ApplicationXml, limits)
+}
+
+/// Parses a document of the given type as `DOMParser.parseFromString` does: with the
+/// HTML parser for `text/html` and as XML otherwise, like [`parse_xml`].
+pub fn parse_from_string(input: String, mime_type: MimeType, options: &ParseOptions) -> DomNode {
+  match parse_from_string_with_limits(input, mime_type, options, ParseLimits::default()) {
+    Ok(document) => document,
+    Err(_) => unreachable!("a parse without limits cannot exceed them"),
+  }
+}
+
+/// Parses a document of the given type like [`parse_from_string`], with the limits
+/// of [`parse_with_limits`] whichever parser it goes to.
+pub fn parse_from_string_with_limits(
+  input: String,
+  mime_type: MimeType,
+  options: &ParseOptions,
+  limits: ParseLimits,
+) -> Result<DomNode, LimitError> {
+  if mime_type.is_html() {
+    parse_with_limits(input, options, limits)
+  } else {
+    xml::parse(input, mime_type, limits)
+  }
+}
+
+/// Parses a document from bytes in an unknown encoding.
+///
+/// The encoding is picked as browsers do: from a byte order mark, then
+/// `transport_charset` (the `charset` of a `Content-Type` header), then a
+/// `<meta charset>` in the first 1024 bytes, and otherwise by guessing from the
+/// content. [`DomNode::character_set`] reports the one chosen.
+///
+/// ```
+/// use domparser::parse_bytes;
+///
+/// let html = b"<meta charset=shift_jis><p>\x82\xb1\x82\xf1\x82\xc9\x82\xbf\x82\xcd</p>";
+/// let root = parse_bytes(html, None);
+/// assert_eq!(root.character_set(), "Shift_JIS");
+/// assert_eq!(root.text_content_getter(), "こんにちは");
+/// ```
+pub fn parse_bytes(bytes: &[u8], transport_charset: Option<&str>) -> DomNode {
+  parse_bytes_with_options(bytes, transport_charset, &ParseOptions::default())
+}
+
+/// Parses a document from bytes like [`parse_bytes`], with the given options.
+pub fn parse_bytes_with_options(
+  bytes: &[u8],
+  transport_charset: Option<&str>,
+  options: &ParseOptions,
+) -> DomNode {
+  let (html, encoding) = decode(bytes, transport_charset);
+  let document = parse_with_options(html, options);
+  set_encoding(&document, encoding);
+  document
+}
+
+/// Decodes a whole document in the encoding [`parse_bytes`] picks for it.
+fn decode(bytes: &[u8], transport_charset: Option<&str>) -> (String, &'static Encoding) {
+  let (encoding, bom_length) = encoding::sniff(bytes, transport_charset);
+  let (html, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+  (html.into_owned(), encoding)
+}
+
+fn set_encoding(document: &DomNode, encoding: &'static Encoding) {
+  let mut info = document.document_info();
+  info.encoding = encoding;
+  set_document_info(&document.0, info);
+}
+
+/// A parse error reported by the HTML tokenizer or tree builder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+  pub message: String,
+  /// 1-based line where the parser was when it detected the error.
+  pub line: u64,
+  /// 1-based column, in characters, where the parser was when it detected the error.
+  pub column: u64,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at {}:{}", self.message, self.line, self.column)
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A parsed document with the errors found while parsing it.
+pub struct ParseResult {
+  pub document: DomNode,
+  pub errors: Vec<ParseError>,
+}
+
+/// Parses a document like [`parse_with_options`], also collecting parse errors.
+///
+/// The input is fed to the tokenizer a word or markup character at a time to locate
+/// each error, so this is slower than a plain parse.
+///
+/// ```
+/// use domparser::{parse_with_diagnostics, ParseOptions};
+///
+/// let html = "<!DOCTYPE html>\n<p>a</b>".to_string();
+/// let result = parse_with_diagnostics(html, &ParseOptions::default());
+/// assert_eq!(result.errors.len(), 1);
+/// assert_eq!((result.errors[0].line, result.errors[0].column), (2, 8));
+/// ```
+pub fn parse_with_diagnostics(html: String, options: &ParseOptions) -> ParseResult {
+  let mut parser = tracking::TrackedParser::new(options, ParseLimits::default());
+  parser.feed(html.into());
+  let Ok((document, errors)) = parser.finish() else {
+    unreachable!("a parse without limits cannot exceed them");
+  };
+  if let Some(quirks_mode) = options.quirks_mode {
+    let mut info = document.document_info();
+    info.quirks_mode = quirks_mode;
+    set_document_info(&document.0, info);
+  }
+  ParseResult { document, errors }
+}
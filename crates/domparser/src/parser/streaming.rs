@@ -0,0 +1,234 @@
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_8};
+use html5ever::driver::Parser;
+use html5ever::parse_document;
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::tree_builder::QuirksMode;
+use markup5ever_rcdom::RcDom;
+
+use super::encoding::{sniff, PRESCAN_LIMIT};
+use super::limits::{chunks, LimitedSink};
+use super::tracking::TrackedParser;
+use super::{LimitError, ParseLimits, ParseOptions};
+use crate::node::{set_document_info, DocumentInfo};
+use crate::DomNode;
+
+enum Tree {
+  Plain(Box<Parser<LimitedSink<RcDom>>>),
+  Tracked(Box<TrackedParser>),
+}
+
+enum Input {
+  /// Bytes held until there are enough to pick an encoding.
+  Sniffing(Vec<u8>),
+  Decoding(Decoder),
+}
+
+/// Parses a document fed in chunks, so that large input never has to be held in
+/// memory at once.
+///
+/// Feed a document either as strings or as bytes. The encoding of bytes is sniffed
+/// as [`parse_bytes`](crate::parse_bytes) does, from the first 1024 bytes.
+///
+/// ```
+/// use domparser::{DocumentParser, ParseOptions};
+///
+/// let mut parser = DocumentParser::new(&ParseOptions::default());
+/// parser.feed("<ul><li>one");
+/// parser.feed("<li>two</ul>");
+/// let root = parser.finish();
+/// assert_eq!(root.select_all("li".to_string()).unwrap().len(), 2);
+/// ```
+pub struct DocumentParser {
+  tree: Tree,
+  input: Input,
+  transport_charset: Option<String>,
+  encoding: Option<&'static Encoding>,
+  limits: ParseLimits,
+  /// The quirks mode the document is forced into, if any.
+  quirks_mode: Option<QuirksMode>,
+  /// The bytes fed so far.
+  input_size: usize,
+  /// The first limit exceeded, after which input is no longer parsed.
+  error: Option<LimitError>,
+}
+
+impl DocumentParser {
+  pub fn new(options: &ParseOptions) -> Self {
+    Self::with_limits(options, ParseLimits::default())
+  }
+
+  /// Creates a parser that stops parsing as soon as the document exceeds `limits`,
+  /// which [`try_finish`](Self::try_finish) then reports. The limits also apply to
+  /// markup later parsed into the document.
+  ///
+  /// ```
+  /// use domparser::{DocumentParser, LimitError, ParseLimits, ParseOptions};
+  ///
+  /// let limits = ParseLimits {
+  ///   max_nodes: Some(100),
+  ///   ..Default::default()
+  /// };
+  /// let mut parser = DocumentParser::with_limits(&ParseOptions::default(), limits);
+  /// for _ in 0..1000 {
+  ///   parser.feed("<p>spam</p>");
+  /// }
+  /// assert_eq!(parser.try_finish().err(), Some(LimitError::TooManyNodes { limit: 100 }));
+  /// ```
+  pub fn with_limits(options: &ParseOptions, limits: ParseLimits) -> Self {
+    let tree = if options.source_locations {
+      Tree::Tracked(Box::new(TrackedParser::new(options, limits)))
+    } else {
+      Tree::Plain(Box::new(parse_document(
+        LimitedSink::new(options.to_dom(), limits),
+        options.to_html5ever(),
+      )))
+    };
+    DocumentParser {
+      tree,
+      input: Input::Sniffing(Vec::new()),
+      transport_charset: None,
+      encoding: None,
+      limits,
+      quirks_mode: options.quirks_mode,
+      input_size: 0,
+      error: None,
+    }
+  }
+
+  /// Sets the charset of the `Content-Type` header the bytes were served with. A
+  /// byte order mark still takes precedence.
+  pub fn transport_charset(mut self, charset: &str) -> Self {
+    self.transport_charset = Some(charset.to_string());
+    self
+  }
+
+  /// Parses the next chunk of a document given as text.
+  pub fn feed(&mut self, chunk: &str) {
+    self.count_input(chunk.len());
+    if matches!(&self.input, Input::Sniffing(buffer) if !buffer.is_empty()) {
+      self.start_decoding(false);
+    }
+    self.feed_tendril(StrTendril::from_slice(chunk));
+  }
+
+  /// Parses the next chunk of a document given as bytes in an unknown encoding.
+  pub fn feed_bytes(&mut self, chunk: &[u8]) {
+    self.count_input(chunk.len());
+    match &mut self.input {
+      Input::Sniffing(buffer) => {
+        buffer.extend_from_slice(chunk);
+        if buffer.len() >= PRESCAN_LIMIT {
+          self.start_decoding(false);
+        }
+      }
+      Input::Decoding(_) => self.decode(chunk, false),
+    }
+  }
+
+  /// Parses whatever input is left and returns the document.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the document exceeded the limits of a parser made with
+  /// [`with_limits`](Self::with_limits). Call [`try_finish`](Self::try_finish) on
+  /// those instead.
+  pub fn finish(self) -> DomNode {
+    self
+      .try_finish()
+      .expect("a parser without limits cannot exceed them")
+  }
+
+  /// Parses whatever input is left and returns the document, or the first limit it
+  /// exceeded.
+  pub fn try_finish(mut self) -> Result<DomNode, LimitError> {
+    match &self.input {
+      Input::Sniffing(buffer) if !buffer.is_empty() => self.start_decoding(true),
+      Input::Sniffing(_) => {}
+      Input::Decoding(_) => self.decode(&[], true),
+    }
+    if let Some(error) = self.error {
+      return Err(error);
+    }
+    let (document, quirks_mode) = match self.tree {
+      Tree::Plain(parser) => {
+        let (dom, error) = (*parser).finish();
+        if let Some(error) = error {
+          return Err(error);
+        }
+        (DomNode(dom.document), dom.quirks_mode)
+      }
+      Tree::Tracked(parser) => {
+        let document = parser.finish()?.0;
+        let quirks_mode = document.quirks_mode();
+        (document, quirks_mode)
+      }
+    };
+    // Nodes moved deeper once inserted are only found in the finished tree.
+    self.limits.check_tree(&document, 1)?;
+    set_document_info(
+      &document.0,
+      DocumentInfo {
+        encoding: self.encoding.unwrap_or(UTF_8),
+        quirks_mode: self.quirks_mode.unwrap_or(quirks_mode),
+        limits: self.limits,
+        ..Default::default()
+      },
+    );
+    Ok(document)
+  }
+
+  /// Parses `input` a chunk at a time, stopping once a limit is exceeded.
+  pub(super) fn feed_tendril(&mut self, input: StrTendril) {
+    for chunk in chunks(input) {
+      if self.error.is_some() {
+        return;
+      }
+      self.error = match &mut self.tree {
+        Tree::Plain(parser) => {
+          parser.process(chunk);
+          parser.tokenizer.sink.sink.error()
+        }
+        Tree::Tracked(parser) => {
+          parser.feed(chunk);
+          parser.limit_error()
+        }
+      };
+    }
+  }
+
+  fn count_input(&mut self, size: usize) {
+    self.input_size += size;
+    if let Err(error) = self.limits.check_input_size(self.input_size) {
+      self.error.get_or_insert(error);
+    }
+  }
+
+  fn start_decoding(&mut self, last: bool) {
+    let Input::Sniffing(buffer) = &mut self.input else {
+      return;
+    };
+    let buffer = std::mem::take(buffer);
+    let (encoding, bom_length) = sniff(&buffer, self.transport_charset.as_deref());
+    self.encoding = Some(encoding);
+    self.input = Input::Decoding(encoding.new_decoder_without_bom_handling());
+    self.decode(&buffer[bom_length..], last);
+  }
+
+  fn decode(&mut self, mut bytes: &[u8], last: bool) {
+    let Input::Decoding(decoder) = &mut self.input else {
+      return;
+    };
+    let mut text = String::with_capacity(bytes.len() + 16);
+    loop {
+      let (result, read, _) = decoder.decode_to_string(bytes, &mut text, last);
+      bytes = &bytes[read..];
+      match result {
+        CoderResult::InputEmpty => break,
+        CoderResult::OutputFull => text.reserve(bytes.len() * 3 + 16),
+      }
+    }
+    if !text.is_empty() {
+      self.feed_tendril(text.into());
+    }
+  }
+}
@@ -0,0 +1,674 @@
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::{
+  BufferQueue, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerResult,
+};
+use html5ever::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeBuilder, TreeSink};
+use html5ever::{local_name, Attribute, ExpandedName, LocalName, QualName};
+use markup5ever_rcdom::{Handle, Node, NodeData, RcDom};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::limits::LimitedSink;
+use super::{LimitError, ParseError, ParseLimits, ParseOptions};
+use crate::node::{
+  get_parent, record_locations, set_document_info, DocumentInfo, SourcePosition, SourceRange,
+  SourceSpan,
+};
+use crate::DomNode;
+
+/// A parser that feeds the tokenizer a run of characters at a time, so that parse
+/// errors and, when `options.source_locations` is set, nodes can be given a position.
+pub(super) struct TrackedParser {
+  tokenizer: Tokenizer<TokenTracker>,
+  queue: BufferQueue,
+  position: SourcePosition,
+  /// The last character fed.
+  previous: Option<char>,
+  /// Whether a character reference may be being read, whose characters are fed one
+  /// at a time to locate errors in it.
+  in_reference: bool,
+  /// A CR ending the last chunk, which is fed once it is known whether an LF follows.
+  held_cr: Option<StrTendril>,
+}
+
+impl TrackedParser {
+  pub(super) fn new(options: &ParseOptions, limits: ParseLimits) -> Self {
+    let sink = LocatingSink::new(options.to_dom(), options.source_locations);
+    Self::build(options, LimitedSink::new(sink, limits), None)
+  }
+
+  /// Creates a parser for a fragment in the given context, with the form element
+  /// pointer set to `form`, whose top-level nodes end up with `depth` ancestors.
+  pub(super) fn new_for_fragment(
+    options: &ParseOptions,
+    context: Handle,
+    form: Option<Handle>,
+    limits: ParseLimits,
+    depth: usize,
+  ) -> Self {
+    let sink = LocatingSink::new(options.to_dom(), options.source_locations);
+    let sink = LimitedSink::for_fragment(sink, limits, depth);
+    Self::build(options, sink, Some((context, form)))
+  }
+
+  fn build(
+    options: &ParseOptions,
+    sink: LimitedSink<LocatingSink>,
+    fragment: Option<(Handle, Option<Handle>)>,
+  ) -> Self {
+    let mut opts = options.to_html5ever();
+    let inner = match fragment {
+      Some((context, form)) => {
+        let tree_builder = TreeBuilder::new_for_fragment(sink, context, form, opts.tree_builder);
+        opts.tokenizer.initial_state = Some(tree_builder.tokenizer_state_for_context_elem());
+        tree_builder
+      }
+      None => TreeBuilder::new(sink, opts.tree_builder),
+    };
+    TrackedParser {
+      tokenizer: Tokenizer::new(TokenTracker { inner }, opts.tokenizer),
+      queue: BufferQueue::default(),
+      position: SourcePosition::default(),
+      previous: None,
+      in_reference: false,
+      held_cr: None,
+    }
+  }
+
+  pub(super) fn feed(&mut self, chunk: StrTendril) {
+    let Some(first) = chunk.chars().next() else {
+      return;
+    };
+    if let Some(cr) = self.held_cr.take() {
+      self.feed_run(cr, Some(first));
+    }
+    let mut offset = 0;
+    while offset < chunk.len() {
+      let rest = &chunk[offset..];
+      let end = offset
+        + match rest.chars().next() {
+          Some(c) if self.in_reference => c.len_utf8(),
+          _ => run_length(rest),
+        };
+      let run = chunk.subtendril(offset as u32, (end - offset) as u32);
+      let following = chunk[end..].chars().next();
+      if &*run == "\r" && following.is_none() {
+        self.held_cr = Some(run);
+      } else {
+        self.feed_run(run, following);
+      }
+      offset = end;
+    }
+  }
+
+  /// Feeds `run` to the tokenizer, `following` being the character after it.
+  fn feed_run(&mut self, run: StrTendril, following: Option<char>) {
+    let mut next = self.position;
+    let mut last = None;
+    let mut chars = run.chars().peekable();
+    while let Some(c) = chars.next() {
+      next = next.advance(c, chars.peek().copied().or(following));
+      last = Some(c);
+    }
+    let Some(last) = last else {
+      return;
+    };
+    let sink = &mut self.tokenizer.sink.inner.sink;
+    sink.start_run(last, self.position, next);
+    self.queue.push_back(run);
+    while let TokenizerResult::Script(_) = self.tokenizer.feed(&mut self.queue) {}
+    self.tokenizer.sink.inner.sink.end_run(last, self.previous);
+    self.previous = Some(last);
+    self.position = next;
+    self.in_reference = last == '&' || self.in_reference && (last.is_alphanumeric() || last == '#');
+  }
+
+  /// The first limit the input exceeded.
+  pub(super) fn limit_error(&self) -> Option<LimitError> {
+    self.tokenizer.sink.inner.sink.error()
+  }
+
+  pub(super) fn finish(mut self) -> Result<(DomNode, Vec<ParseError>), LimitError> {
+    if let Some(cr) = self.held_cr.take() {
+      self.feed_run(cr, None);
+    }
+    self.tokenizer.sink.inner.sink.start_eof(self.position);
+    self.tokenizer.end();
+    match self.tokenizer.sink.inner.sink.finish() {
+      (_, Some(error)) => Err(error),
+      (sink, None) => Ok(sink.into_result()),
+    }
+  }
+}
+
+/// The length of the run at the start of `input` to feed the tokenizer at once: a
+/// stretch of spaces, a word, or a single character that may end a token or start
+/// markup. Tokens then end at the end of a run, and the tree builder never splits a
+/// run into text nodes of its own.
+fn run_length(input: &str) -> usize {
+  let is_space = |c: char| matches!(c, ' ' | '\t' | '\x0C');
+  let is_single = |c: char| {
+    c.is_control()
+      || matches!(
+        c,
+        '<' | '>' | '&' | ';' | '"' | '\'' | '=' | '`' | '/' | '-' | '!' | '?' | '#' | '[' | ']'
+      )
+  };
+  let Some(first) = input.chars().next() else {
+    return 0;
+  };
+  if is_single(first) {
+    return first.len_utf8();
+  }
+  let same = |c: char| !is_single(c) && is_space(c) == is_space(first);
+  input
+    .char_indices()
+    .find(|&(_, c)| !same(c))
+    .map_or(input.len(), |(offset, _)| offset)
+}
+
+/// What the token being processed is, as far as locating nodes is concerned.
+enum TokenKind {
+  StartTag(LocalName),
+  EndTag(LocalName),
+  Text,
+  /// A comment or doctype.
+  Markup,
+  Eof,
+}
+
+/// Passes tokens to the tree builder, telling its sink where each one came from.
+struct TokenTracker {
+  inner: TreeBuilder<Handle, LimitedSink<LocatingSink>>,
+}
+
+impl TokenSink for TokenTracker {
+  type Handle = Handle;
+
+  fn process_token(&mut self, token: Token, line_number: u64) -> TokenSinkResult<Handle> {
+    let kind = match &token {
+      Token::TagToken(tag) if tag.kind == TagKind::StartTag => {
+        TokenKind::StartTag(tag.name.clone())
+      }
+      Token::TagToken(tag) => TokenKind::EndTag(tag.name.clone()),
+      Token::CommentToken(_) | Token::DoctypeToken(_) => TokenKind::Markup,
+      Token::CharacterTokens(text) => {
+        self.inner.sink.begin_text(text);
+        return self.process_located(token, line_number);
+      }
+      Token::NullCharacterToken => {
+        self.inner.sink.begin_text("\0");
+        return self.process_located(token, line_number);
+      }
+      Token::EOFToken => TokenKind::Eof,
+      Token::ParseError(_) => return self.inner.process_token(token, line_number),
+    };
+    self.inner.sink.begin_token(kind);
+    self.process_located(token, line_number)
+  }
+
+  fn end(&mut self) {
+    self.inner.sink.begin_token(TokenKind::Eof);
+    self.inner.end();
+    self.inner.sink.end_token();
+  }
+
+  fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+    self
+      .inner
+      .adjusted_current_node_present_but_not_in_html_namespace()
+  }
+}
+
+impl TokenTracker {
+  fn process_located(&mut self, token: Token, line_number: u64) -> TokenSinkResult<Handle> {
+    let result = self.inner.process_token(token, line_number);
+    self.inner.sink.end_token();
+    result
+  }
+}
+
+/// A tree sink that builds an `RcDom` and records where its nodes came from.
+struct LocatingSink {
+  dom: RcDom,
+  record: bool,
+  errors: Vec<ParseError>,
+  /// The last character of the run being fed, where the run starts and where the one
+  /// after it starts.
+  char: Option<char>,
+  position: SourcePosition,
+  next: SourcePosition,
+  /// The number of runs fed so far.
+  chars: usize,
+  /// A `<` that may start the markup being tokenized, with its run number.
+  markup_start: Option<(SourcePosition, usize)>,
+  /// Where the last token ended.
+  last_end: SourcePosition,
+  token: TokenKind,
+  span: SourceSpan,
+  /// The character tokens since the last other token, which the tree builder may
+  /// insert later, as it does for text in tables.
+  text_run: Option<SourceSpan>,
+  /// The element created for the current start tag.
+  token_element: Option<Handle>,
+  /// The text node the current character token went into.
+  token_text: Option<Handle>,
+  /// The located elements without an end tag that the tree builder may still hold
+  /// open, by lowercase name, innermost last. Each comes with the number of
+  /// references to it once its start tag was processed, which drops when the tree
+  /// builder lets go of it.
+  unclosed: HashMap<LocalName, Vec<(Handle, usize)>>,
+  /// The element the current end tag closes if the tree builder lets go of it, with
+  /// the number of references to it before the tag, or `usize::MAX` once it is
+  /// popped, as a script the tree builder hands back is.
+  closing: Option<(Handle, usize)>,
+  locations: HashMap<*const Node, (Handle, SourceRange)>,
+}
+
+impl LocatingSink {
+  fn new(dom: RcDom, record: bool) -> Self {
+    let start = SourcePosition::default();
+    LocatingSink {
+      dom,
+      record,
+      errors: Vec::new(),
+      char: None,
+      position: start,
+      next: start,
+      chars: 0,
+      markup_start: None,
+      last_end: start,
+      token: TokenKind::Eof,
+      span: SourceSpan { start, end: start },
+      text_run: None,
+      token_element: None,
+      token_text: None,
+      unclosed: HashMap::new(),
+      closing: None,
+      locations: HashMap::new(),
+    }
+  }
+
+  /// Starts a run of input ending in `c`, from `position` to `next`.
+  fn start_run(&mut self, c: char, position: SourcePosition, next: SourcePosition) {
+    self.char = Some(c);
+    self.position = position;
+    self.next = next;
+    self.chars += 1;
+  }
+
+  fn end_run(&mut self, c: char, previous: Option<char>) {
+    if c == '<' && self.markup_start.is_none() {
+      self.markup_start = Some((self.position, self.chars));
+    }
+    // The tokenizer turns a CR LF pair into one line feed when it sees the CR, and
+    // skips the LF, so text ending in the pair ends after the LF.
+    if c == '\n' && previous == Some('\r') && self.last_end == self.position {
+      if let TokenKind::Text = self.token {
+        self.last_end = self.next;
+        if let Some(run) = &mut self.text_run {
+          run.end = self.next;
+        }
+        if let Some(text) = &self.token_text {
+          if let Some((_, range)) = self.locations.get_mut(&Rc::as_ptr(text)) {
+            range.end = self.next;
+          }
+        }
+      }
+    }
+  }
+
+  fn start_eof(&mut self, position: SourcePosition) {
+    self.char = None;
+    self.position = position;
+    self.next = position;
+    self.chars += 1;
+  }
+
+  fn begin_token(&mut self, kind: TokenKind) {
+    let start = match kind {
+      TokenKind::StartTag(_) | TokenKind::EndTag(_) | TokenKind::Markup => self
+        .markup_start
+        .take()
+        .map_or(self.last_end, |(position, _)| position),
+      TokenKind::Text | TokenKind::Eof => self.last_end,
+    };
+    self.span = SourceSpan {
+      start,
+      end: self.next,
+    };
+    if let TokenKind::EndTag(name) = &kind {
+      self.closing = self.innermost_unclosed(name);
+    }
+    self.token = kind;
+    self.token_element = None;
+    self.token_text = None;
+  }
+
+  fn begin_text(&mut self, text: &str) {
+    // A `<` seen before this text did not start markup after all.
+    if matches!(self.markup_start, Some((_, index)) if index < self.chars) {
+      self.markup_start = None;
+    }
+    self.begin_token(TokenKind::Text);
+    // Text emitted on seeing a `<` or `&` ends before it, unless it is that character.
+    if let Some(c) = self.char {
+      if (c == '<' || c == '&') && !text.ends_with(c) {
+        self.span.end = self.position;
+      }
+    }
+    let start = self.text_run.map_or(self.span.start, |run| run.start);
+    self.text_run = Some(SourceSpan {
+      start,
+      end: self.span.end,
+    });
+  }
+
+  fn end_token(&mut self) {
+    self.last_end = self.span.end;
+    if !matches!(self.token, TokenKind::Text) {
+      self.text_run = None;
+    }
+    if let Some(element) = self.token_element.take() {
+      if let NodeData::Element { name, .. } = &element.data {
+        let key = LocalName::from(name.local.to_ascii_lowercase());
+        let held = Rc::strong_count(&element);
+        self.unclosed.entry(key).or_default().push((element, held));
+      }
+    }
+    if let Some((element, count)) = self.closing.take() {
+      self.close_element(element, count);
+    }
+  }
+
+  fn locate(&mut self, node: &Handle, span: SourceSpan, start_tag: Option<SourceSpan>) {
+    self.locations.insert(
+      Rc::as_ptr(node),
+      (
+        node.clone(),
+        SourceRange {
+          start: span.start,
+          end: span.end,
+          start_tag,
+          end_tag: None,
+        },
+      ),
+    );
+  }
+
+  fn locate_text(&mut self, node: Option<Handle>) {
+    let Some(node) = node.filter(|_| self.record) else {
+      return;
+    };
+    let span = match self.token {
+      TokenKind::Text => self.span,
+      _ => self.text_run.unwrap_or(self.span),
+    };
+    match self.locations.get_mut(&Rc::as_ptr(&node)) {
+      Some((_, range)) => range.end = range.end.max(span.end),
+      None => self.locate(&node, span, None),
+    }
+    self.token_text = Some(node);
+  }
+
+  /// The innermost element named `name` that the tree builder still holds, which is
+  /// the one an end tag of that name closes if it closes any.
+  fn innermost_unclosed(&mut self, name: &LocalName) -> Option<(Handle, usize)> {
+    let unclosed = self.unclosed.get_mut(name)?;
+    // Elements let go of without an end tag are dropped once they are innermost.
+    while let Some((element, held)) = unclosed.last() {
+      if Rc::strong_count(element) >= *held {
+        let element = element.clone();
+        let count = Rc::strong_count(&element);
+        return Some((element, count));
+      }
+      unclosed.pop();
+    }
+    None
+  }
+
+  /// Gives the end tag being processed to `element` if the tree builder let go of it,
+  /// `count` being the number of references to it before the tag.
+  fn close_element(&mut self, element: Handle, count: usize) {
+    let TokenKind::EndTag(name) = &self.token else {
+      return;
+    };
+    // The tree builder keeps `<body>` and `<html>` open after their end tags.
+    let stays_open = *name == local_name!("body") || *name == local_name!("html");
+    if !stays_open && Rc::strong_count(&element) >= count {
+      return;
+    }
+    if let Some(unclosed) = self.unclosed.get_mut(name) {
+      unclosed.pop();
+    }
+    if let Some((_, range)) = self.locations.get_mut(&Rc::as_ptr(&element)) {
+      range.end_tag = Some(self.span);
+      range.end = self.span.end;
+    }
+  }
+
+  fn into_result(mut self) -> (DomNode, Vec<ParseError>) {
+    let document = self.dom.document.clone();
+    if self.record {
+      self.extend_implied_ends(&document);
+      record_locations(self.locations.into_values().collect());
+    }
+    set_document_info(
+      &document,
+      DocumentInfo {
+        quirks_mode: self.dom.quirks_mode,
+        ..Default::default()
+      },
+    );
+    (DomNode(document), self.errors)
+  }
+
+  /// Ends each element without an end tag at the end of its last located descendant.
+  fn extend_implied_ends(&mut self, document: &Handle) {
+    let mut order = Vec::new();
+    let mut stack = vec![document.clone()];
+    while let Some(node) = stack.pop() {
+      stack.extend(children_of(&node));
+      order.push(node);
+    }
+
+    let mut subtree_ends: HashMap<*const Node, SourcePosition> = HashMap::new();
+    for node in order.iter().rev() {
+      let key = Rc::as_ptr(node);
+      let mut end = self.locations.get(&key).map(|(_, range)| range.end);
+      for child in children_of(node) {
+        if let Some(&child_end) = subtree_ends.get(&Rc::as_ptr(&child)) {
+          end = end.max(Some(child_end));
+        }
+      }
+      if let Some(end) = end {
+        if let Some((_, range)) = self.locations.get_mut(&key) {
+          if range.end_tag.is_none() {
+            range.end = end;
+          }
+        }
+        subtree_ends.insert(key, end);
+      }
+    }
+  }
+}
+
+/// The children of a node, with the contents of a template counted as its children.
+fn children_of(node: &Handle) -> Vec<Handle> {
+  let mut children = node.children.borrow().clone();
+  if let NodeData::Element {
+    template_contents, ..
+  } = &node.data
+  {
+    if let Some(contents) = template_contents.borrow().as_ref() {
+      children.extend(contents.children.borrow().iter().cloned());
+    }
+  }
+  children
+}
+
+impl TreeSink for LocatingSink {
+  type Handle = Handle;
+  type Output = Self;
+
+  fn finish(self) -> Self {
+    self
+  }
+
+  fn parse_error(&mut self, msg: Cow<'static, str>) {
+    self.errors.push(ParseError {
+      message: msg.into_owned(),
+      line: self.position.line,
+      column: self.position.column,
+    });
+  }
+
+  fn get_document(&mut self) -> Handle {
+    self.dom.get_document()
+  }
+
+  fn elem_name<'a>(&'a self, target: &'a Handle) -> ExpandedName<'a> {
+    self.dom.elem_name(target)
+  }
+
+  fn create_element(
+    &mut self,
+    name: QualName,
+    attrs: Vec<Attribute>,
+    flags: ElementFlags,
+  ) -> Handle {
+    let matches_token = match &self.token {
+      TokenKind::StartTag(tag) => {
+        tag.eq_ignore_ascii_case(&name.local)
+          || *tag == local_name!("image") && name.local == local_name!("img")
+      }
+      _ => false,
+    };
+    let element = self.dom.create_element(name, attrs, flags);
+    if self.record && matches_token {
+      // Elements recreated before the one for the tag, such as reopened formatting
+      // elements, are not from the tag.
+      if let Some(previous) = self.token_element.replace(element.clone()) {
+        self.locations.remove(&Rc::as_ptr(&previous));
+      }
+      let span = self.span;
+      self.locate(&element, span, Some(span));
+    }
+    element
+  }
+
+  fn create_comment(&mut self, text: StrTendril) -> Handle {
+    let comment = self.dom.create_comment(text);
+    if self.record {
+      let span = self.span;
+      self.locate(&comment, span, None);
+    }
+    comment
+  }
+
+  fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle {
+    self.dom.create_pi(target, data)
+  }
+
+  fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+    let is_text = matches!(child, NodeOrText::AppendText(_));
+    self.dom.append(parent, child);
+    if is_text {
+      let text = parent.children.borrow().last().cloned();
+      self.locate_text(text);
+    }
+  }
+
+  fn append_based_on_parent_node(
+    &mut self,
+    element: &Handle,
+    prev_element: &Handle,
+    child: NodeOrText<Handle>,
+  ) {
+    if get_parent(element).is_some() {
+      self.append_before_sibling(element, child);
+    } else {
+      self.append(prev_element, child);
+    }
+  }
+
+  fn append_doctype_to_document(
+    &mut self,
+    name: StrTendril,
+    public_id: StrTendril,
+    system_id: StrTendril,
+  ) {
+    self
+      .dom
+      .append_doctype_to_document(name, public_id, system_id);
+    if self.record {
+      let doctype = self.dom.document.children.borrow().last().cloned();
+      if let Some(doctype) = doctype {
+        let span = self.span;
+        self.locate(&doctype, span, None);
+      }
+    }
+  }
+
+  fn mark_script_already_started(&mut self, node: &Handle) {
+    self.dom.mark_script_already_started(node);
+  }
+
+  fn pop(&mut self, node: &Handle) {
+    // A void element is let go of as soon as it is inserted.
+    if let Some(element) = &self.token_element {
+      if Rc::ptr_eq(element, node) {
+        self.token_element = None;
+      }
+    }
+    if let Some((element, count)) = &mut self.closing {
+      if Rc::ptr_eq(element, node) {
+        *count = usize::MAX;
+      }
+    }
+  }
+
+  fn get_template_contents(&mut self, target: &Handle) -> Handle {
+    self.dom.get_template_contents(target)
+  }
+
+  fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+    self.dom.same_node(x, y)
+  }
+
+  fn set_quirks_mode(&mut self, mode: QuirksMode) {
+    self.dom.set_quirks_mode(mode);
+  }
+
+  fn append_before_sibling(&mut self, sibling: &Handle, new_node: NodeOrText<Handle>) {
+    let is_text = matches!(new_node, NodeOrText::AppendText(_));
+    self.dom.append_before_sibling(sibling, new_node);
+    if is_text {
+      let text = get_parent(sibling).and_then(|parent| {
+        let children = parent.children.borrow();
+        let index = children
+          .iter()
+          .position(|child| Rc::ptr_eq(child, sibling))?;
+        index.checked_sub(1).map(|index| children[index].clone())
+      });
+      self.locate_text(text);
+    }
+  }
+
+  fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+    self.dom.add_attrs_if_missing(target, attrs);
+  }
+
+  fn remove_from_parent(&mut self, target: &Handle) {
+    self.dom.remove_from_parent(target);
+  }
+
+  fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+    self.dom.reparent_children(node, new_parent);
+  }
+
+  fn is_mathml_annotation_xml_integration_point(&self, handle: &Handle) -> bool {
+    self.dom.is_mathml_annotation_xml_integration_point(handle)
+  }
+}
@@ -0,0 +1,264 @@
+use markup5ever_rcdom::{Handle, Node, NodeData, RcDom};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+use xml5ever::driver::{parse_document, XmlParseOpts, XmlParser};
+use xml5ever::interface::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use xml5ever::tendril::{StrTendril, TendrilSink};
+use xml5ever::{Attribute, ExpandedName, LocalName, Namespace, QualName};
+
+use super::limits::{chunks, LimitError, LimitedSink, ParseLimits};
+use crate::node::{set_document_info, DocumentInfo, MimeType, SourcePosition};
+use crate::DomNode;
+
+/// The namespace of the `<parsererror>` element a document that is not well-formed
+/// is replaced with, as browsers do.
+const PARSERERROR_NAMESPACE: &str = "http://www.mozilla.org/newlayout/xml/parsererror.xml";
+
+/// Parses `xml` as a document of the given type, replacing it with a `<parsererror>`
+/// document if it is not well-formed, unless it exceeds `limits`.
+pub(super) fn parse(
+  xml: String,
+  content_type: MimeType,
+  limits: ParseLimits,
+) -> Result<DomNode, LimitError> {
+  limits.check_input_size(xml.len())?;
+  let sink = LimitedSink::new(XmlSink::default(), limits);
+  let mut parser = parse_document(sink, XmlParseOpts::default());
+  for chunk in chunks(StrTendril::from_slice(&xml)) {
+    parser.process(chunk);
+    if let Some(error) = parser.tokenizer.sink.sink.error() {
+      return Err(error);
+    }
+  }
+  let (sink, error) = end(parser);
+  if let Some(error) = error {
+    return Err(error);
+  }
+  let document = match check(sink) {
+    Ok(document) => document,
+    Err(message) => {
+      let position = locate_error(&xml);
+      parser_error_document(&message, position, &xml)
+    }
+  };
+  set_document_info(
+    &document,
+    DocumentInfo {
+      content_type,
+      limits,
+      ..Default::default()
+    },
+  );
+  Ok(DomNode(document))
+}
+
+/// Finishes parsing. Elements still open are an error, which the sink can only tell
+/// before the tree builder closes them.
+fn end(mut parser: XmlParser<LimitedSink<XmlSink>>) -> (XmlSink, Option<LimitError>) {
+  parser.tokenizer.sink.sink.input_ended = true;
+  parser.finish()
+}
+
+/// Returns the document, or the first well-formedness error.
+fn check(sink: XmlSink) -> Result<Handle, Cow<'static, str>> {
+  match sink.errors.into_iter().next() {
+    Some(message) => Err(message),
+    None => Ok(sink.dom.document),
+  }
+}
+
+/// Finds where the first error is reported by feeding the input again one character
+/// at a time, which is only worth doing once the input is known to have one.
+fn locate_error(xml: &str) -> SourcePosition {
+  let mut parser = parse_document(XmlSink::default(), XmlParseOpts::default());
+  let mut position = SourcePosition::default();
+  let mut chars = xml.chars().peekable();
+  while let Some(c) = chars.next() {
+    parser.process(StrTendril::from_char(c));
+    if !parser.tokenizer.sink.sink.errors.is_empty() {
+      return position;
+    }
+    position = position.advance(c, chars.peek().copied());
+  }
+  position
+}
+
+/// A document holding only a `<parsererror>` that describes the error, in the form
+/// Firefox uses: a message followed by a `<sourcetext>` pointing at the error.
+fn parser_error_document(message: &str, position: SourcePosition, xml: &str) -> Handle {
+  let line = xml
+    .lines()
+    .nth(position.line as usize - 1)
+    .unwrap_or_default();
+  let source_text = format!("{}\n{}^", line, "-".repeat(position.column as usize - 1));
+
+  let root = new_element("parsererror");
+  append(
+    &root,
+    new_text(format!(
+      "XML Parsing Error: {}\nLine Number {}, Column {}:",
+      message, position.line, position.column
+    )),
+  );
+  let source = new_element("sourcetext");
+  append(&source, new_text(source_text));
+  append(&root, source);
+
+  let document = RcDom::default().document;
+  append(&document, root);
+  document
+}
+
+fn new_element(name: &str) -> Handle {
+  Node::new(NodeData::Element {
+    name: QualName::new(
+      None,
+      Namespace::from(PARSERERROR_NAMESPACE),
+      LocalName::from(name),
+    ),
+    attrs: RefCell::new(vec![]),
+    template_contents: RefCell::new(None),
+    mathml_annotation_xml_integration_point: false,
+  })
+}
+
+fn new_text(text: String) -> Handle {
+  Node::new(NodeData::Text {
+    contents: RefCell::new(text.into()),
+  })
+}
+
+fn append(parent: &Handle, child: Handle) {
+  child.parent.set(Some(Rc::downgrade(parent)));
+  parent.children.borrow_mut().push(child);
+}
+
+/// Builds the tree in an [`RcDom`], collecting the errors the XML parser reports and
+/// those it lets through: elements left open at the end and a misplaced XML declaration.
+#[derive(Default)]
+struct XmlSink {
+  dom: RcDom,
+  errors: Vec<Cow<'static, str>>,
+  open_elements: usize,
+  input_ended: bool,
+}
+
+impl TreeSink for XmlSink {
+  type Handle = Handle;
+  type Output = Self;
+
+  fn finish(mut self) -> Self {
+    if self.open_elements > 0 {
+      self
+        .errors
+        .push(Cow::Borrowed("Unclosed element at end of input"));
+    }
+    self
+  }
+
+  fn parse_error(&mut self, msg: Cow<'static, str>) {
+    self.errors.push(msg);
+  }
+
+  fn get_document(&mut self) -> Handle {
+    self.dom.get_document()
+  }
+
+  fn elem_name<'a>(&'a self, target: &'a Handle) -> ExpandedName<'a> {
+    self.dom.elem_name(target)
+  }
+
+  fn create_element(
+    &mut self,
+    name: QualName,
+    attrs: Vec<Attribute>,
+    flags: ElementFlags,
+  ) -> Handle {
+    self.open_elements += 1;
+    self.dom.create_element(name, attrs, flags)
+  }
+
+  fn create_comment(&mut self, text: StrTendril) -> Handle {
+    self.dom.create_comment(text)
+  }
+
+  fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle {
+    self.dom.create_pi(target, data)
+  }
+
+  fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+    // The XML declaration is read as a processing instruction, but it is not a node.
+    if let NodeOrText::AppendNode(node) = &child {
+      if let NodeData::ProcessingInstruction { target, .. } = &node.data {
+        if target.eq_ignore_ascii_case("xml") {
+          let at_start =
+            matches!(parent.data, NodeData::Document) && parent.children.borrow().is_empty();
+          if !at_start {
+            self.errors.push(Cow::Borrowed(
+              "XML or text declaration not at start of entity",
+            ));
+          }
+          return;
+        }
+      }
+    }
+    self.dom.append(parent, child)
+  }
+
+  fn append_based_on_parent_node(
+    &mut self,
+    element: &Handle,
+    prev_element: &Handle,
+    child: NodeOrText<Handle>,
+  ) {
+    self
+      .dom
+      .append_based_on_parent_node(element, prev_element, child)
+  }
+
+  fn append_doctype_to_document(
+    &mut self,
+    name: StrTendril,
+    public_id: StrTendril,
+    system_id: StrTendril,
+  ) {
+    self
+      .dom
+      .append_doctype_to_document(name, public_id, system_id)
+  }
+
+  fn pop(&mut self, _node: &Handle) {
+    if !self.input_ended {
+      self.open_elements -= 1;
+    }
+  }
+
+  fn get_template_contents(&mut self, target: &Handle) -> Handle {
+    self.dom.get_template_contents(target)
+  }
+
+  fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+    self.dom.same_node(x, y)
+  }
+
+  fn set_quirks_mode(&mut self, mode: QuirksMode) {
+    self.dom.set_quirks_mode(mode)
+  }
+
+  fn append_before_sibling(&mut self, sibling: &Handle, new_node: NodeOrText<Handle>) {
+    self.dom.append_before_sibling(sibling, new_node)
+  }
+
+  fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+    self.dom.add_attrs_if_missing(target, attrs)
+  }
+
+  fn remove_from_parent(&mut self, target: &Handle) {
+    self.dom.remove_from_parent(target)
+  }
+
+  fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+    self.dom.reparent_children(node, new_parent)
+  }
+}
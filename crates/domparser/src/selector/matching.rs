@@ -0,0 +1,627 @@
+use html5ever::tree_builder::QuirksMode;
+use html5ever::{namespace_url, ns, Attribute, Namespace, QualName};
+use markup5ever_rcdom::{Handle, Node, NodeData};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::states;
+use super::{
+  AttributeCase, AttributeOperator, AttributeSelector, Combinator, ComplexSelector,
+  CompoundSelector, NamespaceConstraint, Nth, PseudoClass, SelectorList, SimpleSelector,
+};
+use crate::DomNode;
+
+/// State shared by every step of a single match.
+#[derive(Clone)]
+pub(crate) struct MatchingContext<'a> {
+  /// The element (or document) that `:scope` refers to. Without one, `:scope` is `:root`.
+  pub(crate) scope: Option<&'a DomNode>,
+  /// Whether the elements belong to an HTML document, where HTML element names and
+  /// some attribute values match case-insensitively.
+  pub(crate) html_document: bool,
+  /// Whether the document is in quirks mode, where ids and classes match
+  /// ASCII case-insensitively.
+  pub(crate) quirks_mode: bool,
+  /// Sibling positions already counted by `:nth-*` pseudo-classes.
+  pub(crate) nth_cache: Rc<NthCache>,
+}
+
+/// Identifies one `:nth-*` pseudo-class counting in one direction, with the `:scope` its
+/// `of S` selector was matched against.
+type NthKey = (*const PseudoClass, bool, Option<*const Node>);
+
+/// Remembers sibling indices and `:nth-*` positions for the length of a query, so that
+/// matching every child of a long list or table stays linear. rcdom has no sibling
+/// links, so without it finding an element among its siblings is itself a scan.
+#[derive(Default)]
+pub(crate) struct NthCache {
+  /// The index of each child among its parent's children, filled a parent at a time.
+  indices: RefCell<HashMap<*const Node, usize>>,
+  positions: RefCell<HashMap<(NthKey, *const Node), usize>>,
+}
+
+impl NthCache {
+  fn index_of(&self, children: &[Handle], element: &DomNode) -> Option<usize> {
+    let mut indices = self.indices.borrow_mut();
+    if let Some(&index) = indices.get(&Rc::as_ptr(&element.0)) {
+      return Some(index);
+    }
+    let index = children
+      .iter()
+      .position(|child| Rc::ptr_eq(child, &element.0))?;
+    indices.extend(
+      children
+        .iter()
+        .enumerate()
+        .map(|(index, child)| (Rc::as_ptr(child), index)),
+    );
+    Some(index)
+  }
+
+  fn position(&self, key: NthKey, element: &Handle) -> Option<usize> {
+    self
+      .positions
+      .borrow()
+      .get(&(key, Rc::as_ptr(element)))
+      .copied()
+  }
+}
+
+impl<'a> MatchingContext<'a> {
+  pub(crate) fn new(scope: &'a DomNode) -> Self {
+    MatchingContext {
+      scope: Some(scope),
+      ..MatchingContext::unscoped(scope)
+    }
+  }
+
+  /// A context for matching within `node`'s document with no scoping root, as a style
+  /// sheet does, so that `:scope` is `:root`.
+  pub(crate) fn unscoped(node: &DomNode) -> Self {
+    let info = node.document_info();
+    MatchingContext {
+      scope: None,
+      html_document: info.content_type.is_html(),
+      quirks_mode: info.quirks_mode == QuirksMode::Quirks,
+      nth_cache: Rc::default(),
+    }
+  }
+}
+
+impl SelectorList {
+  /// Returns whether `element` matches any selector in the list.
+  pub(crate) fn matches(&self, element: &DomNode, context: &MatchingContext) -> bool {
+    self
+      .0
+      .iter()
+      .any(|selector| selector.matches(element, context))
+  }
+}
+
+impl ComplexSelector {
+  pub(crate) fn matches(&self, element: &DomNode, context: &MatchingContext) -> bool {
+    matches_complex(element, self, self.compounds.len() - 1, context)
+  }
+}
+
+/// Matches `element` against `selector.compounds[index]`, then walks the combinators
+/// right to left, backtracking over candidate ancestors and siblings.
+fn matches_complex(
+  element: &DomNode,
+  selector: &ComplexSelector,
+  index: usize,
+  context: &MatchingContext,
+) -> bool {
+  if !matches_compound(element, &selector.compounds[index], context) {
+    return false;
+  }
+  if index == 0 {
+    return true;
+  }
+
+  match selector.combinators[index - 1] {
+    Combinator::Child => element
+      .parent_node()
+      .is_some_and(|parent| matches_complex(&parent, selector, index - 1, context)),
+    Combinator::Descendant => {
+      let mut current = element.parent_node();
+      while let Some(ancestor) = current {
+        if matches_complex(&ancestor, selector, index - 1, context) {
+          return true;
+        }
+        current = ancestor.parent_node();
+      }
+      false
+    }
+    Combinator::NextSibling => element
+      .previous_element_sibling()
+      .is_some_and(|sibling| matches_complex(&sibling, selector, index - 1, context)),
+    Combinator::SubsequentSibling => {
+      let mut current = element.previous_element_sibling();
+      while let Some(sibling) = current {
+        if matches_complex(&sibling, selector, index - 1, context) {
+          return true;
+        }
+        current = sibling.previous_element_sibling();
+      }
+      false
+    }
+  }
+}
+
+fn matches_compound(
+  element: &DomNode,
+  compound: &CompoundSelector,
+  context: &MatchingContext,
+) -> bool {
+  if !is_element(element) {
+    // Only a bare `:scope` can match a scoping root that is not an element, such as the
+    // document in `document.querySelectorAll(":scope > html")`.
+    return compound
+      .0
+      .iter()
+      .all(|simple| matches!(simple, SimpleSelector::PseudoClass(PseudoClass::Scope)))
+      && context
+        .scope
+        .is_some_and(|scope| scope.is_same_node(element));
+  }
+  compound
+    .0
+    .iter()
+    .all(|simple| matches_simple(element, simple, context))
+}
+
+/// Compares an id or class, which quirks mode matches case-insensitively.
+fn matches_identifier(value: &str, expected: &str, context: &MatchingContext) -> bool {
+  if context.quirks_mode {
+    value.eq_ignore_ascii_case(expected)
+  } else {
+    value == expected
+  }
+}
+
+fn matches_simple(element: &DomNode, selector: &SimpleSelector, context: &MatchingContext) -> bool {
+  let NodeData::Element { name, attrs, .. } = &element.0.data else {
+    return false;
+  };
+  match selector {
+    SimpleSelector::Universal(namespace) => matches_namespace(&name.ns, namespace),
+    SimpleSelector::Type(namespace, tag) => {
+      matches_namespace(&name.ns, namespace)
+        && matches_name(element, name.local.as_ref(), tag, context)
+    }
+    SimpleSelector::Id(id) => attrs
+      .borrow()
+      .iter()
+      .any(|a| a.name.local.as_ref() == "id" && matches_identifier(&a.value, id, context)),
+    SimpleSelector::Class(class) => attrs
+      .borrow()
+      .iter()
+      .find(|a| a.name.local.as_ref() == "class")
+      .is_some_and(|a| {
+        a.value
+          .split_ascii_whitespace()
+          .any(|c| matches_identifier(c, class, context))
+      }),
+    SimpleSelector::Attribute(selector) => matches_attribute(element, selector, context),
+    SimpleSelector::PseudoClass(pseudo) => matches_pseudo_class(element, pseudo, context),
+  }
+}
+
+fn matches_attribute(
+  element: &DomNode,
+  selector: &AttributeSelector,
+  context: &MatchingContext,
+) -> bool {
+  let NodeData::Element { attrs, .. } = &element.0.data else {
+    return false;
+  };
+  // A name in any namespace, as in `[*|href]`, may match several attributes.
+  attrs.borrow().iter().any(|attr| {
+    matches_attribute_name(element, &attr.name, selector, context)
+      && matches_attribute_value(element, attr, selector, context)
+  })
+}
+
+fn matches_attribute_value(
+  element: &DomNode,
+  attr: &Attribute,
+  selector: &AttributeSelector,
+  context: &MatchingContext,
+) -> bool {
+  let Some((operator, expected)) = &selector.operation else {
+    return true;
+  };
+  let ignore_case = match selector.case {
+    AttributeCase::Insensitive => true,
+    AttributeCase::Sensitive => false,
+    AttributeCase::Default => {
+      is_html_element(element, context)
+        && attr.name.ns == ns!()
+        && is_case_insensitive_attribute(&attr.name.local)
+    }
+  };
+  let (actual, expected): (Cow<str>, Cow<str>) = if ignore_case {
+    (
+      attr.value.to_ascii_lowercase().into(),
+      expected.to_ascii_lowercase().into(),
+    )
+  } else {
+    (attr.value.as_ref().into(), expected.as_str().into())
+  };
+  let (actual, expected) = (actual.as_ref(), expected.as_ref());
+  match operator {
+    AttributeOperator::Equals => actual == expected,
+    AttributeOperator::Includes => {
+      !expected.is_empty()
+        && !expected.contains(|c: char| c.is_ascii_whitespace())
+        && actual.split_ascii_whitespace().any(|v| v == expected)
+    }
+    AttributeOperator::DashMatch => {
+      actual == expected
+        || (actual.starts_with(expected) && actual[expected.len()..].starts_with('-'))
+    }
+    AttributeOperator::Prefix => !expected.is_empty() && actual.starts_with(expected),
+    AttributeOperator::Suffix => !expected.is_empty() && actual.ends_with(expected),
+    AttributeOperator::Substring => !expected.is_empty() && actual.contains(expected),
+  }
+}
+
+/// Whether values of the attribute are compared ASCII case-insensitively on HTML
+/// elements, per the HTML spec's list of attributes matched that way by selectors.
+fn is_case_insensitive_attribute(name: &str) -> bool {
+  matches!(
+    name,
+    "accept"
+      | "accept-charset"
+      | "align"
+      | "alink"
+      | "axis"
+      | "bgcolor"
+      | "charset"
+      | "checked"
+      | "clear"
+      | "codetype"
+      | "color"
+      | "compact"
+      | "declare"
+      | "defer"
+      | "dir"
+      | "direction"
+      | "disabled"
+      | "enctype"
+      | "face"
+      | "frame"
+      | "hreflang"
+      | "http-equiv"
+      | "lang"
+      | "language"
+      | "link"
+      | "media"
+      | "method"
+      | "multiple"
+      | "nohref"
+      | "noresize"
+      | "noshade"
+      | "nowrap"
+      | "readonly"
+      | "rel"
+      | "rev"
+      | "rules"
+      | "scope"
+      | "scrolling"
+      | "selected"
+      | "shape"
+      | "target"
+      | "text"
+      | "type"
+      | "valign"
+      | "valuetype"
+      | "vlink"
+  )
+}
+
+fn matches_attribute_name(
+  element: &DomNode,
+  name: &QualName,
+  selector: &AttributeSelector,
+  context: &MatchingContext,
+) -> bool {
+  match &selector.namespace {
+    // Without a prefix the selector is compared with the qualified name, so `xlink\:href`
+    // still finds `xlink:href`.
+    None => match &name.prefix {
+      Some(prefix) if !prefix.is_empty() => matches_name(
+        element,
+        &format!("{}:{}", prefix, name.local),
+        &selector.name,
+        context,
+      ),
+      _ => matches_name(element, name.local.as_ref(), &selector.name, context),
+    },
+    Some(namespace) => {
+      matches_namespace(&name.ns, namespace)
+        && matches_name(element, name.local.as_ref(), &selector.name, context)
+    }
+  }
+}
+
+fn matches_namespace(actual: &Namespace, constraint: &NamespaceConstraint) -> bool {
+  match constraint {
+    NamespaceConstraint::Any => true,
+    NamespaceConstraint::Empty => *actual == ns!(),
+    NamespaceConstraint::Uri(uri) => actual.as_ref() == uri,
+  }
+}
+
+/// Compares an element or attribute name with a name from a selector. Names are only
+/// ASCII case-insensitive on HTML elements in HTML documents, so `foreignObject` and
+/// `linearGradient` must be written in their own case.
+fn matches_name(
+  element: &DomNode,
+  actual: &str,
+  expected: &str,
+  context: &MatchingContext,
+) -> bool {
+  if is_html_element(element, context) {
+    actual.eq_ignore_ascii_case(expected)
+  } else {
+    actual == expected
+  }
+}
+
+fn is_html_element(element: &DomNode, context: &MatchingContext) -> bool {
+  context.html_document
+    && matches!(&element.0.data, NodeData::Element { name, .. } if name.ns == ns!(html))
+}
+
+fn matches_pseudo_class(
+  element: &DomNode,
+  pseudo: &PseudoClass,
+  context: &MatchingContext,
+) -> bool {
+  match pseudo {
+    PseudoClass::Root => is_root(element),
+    PseudoClass::Scope => match context.scope {
+      Some(scope) => scope.is_same_node(element),
+      None => is_root(element),
+    },
+    PseudoClass::Is(list) | PseudoClass::Where(list) => list.matches(element, context),
+    PseudoClass::Not(list) => !list.matches(element, context),
+    PseudoClass::Has(list) => list
+      .0
+      .iter()
+      .any(|relative| matches_relative(element, relative, context)),
+    PseudoClass::Empty => element
+      .0
+      .children
+      .borrow()
+      .iter()
+      .all(|child| match &child.data {
+        NodeData::Element { .. } => false,
+        NodeData::Text { contents } => contents.borrow().is_empty(),
+        _ => true,
+      }),
+    PseudoClass::FirstChild => matches_nth(
+      element,
+      pseudo,
+      Nth { a: 0, b: 1 },
+      false,
+      context,
+      |_, _| true,
+    ),
+    PseudoClass::LastChild => matches_nth(
+      element,
+      pseudo,
+      Nth { a: 0, b: 1 },
+      true,
+      context,
+      |_, _| true,
+    ),
+    PseudoClass::OnlyChild => {
+      matches_nth(
+        element,
+        pseudo,
+        Nth { a: 0, b: 1 },
+        false,
+        context,
+        |_, _| true,
+      ) && matches_nth(
+        element,
+        pseudo,
+        Nth { a: 0, b: 1 },
+        true,
+        context,
+        |_, _| true,
+      )
+    }
+    PseudoClass::FirstOfType => matches_nth(
+      element,
+      pseudo,
+      Nth { a: 0, b: 1 },
+      false,
+      context,
+      is_same_type,
+    ),
+    PseudoClass::LastOfType => matches_nth(
+      element,
+      pseudo,
+      Nth { a: 0, b: 1 },
+      true,
+      context,
+      is_same_type,
+    ),
+    PseudoClass::OnlyOfType => {
+      matches_nth(
+        element,
+        pseudo,
+        Nth { a: 0, b: 1 },
+        false,
+        context,
+        is_same_type,
+      ) && matches_nth(
+        element,
+        pseudo,
+        Nth { a: 0, b: 1 },
+        true,
+        context,
+        is_same_type,
+      )
+    }
+    PseudoClass::NthChild(nth, of) => {
+      matches_nth(element, pseudo, *nth, false, context, |_, sibling| {
+        of.as_ref().is_none_or(|of| of.matches(sibling, context))
+      })
+    }
+    PseudoClass::NthLastChild(nth, of) => {
+      matches_nth(element, pseudo, *nth, true, context, |_, sibling| {
+        of.as_ref().is_none_or(|of| of.matches(sibling, context))
+      })
+    }
+    PseudoClass::NthOfType(nth) => matches_nth(element, pseudo, *nth, false, context, is_same_type),
+    PseudoClass::NthLastOfType(nth) => {
+      matches_nth(element, pseudo, *nth, true, context, is_same_type)
+    }
+    PseudoClass::Checked => states::is_checked(element),
+    PseudoClass::Disabled => states::is_disabled(element),
+    PseudoClass::Enabled => states::is_enabled(element),
+    PseudoClass::Required => states::is_required(element),
+    PseudoClass::Optional => states::is_optional(element),
+    PseudoClass::ReadOnly => !states::is_read_write(element),
+    PseudoClass::ReadWrite => states::is_read_write(element),
+    PseudoClass::Default => states::is_default(element),
+    PseudoClass::AnyLink | PseudoClass::Link => states::is_link(element),
+    PseudoClass::Defined => states::is_defined(element),
+    PseudoClass::Lang(ranges) => states::matches_lang(element, ranges),
+    PseudoClass::Dir(direction) => states::matches_dir(element, direction),
+  }
+}
+
+/// Matches a `:has()` argument, which was parsed as `:scope <combinator> ...`, with
+/// `anchor` bound to `:scope`.
+fn matches_relative(
+  anchor: &DomNode,
+  selector: &ComplexSelector,
+  context: &MatchingContext,
+) -> bool {
+  let context = MatchingContext {
+    scope: Some(anchor),
+    ..context.clone()
+  };
+  let reaches_siblings = selector.combinators.iter().any(|combinator| {
+    matches!(
+      combinator,
+      Combinator::NextSibling | Combinator::SubsequentSibling
+    )
+  });
+  // Every element the relative selector can reach lives below the anchor, or below its
+  // parent once sibling combinators are involved.
+  let root = if reaches_siblings {
+    match anchor.parent_node() {
+      Some(parent) => parent,
+      None => return false,
+    }
+  } else {
+    anchor.clone()
+  };
+
+  let last = selector.compounds.len() - 1;
+  let mut stack: Vec<DomNode> = root.child_nodes().into_iter().rev().collect();
+  while let Some(candidate) = stack.pop() {
+    if matches_complex(&candidate, selector, last, &context) {
+      return true;
+    }
+    stack.extend(candidate.child_nodes().into_iter().rev());
+  }
+  false
+}
+
+/// Computes the 1-based position of `element` among its element siblings accepted by
+/// `filter`, counting from the end when `from_end` is set, and tests it against `nth`.
+///
+/// The walk over the siblings on the counted side stops at the first one whose position
+/// `pseudo` has already counted, or once the position has grown past every value `nth`
+/// can still match.
+fn matches_nth(
+  element: &DomNode,
+  pseudo: &PseudoClass,
+  nth: Nth,
+  from_end: bool,
+  context: &MatchingContext,
+  filter: impl Fn(&DomNode, &DomNode) -> bool,
+) -> bool {
+  if !filter(element, element) {
+    return false;
+  }
+  let Some(parent) = element.parent_node() else {
+    return nth.matches(1);
+  };
+  let cache = &context.nth_cache;
+  let key = (
+    pseudo as *const PseudoClass,
+    from_end,
+    context.scope.map(|scope| Rc::as_ptr(&scope.0)),
+  );
+  let position = match cache.position(key, &element.0) {
+    Some(position) => position,
+    None => {
+      let children = parent.0.children.borrow();
+      let Some(index) = cache.index_of(&children, element) else {
+        return false;
+      };
+      let before: &mut dyn Iterator<Item = &Handle> = if from_end {
+        &mut children[index + 1..].iter()
+      } else {
+        &mut children[..index].iter().rev()
+      };
+      // With a non-positive step, no position above `b` can match.
+      let limit = (nth.a <= 0).then(|| usize::try_from(nth.b).unwrap_or(0));
+      let mut position = 1;
+      for sibling in before {
+        if limit.is_some_and(|limit| position > limit) {
+          return false;
+        }
+        let sibling = DomNode(sibling.clone());
+        if is_element(&sibling) && filter(element, &sibling) {
+          if let Some(known) = cache.position(key, &sibling.0) {
+            position += known;
+            break;
+          }
+          position += 1;
+        }
+      }
+      cache
+        .positions
+        .borrow_mut()
+        .insert((key, Rc::as_ptr(&element.0)), position);
+      position
+    }
+  };
+  i32::try_from(position).is_ok_and(|position| nth.matches(position))
+}
+
+fn is_root(element: &DomNode) -> bool {
+  element
+    .parent_node()
+    .is_some_and(|parent| matches!(parent.0.data, NodeData::Document))
+}
+
+fn is_same_type(element: &DomNode, other: &DomNode) -> bool {
+  match (&element.0.data, &other.0.data) {
+    (NodeData::Element { name: a, .. }, NodeData::Element { name: b, .. }) => {
+      a.local == b.local && a.ns == b.ns
+    }
+    _ => false,
+  }
+}
+
+/// Returns whether the node is an element, excluding the element that stands in for a
+/// document fragment.
+fn is_element(node: &DomNode) -> bool {
+  match &node.0.data {
+    NodeData::Element { name, .. } => name.local.as_ref() != "#document-fragment",
+    _ => false,
+  }
+}
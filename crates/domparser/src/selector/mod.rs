@@ -0,0 +1,329 @@
+mod matching;
+mod parser;
+mod specificity;
+mod states;
+
+use html5ever::{namespace_url, ns};
+use std::collections::HashMap;
+use std::fmt;
+
+pub(crate) use matching::MatchingContext;
+pub use specificity::Specificity;
+
+use crate::DomNode;
+
+/// A selector list parsed once and reusable across nodes and documents.
+///
+/// ```
+/// use domparser::{parse, Selector};
+///
+/// let selector = Selector::parse("ul > li:first-child").unwrap();
+/// let root = parse("<ul><li>a</li><li>b</li></ul>".to_string());
+/// assert_eq!(root.select_compiled(&selector).unwrap().text(), "a");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Selector(pub(crate) SelectorList);
+
+impl Selector {
+  pub fn parse(selectors: &str) -> Result<Selector, SelectorError> {
+    Selector::parse_with_namespaces(selectors, &NamespaceMap::default())
+  }
+
+  /// Parses a selector list whose `prefix|name` selectors resolve against `namespaces`.
+  pub fn parse_with_namespaces(
+    selectors: &str,
+    namespaces: &NamespaceMap,
+  ) -> Result<Selector, SelectorError> {
+    SelectorList::parse(selectors, namespaces).map(Selector)
+  }
+
+  /// The specificity of each complex selector in the list, in source order.
+  pub fn specificities(&self) -> Vec<Specificity> {
+    self.0 .0.iter().map(ComplexSelector::specificity).collect()
+  }
+
+  /// Explains why `element` matches: of the complex selectors in the list that match,
+  /// returns the one the cascade would use, which is the most specific and, among
+  /// equally specific ones, the first. Returns `None` if nothing matches.
+  ///
+  /// As in a style sheet, `:scope` matches the root element rather than `element`.
+  ///
+  /// ```
+  /// use domparser::{parse, Selector, Specificity};
+  ///
+  /// let root = parse("<p id=\"a\" class=\"b\"></p>".to_string());
+  /// let p = root.select("p".to_string()).unwrap().unwrap();
+  /// let selector = Selector::parse("p, .b, #a, div").unwrap();
+  /// let found = selector.match_details(&p).unwrap();
+  /// assert_eq!((found.index, found.selector.as_str()), (2, "#a"));
+  /// assert_eq!(found.specificity, Specificity { a: 1, b: 0, c: 0 });
+  /// ```
+  pub fn match_details(&self, element: &DomNode) -> Option<SelectorMatch> {
+    let context = MatchingContext::unscoped(element);
+    self
+      .0
+       .0
+      .iter()
+      .enumerate()
+      .filter(|(_, selector)| selector.matches(element, &context))
+      .map(|(index, selector)| SelectorMatch {
+        index,
+        selector: selector.source.clone(),
+        specificity: selector.specificity(),
+      })
+      .reduce(|best, next| {
+        if next.specificity > best.specificity {
+          next
+        } else {
+          best
+        }
+      })
+  }
+}
+
+/// The complex selector of a list that an element matched, from
+/// [`Selector::match_details`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorMatch {
+  /// Position of the complex selector in the list.
+  pub index: usize,
+  /// The complex selector as written, without surrounding whitespace.
+  pub selector: String,
+  pub specificity: Specificity,
+}
+
+/// Namespace prefixes available to selectors, like CSS `@namespace` rules.
+///
+/// The `html`, `svg`, `math` and `xlink` prefixes are predefined.
+#[derive(Debug, Clone)]
+pub struct NamespaceMap {
+  default: Option<String>,
+  prefixes: HashMap<String, String>,
+}
+
+impl Default for NamespaceMap {
+  fn default() -> Self {
+    let prefixes = [
+      ("html", ns!(html)),
+      ("svg", ns!(svg)),
+      ("math", ns!(mathml)),
+      ("xlink", ns!(xlink)),
+    ]
+    .into_iter()
+    .map(|(prefix, uri)| (prefix.to_string(), uri.to_string()))
+    .collect();
+    NamespaceMap {
+      default: None,
+      prefixes,
+    }
+  }
+}
+
+impl NamespaceMap {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Binds `prefix` to `uri`, like `@namespace prefix url(uri)`.
+  pub fn insert(&mut self, prefix: String, uri: String) {
+    self.prefixes.insert(prefix, uri);
+  }
+
+  /// Restricts type and universal selectors without a prefix to `uri`, like
+  /// `@namespace url(uri)`.
+  pub fn set_default(&mut self, uri: String) {
+    self.default = Some(uri);
+  }
+
+  pub(crate) fn get(&self, prefix: &str) -> Option<&str> {
+    self.prefixes.get(prefix).map(String::as_str)
+  }
+
+  pub(crate) fn default_namespace(&self) -> Option<&str> {
+    self.default.as_deref()
+  }
+}
+
+impl std::str::FromStr for Selector {
+  type Err = SelectorError;
+
+  fn from_str(selectors: &str) -> Result<Self, Self::Err> {
+    Selector::parse(selectors)
+  }
+}
+
+/// A parsed selector list, such as `div.a > p, #main li`.
+#[derive(Debug, Clone)]
+pub(crate) struct SelectorList(pub(crate) Vec<ComplexSelector>);
+
+/// A chain of compound selectors joined by combinators.
+///
+/// `combinators[i]` sits between `compounds[i]` and `compounds[i + 1]`.
+#[derive(Debug, Clone)]
+pub(crate) struct ComplexSelector {
+  pub(crate) compounds: Vec<CompoundSelector>,
+  pub(crate) combinators: Vec<Combinator>,
+  /// The selector as written, for reporting matches.
+  pub(crate) source: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Combinator {
+  /// `a b`
+  Descendant,
+  /// `a > b`
+  Child,
+  /// `a + b`
+  NextSibling,
+  /// `a ~ b`
+  SubsequentSibling,
+}
+
+/// A sequence of simple selectors that all apply to the same element.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CompoundSelector(pub(crate) Vec<SimpleSelector>);
+
+#[derive(Debug, Clone)]
+pub(crate) enum SimpleSelector {
+  Universal(NamespaceConstraint),
+  Type(NamespaceConstraint, String),
+  Id(String),
+  Class(String),
+  Attribute(AttributeSelector),
+  PseudoClass(PseudoClass),
+}
+
+/// The `ns|` part of a type, universal or attribute selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum NamespaceConstraint {
+  /// `*|`, or no prefix on a type selector without a default namespace.
+  Any,
+  /// `|`, matching only names without a namespace.
+  Empty,
+  /// A declared prefix, resolved to its namespace URI.
+  Uri(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AttributeSelector {
+  /// `None` when no prefix was written, matching the attribute's qualified name.
+  pub(crate) namespace: Option<NamespaceConstraint>,
+  pub(crate) name: String,
+  pub(crate) operation: Option<(AttributeOperator, String)>,
+  pub(crate) case: AttributeCase,
+}
+
+/// How an attribute selector compares values, set by the `i` and `s` flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AttributeCase {
+  /// No flag: ASCII case-insensitive only for the HTML attributes listed in
+  /// [`matching::is_case_insensitive_attribute`].
+  Default,
+  /// `[attr=value i]`
+  Insensitive,
+  /// `[attr=value s]`
+  Sensitive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AttributeOperator {
+  /// `[attr=value]`
+  Equals,
+  /// `[attr~=value]`
+  Includes,
+  /// `[attr|=value]`
+  DashMatch,
+  /// `[attr^=value]`
+  Prefix,
+  /// `[attr$=value]`
+  Suffix,
+  /// `[attr*=value]`
+  Substring,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum PseudoClass {
+  Root,
+  Scope,
+  /// `:is(S)`, with invalid arguments dropped from the list.
+  Is(SelectorList),
+  /// `:where(S)`, like `:is()` but without specificity.
+  Where(SelectorList),
+  Not(SelectorList),
+  /// `:has(S)`, each relative selector stored with a leading `:scope` compound.
+  Has(SelectorList),
+  Empty,
+  FirstChild,
+  LastChild,
+  OnlyChild,
+  FirstOfType,
+  LastOfType,
+  OnlyOfType,
+  /// `:nth-child(An+B [of S])`
+  NthChild(Nth, Option<SelectorList>),
+  /// `:nth-last-child(An+B [of S])`
+  NthLastChild(Nth, Option<SelectorList>),
+  NthOfType(Nth),
+  NthLastOfType(Nth),
+  Checked,
+  Disabled,
+  Enabled,
+  Required,
+  Optional,
+  ReadOnly,
+  ReadWrite,
+  Default,
+  AnyLink,
+  /// `:link`, the same as `:any-link` since no link is ever visited.
+  Link,
+  Defined,
+  /// `:lang(L, ...)`, holding the language ranges lowercased.
+  Lang(Vec<String>),
+  /// `:dir(ltr)` or `:dir(rtl)`. Any other identifier is kept and never matches.
+  Dir(String),
+}
+
+/// The `An+B` micro-syntax used by the `:nth-*` pseudo-classes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Nth {
+  pub(crate) a: i32,
+  pub(crate) b: i32,
+}
+
+impl Nth {
+  /// Returns whether the 1-based `position` is `An+B` for some non-negative `n`.
+  pub(crate) fn matches(&self, position: i32) -> bool {
+    // Coefficients saturate at the bounds of `i32`, so the arithmetic is done wider.
+    let (a, diff) = (i64::from(self.a), i64::from(position) - i64::from(self.b));
+    if a == 0 {
+      diff == 0
+    } else {
+      diff % a == 0 && diff / a >= 0
+    }
+  }
+}
+
+/// An error produced while parsing a selector string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorError {
+  pub message: String,
+  /// Character offset in the selector string where the error was detected.
+  pub position: usize,
+}
+
+impl fmt::Display for SelectorError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at position {}", self.message, self.position)
+  }
+}
+
+impl std::error::Error for SelectorError {}
+
+impl SelectorList {
+  pub(crate) fn parse(
+    input: &str,
+    namespaces: &NamespaceMap,
+  ) -> Result<SelectorList, SelectorError> {
+    parser::Parser::new(input, namespaces).parse_selector_list()
+  }
+}
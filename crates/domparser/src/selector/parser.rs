@@ -0,0 +1,718 @@
+use super::{
+  AttributeCase, AttributeOperator, AttributeSelector, Combinator, ComplexSelector,
+  CompoundSelector, NamespaceConstraint, NamespaceMap, Nth, PseudoClass, SelectorError,
+  SelectorList, SimpleSelector,
+};
+
+/// How deeply functional pseudo-classes such as `:is()` may nest. Parsing and
+/// matching recurse into their arguments, so this bounds the stack they use.
+const MAX_NESTING: usize = 32;
+
+/// A hand-written recursive descent parser for the selector grammar.
+///
+/// Positions reported in errors are character offsets into the input.
+pub(super) struct Parser<'a> {
+  chars: Vec<char>,
+  pos: usize,
+  namespaces: &'a NamespaceMap,
+  /// The number of functional pseudo-classes the parser is inside.
+  nesting: usize,
+  /// The error for nesting too deeply, which forgiving selector lists do not forgive.
+  too_deep: Option<SelectorError>,
+}
+
+impl<'a> Parser<'a> {
+  pub(super) fn new(input: &str, namespaces: &'a NamespaceMap) -> Self {
+    Parser {
+      chars: input.chars().collect(),
+      pos: 0,
+      namespaces,
+      nesting: 0,
+      too_deep: None,
+    }
+  }
+
+  pub(super) fn parse_selector_list(&mut self) -> Result<SelectorList, SelectorError> {
+    let mut selectors = Vec::new();
+    loop {
+      self.skip_whitespace();
+      selectors.push(self.parse_complex()?);
+      self.skip_whitespace();
+      match self.peek() {
+        None => break,
+        Some(',') => self.pos += 1,
+        Some(c) => return Err(self.error(format!("Unexpected character '{}'", c))),
+      }
+    }
+    Ok(SelectorList(selectors))
+  }
+
+  fn parse_complex(&mut self) -> Result<ComplexSelector, SelectorError> {
+    let start = self.pos;
+    let mut compounds = vec![self.parse_compound()?];
+    let mut combinators = Vec::new();
+    loop {
+      let had_whitespace = self.skip_whitespace();
+      let combinator = match self.peek() {
+        None | Some(',' | ')') => break,
+        Some('>') => Combinator::Child,
+        Some('+') => Combinator::NextSibling,
+        Some('~') => Combinator::SubsequentSibling,
+        Some(_) if had_whitespace => Combinator::Descendant,
+        Some(c) => return Err(self.error(format!("Unexpected character '{}'", c))),
+      };
+      if combinator != Combinator::Descendant {
+        self.pos += 1;
+        self.skip_whitespace();
+      }
+      compounds.push(self.parse_compound()?);
+      combinators.push(combinator);
+    }
+    let source: String = self.chars[start..self.pos].iter().collect();
+    Ok(ComplexSelector {
+      compounds,
+      combinators,
+      source: source.trim_end().to_string(),
+    })
+  }
+
+  fn parse_compound(&mut self) -> Result<CompoundSelector, SelectorError> {
+    let mut selectors = Vec::new();
+
+    match self.parse_type_selector()? {
+      Some((namespace, Some(name))) => selectors.push(SimpleSelector::Type(namespace, name)),
+      Some((namespace, None)) => selectors.push(SimpleSelector::Universal(namespace)),
+      None => {}
+    }
+
+    loop {
+      match self.peek() {
+        Some('#') => {
+          self.pos += 1;
+          let name = self.parse_ident()?;
+          selectors.push(SimpleSelector::Id(name));
+        }
+        Some('.') => {
+          self.pos += 1;
+          let name = self.parse_ident()?;
+          selectors.push(SimpleSelector::Class(name));
+        }
+        Some('[') => {
+          self.pos += 1;
+          selectors.push(SimpleSelector::Attribute(self.parse_attribute()?));
+        }
+        Some(':') => {
+          self.pos += 1;
+          selectors.push(SimpleSelector::PseudoClass(self.parse_pseudo_class()?));
+        }
+        _ => break,
+      }
+    }
+
+    if selectors.is_empty() {
+      return Err(match self.peek() {
+        Some(c) => self.error(format!("Unexpected character '{}'", c)),
+        None => self.error("Expected selector"),
+      });
+    }
+    // Under a default namespace, `.a` means `*.a` and so is restricted to it as well.
+    if let (Some(uri), false) = (
+      self.namespaces.default_namespace(),
+      matches!(
+        selectors[0],
+        SimpleSelector::Type(..) | SimpleSelector::Universal(_)
+      ),
+    ) {
+      selectors.insert(
+        0,
+        SimpleSelector::Universal(NamespaceConstraint::Uri(uri.to_string())),
+      );
+    }
+    Ok(CompoundSelector(selectors))
+  }
+
+  /// Parses an optional type or universal selector with its namespace prefix, returning
+  /// `None` as the name for `*`.
+  fn parse_type_selector(
+    &mut self,
+  ) -> Result<Option<(NamespaceConstraint, Option<String>)>, SelectorError> {
+    let start = self.pos;
+    let namespace = match self.peek() {
+      Some('|') => {
+        self.pos += 1;
+        NamespaceConstraint::Empty
+      }
+      Some('*') if self.peek_at(1) == Some('|') => {
+        self.pos += 2;
+        NamespaceConstraint::Any
+      }
+      Some('*') => {
+        self.pos += 1;
+        return Ok(Some((self.default_namespace(), None)));
+      }
+      _ if self.at_ident_start() => {
+        let name = self.parse_ident()?;
+        if self.peek() != Some('|') {
+          return Ok(Some((self.default_namespace(), Some(name))));
+        }
+        self.pos += 1;
+        self.resolve_prefix(&name, start)?
+      }
+      _ => return Ok(None),
+    };
+    if self.peek() == Some('*') {
+      self.pos += 1;
+      Ok(Some((namespace, None)))
+    } else if self.at_ident_start() {
+      Ok(Some((namespace, Some(self.parse_ident()?))))
+    } else {
+      Err(self.error("Expected element name or '*' after namespace prefix"))
+    }
+  }
+
+  fn default_namespace(&self) -> NamespaceConstraint {
+    match self.namespaces.default_namespace() {
+      Some(uri) => NamespaceConstraint::Uri(uri.to_string()),
+      None => NamespaceConstraint::Any,
+    }
+  }
+
+  fn resolve_prefix(
+    &self,
+    prefix: &str,
+    position: usize,
+  ) -> Result<NamespaceConstraint, SelectorError> {
+    match self.namespaces.get(prefix) {
+      Some(uri) => Ok(NamespaceConstraint::Uri(uri.to_string())),
+      None => Err(self.error_at(
+        position,
+        format!("Undeclared namespace prefix '{}'", prefix),
+      )),
+    }
+  }
+
+  /// Parses a comma separated list of complex selectors inside a functional
+  /// pseudo-class, stopping before the closing parenthesis.
+  fn parse_nested_selector_list(&mut self) -> Result<SelectorList, SelectorError> {
+    let mut selectors = Vec::new();
+    loop {
+      self.skip_whitespace();
+      selectors.push(self.parse_complex()?);
+      self.skip_whitespace();
+      match self.peek() {
+        Some(',') => self.pos += 1,
+        _ => break,
+      }
+    }
+    Ok(SelectorList(selectors))
+  }
+
+  /// Parses the argument of `:is()` and `:where()`. Selectors that fail to parse are
+  /// dropped instead of invalidating the whole list.
+  fn parse_forgiving_selector_list(&mut self) -> Result<SelectorList, SelectorError> {
+    let mut selectors = Vec::new();
+    loop {
+      self.skip_whitespace();
+      let start = self.pos;
+      let parsed = self.parse_complex();
+      if let Some(error) = &self.too_deep {
+        return Err(error.clone());
+      }
+      let parsed = parsed.ok().filter(|_| {
+        self.skip_whitespace();
+        matches!(self.peek(), Some(',' | ')'))
+      });
+      match parsed {
+        Some(selector) => selectors.push(selector),
+        None => {
+          self.pos = start;
+          self.skip_to_list_separator();
+        }
+      }
+      match self.peek() {
+        Some(',') => self.pos += 1,
+        _ => break,
+      }
+    }
+    Ok(SelectorList(selectors))
+  }
+
+  /// Skips input up to the next `,` or `)` that is not nested inside brackets or strings.
+  fn skip_to_list_separator(&mut self) {
+    let mut closers = Vec::new();
+    while let Some(c) = self.peek() {
+      match c {
+        ',' | ')' if closers.is_empty() => return,
+        '(' => closers.push(')'),
+        '[' => closers.push(']'),
+        ')' | ']' if closers.last() == Some(&c) => {
+          closers.pop();
+        }
+        '"' | '\'' => {
+          self.pos += 1;
+          if self.parse_string(c).is_err() {
+            return;
+          }
+          continue;
+        }
+        '\\' => self.pos += 1,
+        _ => {}
+      }
+      self.pos += 1;
+    }
+  }
+
+  /// Parses the argument of `:has()`. Each relative selector is absolutized by
+  /// prepending a `:scope` compound, using the descendant combinator when none is given.
+  fn parse_relative_selector_list(&mut self) -> Result<SelectorList, SelectorError> {
+    let mut selectors = Vec::new();
+    loop {
+      self.skip_whitespace();
+      let combinator = match self.peek() {
+        Some('>') => Combinator::Child,
+        Some('+') => Combinator::NextSibling,
+        Some('~') => Combinator::SubsequentSibling,
+        _ => Combinator::Descendant,
+      };
+      if combinator != Combinator::Descendant {
+        self.pos += 1;
+        self.skip_whitespace();
+      }
+      let mut selector = self.parse_complex()?;
+      selector.compounds.insert(
+        0,
+        CompoundSelector(vec![SimpleSelector::PseudoClass(PseudoClass::Scope)]),
+      );
+      selector.combinators.insert(0, combinator);
+      selectors.push(selector);
+      self.skip_whitespace();
+      match self.peek() {
+        Some(',') => self.pos += 1,
+        _ => break,
+      }
+    }
+    Ok(SelectorList(selectors))
+  }
+
+  /// Parses a pseudo-class, the leading colon already consumed.
+  fn parse_pseudo_class(&mut self) -> Result<PseudoClass, SelectorError> {
+    if self.peek() == Some(':') {
+      return Err(self.error("Pseudo-elements are not supported"));
+    }
+    let start = self.pos;
+    let name = self.parse_ident()?.to_ascii_lowercase();
+
+    if self.peek() != Some('(') {
+      return Ok(match name.as_str() {
+        "root" => PseudoClass::Root,
+        "scope" => PseudoClass::Scope,
+        "empty" => PseudoClass::Empty,
+        "first-child" => PseudoClass::FirstChild,
+        "last-child" => PseudoClass::LastChild,
+        "only-child" => PseudoClass::OnlyChild,
+        "first-of-type" => PseudoClass::FirstOfType,
+        "last-of-type" => PseudoClass::LastOfType,
+        "only-of-type" => PseudoClass::OnlyOfType,
+        "checked" => PseudoClass::Checked,
+        "disabled" => PseudoClass::Disabled,
+        "enabled" => PseudoClass::Enabled,
+        "required" => PseudoClass::Required,
+        "optional" => PseudoClass::Optional,
+        "read-only" => PseudoClass::ReadOnly,
+        "read-write" => PseudoClass::ReadWrite,
+        "default" => PseudoClass::Default,
+        "any-link" => PseudoClass::AnyLink,
+        "link" => PseudoClass::Link,
+        "defined" => PseudoClass::Defined,
+        _ => return Err(self.error_at(start, format!("Unknown pseudo-class ':{}'", name))),
+      });
+    }
+
+    self.pos += 1;
+    self.skip_whitespace();
+    if self.nesting == MAX_NESTING {
+      let error = self.error_at(start, "Selectors are nested too deeply");
+      self.too_deep = Some(error.clone());
+      return Err(error);
+    }
+    self.nesting += 1;
+    let pseudo = self.parse_pseudo_class_arguments(&name, start);
+    self.nesting -= 1;
+    let pseudo = pseudo?;
+    self.skip_whitespace();
+    self.expect(')')?;
+    Ok(pseudo)
+  }
+
+  /// Parses the arguments of the functional pseudo-class `name`, up to the closing
+  /// parenthesis.
+  fn parse_pseudo_class_arguments(
+    &mut self,
+    name: &str,
+    start: usize,
+  ) -> Result<PseudoClass, SelectorError> {
+    Ok(match name {
+      "nth-child" | "nth-last-child" => {
+        let nth = self.parse_nth()?;
+        let of = if self.skip_whitespace() && self.eat_keyword("of") {
+          if !self.skip_whitespace() {
+            return Err(self.error("Expected whitespace after 'of'"));
+          }
+          Some(self.parse_nested_selector_list()?)
+        } else {
+          None
+        };
+        if name == "nth-child" {
+          PseudoClass::NthChild(nth, of)
+        } else {
+          PseudoClass::NthLastChild(nth, of)
+        }
+      }
+      "is" => PseudoClass::Is(self.parse_forgiving_selector_list()?),
+      "where" => PseudoClass::Where(self.parse_forgiving_selector_list()?),
+      "not" => PseudoClass::Not(self.parse_nested_selector_list()?),
+      "has" => PseudoClass::Has(self.parse_relative_selector_list()?),
+      "nth-of-type" => PseudoClass::NthOfType(self.parse_nth()?),
+      "nth-last-of-type" => PseudoClass::NthLastOfType(self.parse_nth()?),
+      "lang" => PseudoClass::Lang(self.parse_language_ranges()?),
+      "dir" => PseudoClass::Dir(self.parse_ident()?.to_ascii_lowercase()),
+      _ => return Err(self.error_at(start, format!("Unknown pseudo-class ':{}()'", name))),
+    })
+  }
+
+  /// Parses the comma-separated identifiers or strings of `:lang()`.
+  fn parse_language_ranges(&mut self) -> Result<Vec<String>, SelectorError> {
+    let mut ranges = Vec::new();
+    loop {
+      let range = match self.peek() {
+        Some(quote @ ('"' | '\'')) => {
+          self.pos += 1;
+          self.parse_string(quote)?
+        }
+        _ => self.parse_ident()?,
+      };
+      ranges.push(range.to_ascii_lowercase());
+      self.skip_whitespace();
+      if self.peek() != Some(',') {
+        return Ok(ranges);
+      }
+      self.pos += 1;
+      self.skip_whitespace();
+    }
+  }
+
+  /// Parses the `An+B` micro-syntax, e.g. `odd`, `3`, `-n+2` or `2n - 1`.
+  fn parse_nth(&mut self) -> Result<Nth, SelectorError> {
+    if self.eat_keyword("odd") {
+      return Ok(Nth { a: 2, b: 1 });
+    }
+    if self.eat_keyword("even") {
+      return Ok(Nth { a: 2, b: 0 });
+    }
+
+    let start = self.pos;
+    let sign = match self.peek() {
+      Some('-') => {
+        self.pos += 1;
+        -1
+      }
+      Some('+') => {
+        self.pos += 1;
+        1
+      }
+      _ => 1,
+    };
+    let digits = self.parse_integer();
+
+    if matches!(self.peek(), Some('n' | 'N')) {
+      self.pos += 1;
+      let a = sign * digits.unwrap_or(1);
+      let before_whitespace = self.pos;
+      self.skip_whitespace();
+      let b_sign = match self.peek() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => {
+          self.pos = before_whitespace;
+          return self.finish_nth(start, Nth { a, b: 0 });
+        }
+      };
+      self.pos += 1;
+      self.skip_whitespace();
+      let b = self
+        .parse_integer()
+        .ok_or_else(|| self.error("Expected integer in An+B expression"))?;
+      return self.finish_nth(start, Nth { a, b: b_sign * b });
+    }
+
+    match digits {
+      Some(b) => self.finish_nth(start, Nth { a: 0, b: sign * b }),
+      None => Err(self.error_at(start, "Invalid An+B expression")),
+    }
+  }
+
+  fn finish_nth(&mut self, start: usize, nth: Nth) -> Result<Nth, SelectorError> {
+    if self.at_ident_start() || self.peek().is_some_and(|c| c.is_ascii_digit()) {
+      return Err(self.error_at(start, "Invalid An+B expression"));
+    }
+    Ok(nth)
+  }
+
+  fn parse_integer(&mut self) -> Option<i32> {
+    let start = self.pos;
+    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+      self.pos += 1;
+    }
+    if self.pos == start {
+      return None;
+    }
+    let digits: String = self.chars[start..self.pos].iter().collect();
+    Some(digits.parse().unwrap_or(i32::MAX))
+  }
+
+  /// Consumes `keyword` (ASCII case-insensitively) if it appears as a whole identifier.
+  fn eat_keyword(&mut self, keyword: &str) -> bool {
+    let len = keyword.chars().count();
+    let Some(candidate) = self.chars.get(self.pos..self.pos + len) else {
+      return false;
+    };
+    let candidate: String = candidate.iter().collect();
+    let followed_by_name = self.peek_at(len).is_some_and(is_name_char);
+    if candidate.eq_ignore_ascii_case(keyword) && !followed_by_name {
+      self.pos += len;
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Parses the inside of `[...]`, the opening bracket already consumed.
+  fn parse_attribute(&mut self) -> Result<AttributeSelector, SelectorError> {
+    self.skip_whitespace();
+    let start = self.pos;
+    let (namespace, name) = match self.peek() {
+      Some('|') => {
+        self.pos += 1;
+        (Some(NamespaceConstraint::Empty), self.parse_ident()?)
+      }
+      Some('*') if self.peek_at(1) == Some('|') => {
+        self.pos += 2;
+        (Some(NamespaceConstraint::Any), self.parse_ident()?)
+      }
+      _ => {
+        let name = self.parse_ident()?;
+        if self.peek() == Some('|') && self.peek_at(1) != Some('=') {
+          self.pos += 1;
+          (
+            Some(self.resolve_prefix(&name, start)?),
+            self.parse_ident()?,
+          )
+        } else {
+          (None, name)
+        }
+      }
+    };
+    self.skip_whitespace();
+
+    let operator = match self.peek() {
+      Some(']') => {
+        self.pos += 1;
+        return Ok(AttributeSelector {
+          namespace,
+          name,
+          operation: None,
+          case: AttributeCase::Default,
+        });
+      }
+      Some('=') => {
+        self.pos += 1;
+        AttributeOperator::Equals
+      }
+      Some(c) if self.peek_at(1) == Some('=') => {
+        let operator = match c {
+          '~' => AttributeOperator::Includes,
+          '|' => AttributeOperator::DashMatch,
+          '^' => AttributeOperator::Prefix,
+          '$' => AttributeOperator::Suffix,
+          '*' => AttributeOperator::Substring,
+          _ => return Err(self.error("Expected attribute operator")),
+        };
+        self.pos += 2;
+        operator
+      }
+      _ => return Err(self.error("Expected attribute operator or ']'")),
+    };
+
+    self.skip_whitespace();
+    let value = match self.peek() {
+      Some(quote @ ('"' | '\'')) => {
+        self.pos += 1;
+        self.parse_string(quote)?
+      }
+      _ => self.parse_ident()?,
+    };
+    self.skip_whitespace();
+    let case = match self.peek() {
+      Some(c) if c != ']' => {
+        let start = self.pos;
+        let flag = self.parse_ident()?;
+        self.skip_whitespace();
+        match flag.to_ascii_lowercase().as_str() {
+          "i" => AttributeCase::Insensitive,
+          "s" => AttributeCase::Sensitive,
+          _ => return Err(self.error_at(start, format!("Unknown attribute flag '{}'", flag))),
+        }
+      }
+      _ => AttributeCase::Default,
+    };
+    self.expect(']')?;
+
+    Ok(AttributeSelector {
+      namespace,
+      name,
+      operation: Some((operator, value)),
+      case,
+    })
+  }
+
+  /// Parses a quoted string, the opening quote already consumed.
+  fn parse_string(&mut self, quote: char) -> Result<String, SelectorError> {
+    let mut value = String::new();
+    loop {
+      match self.peek() {
+        None => return Err(self.error("Unterminated string")),
+        Some('\n') => return Err(self.error("Unexpected newline in string")),
+        Some(c) if c == quote => {
+          self.pos += 1;
+          return Ok(value);
+        }
+        Some('\\') => {
+          if self.peek_at(1) == Some('\n') {
+            self.pos += 2;
+          } else {
+            self.pos += 1;
+            value.push(self.parse_escape());
+          }
+        }
+        Some(c) => {
+          self.pos += 1;
+          value.push(c);
+        }
+      }
+    }
+  }
+
+  fn parse_ident(&mut self) -> Result<String, SelectorError> {
+    if !self.at_ident_start() {
+      return Err(self.error("Expected identifier"));
+    }
+    self.parse_name()
+  }
+
+  /// Parses a run of name code points and escapes.
+  fn parse_name(&mut self) -> Result<String, SelectorError> {
+    let mut name = String::new();
+    while let Some(c) = self.peek() {
+      if is_name_char(c) {
+        self.pos += 1;
+        name.push(c);
+      } else if c == '\\' && self.peek_at(1).is_some_and(|c| c != '\n') {
+        self.pos += 1;
+        name.push(self.parse_escape());
+      } else {
+        break;
+      }
+    }
+    if name.is_empty() {
+      return Err(self.error("Expected identifier"));
+    }
+    Ok(name)
+  }
+
+  /// Parses an escape sequence, the backslash already consumed.
+  fn parse_escape(&mut self) -> char {
+    let mut hex = String::new();
+    while hex.len() < 6 {
+      match self.peek() {
+        Some(c) if c.is_ascii_hexdigit() => {
+          self.pos += 1;
+          hex.push(c);
+        }
+        _ => break,
+      }
+    }
+    if hex.is_empty() {
+      return match self.peek() {
+        Some(c) => {
+          self.pos += 1;
+          c
+        }
+        None => char::REPLACEMENT_CHARACTER,
+      };
+    }
+    if matches!(self.peek(), Some(' ' | '\t' | '\n')) {
+      self.pos += 1;
+    }
+    match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+      Some('\0') | None => char::REPLACEMENT_CHARACTER,
+      Some(c) => c,
+    }
+  }
+
+  fn at_ident_start(&self) -> bool {
+    let is_start = |c: Option<char>, next: Option<char>| match c {
+      Some(c) if is_name_start_char(c) => true,
+      Some('\\') => next.is_some_and(|n| n != '\n'),
+      _ => false,
+    };
+    match self.peek() {
+      Some('-') => self.peek_at(1) == Some('-') || is_start(self.peek_at(1), self.peek_at(2)),
+      c => is_start(c, self.peek_at(1)),
+    }
+  }
+
+  fn expect(&mut self, expected: char) -> Result<(), SelectorError> {
+    if self.peek() == Some(expected) {
+      self.pos += 1;
+      Ok(())
+    } else {
+      Err(self.error(format!("Expected '{}'", expected)))
+    }
+  }
+
+  /// Skips whitespace and reports whether any was found.
+  fn skip_whitespace(&mut self) -> bool {
+    let start = self.pos;
+    while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r' | '\x0C')) {
+      self.pos += 1;
+    }
+    self.pos > start
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).copied()
+  }
+
+  fn peek_at(&self, offset: usize) -> Option<char> {
+    self.chars.get(self.pos + offset).copied()
+  }
+
+  fn error(&self, message: impl Into<String>) -> SelectorError {
+    self.error_at(self.pos, message)
+  }
+
+  fn error_at(&self, position: usize, message: impl Into<String>) -> SelectorError {
+    SelectorError {
+      message: message.into(),
+      position,
+    }
+  }
+}
+
+fn is_name_start_char(c: char) -> bool {
+  c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name_char(c: char) -> bool {
+  is_name_start_char(c) || c.is_ascii_digit() || c == '-'
+}
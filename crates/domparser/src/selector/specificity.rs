@@ -0,0 +1,101 @@
+use super::{ComplexSelector, CompoundSelector, PseudoClass, SelectorList, SimpleSelector};
+
+/// The specificity `(a, b, c)` of a selector: ids, then classes, attributes and
+/// pseudo-classes, then type selectors.
+///
+/// Specificities order the way the cascade compares them, so the greater one wins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Specificity {
+  pub a: u32,
+  pub b: u32,
+  pub c: u32,
+}
+
+impl std::ops::Add for Specificity {
+  type Output = Specificity;
+
+  fn add(self, other: Specificity) -> Specificity {
+    Specificity {
+      a: self.a + other.a,
+      b: self.b + other.b,
+      c: self.c + other.c,
+    }
+  }
+}
+
+impl From<Specificity> for (u32, u32, u32) {
+  fn from(specificity: Specificity) -> Self {
+    (specificity.a, specificity.b, specificity.c)
+  }
+}
+
+impl ComplexSelector {
+  pub(crate) fn specificity(&self) -> Specificity {
+    self
+      .compounds
+      .iter()
+      .map(CompoundSelector::specificity)
+      .fold(Specificity::default(), |total, next| total + next)
+  }
+}
+
+impl CompoundSelector {
+  fn specificity(&self) -> Specificity {
+    self
+      .0
+      .iter()
+      .map(SimpleSelector::specificity)
+      .fold(Specificity::default(), |total, next| total + next)
+  }
+}
+
+impl SimpleSelector {
+  fn specificity(&self) -> Specificity {
+    match self {
+      SimpleSelector::Universal(_) => Specificity::default(),
+      SimpleSelector::Type(..) => Specificity { a: 0, b: 0, c: 1 },
+      SimpleSelector::Id(_) => Specificity { a: 1, b: 0, c: 0 },
+      SimpleSelector::Class(_) | SimpleSelector::Attribute(_) => Specificity { a: 0, b: 1, c: 0 },
+      SimpleSelector::PseudoClass(pseudo) => pseudo.specificity(),
+    }
+  }
+}
+
+impl PseudoClass {
+  /// Pseudo-classes count as classes, except that `:is()`, `:not()` and `:has()` take
+  /// the most specific selector in their argument instead, and `:where()` counts nothing.
+  fn specificity(&self) -> Specificity {
+    let class = Specificity { a: 0, b: 1, c: 0 };
+    match self {
+      PseudoClass::Where(_) => Specificity::default(),
+      PseudoClass::Is(list) | PseudoClass::Not(list) => list.max_specificity(),
+      // The leading `:scope` compound is implied by the relative selector, not written.
+      PseudoClass::Has(list) => list
+        .0
+        .iter()
+        .map(|relative| {
+          relative.compounds[1..]
+            .iter()
+            .map(CompoundSelector::specificity)
+            .fold(Specificity::default(), |total, next| total + next)
+        })
+        .max()
+        .unwrap_or_default(),
+      PseudoClass::NthChild(_, Some(of)) | PseudoClass::NthLastChild(_, Some(of)) => {
+        class + of.max_specificity()
+      }
+      _ => class,
+    }
+  }
+}
+
+impl SelectorList {
+  fn max_specificity(&self) -> Specificity {
+    self
+      .0
+      .iter()
+      .map(ComplexSelector::specificity)
+      .max()
+      .unwrap_or_default()
+  }
+}
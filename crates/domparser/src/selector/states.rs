@@ -0,0 +1,481 @@
+//! Element states behind the form, link, language and direction pseudo-classes.
+//!
+//! There is no user interaction on a parsed tree, so every state is computed from
+//! attributes and ancestry alone: `checked` is the checkedness, and no link is visited.
+
+use html5ever::{namespace_url, ns};
+use markup5ever_rcdom::NodeData;
+
+use crate::node::descendants;
+use crate::DomNode;
+
+/// Returns the local name of an HTML element, or `None` for anything else.
+fn html_name(element: &DomNode) -> Option<&str> {
+  match &element.0.data {
+    NodeData::Element { name, .. } if name.ns == ns!(html) => Some(name.local.as_ref()),
+    _ => None,
+  }
+}
+
+/// Returns the value of the attribute `name` in no namespace.
+fn attribute(element: &DomNode, name: &str) -> Option<String> {
+  match &element.0.data {
+    NodeData::Element { attrs, .. } => attrs
+      .borrow()
+      .iter()
+      .find(|attr| attr.name.ns == ns!() && attr.name.local.as_ref() == name)
+      .map(|attr| attr.value.to_string()),
+    _ => None,
+  }
+}
+
+fn has_attribute(element: &DomNode, name: &str) -> bool {
+  attribute(element, name).is_some()
+}
+
+/// The state of an `input` element's `type` attribute, `text` when missing or invalid.
+fn input_type(element: &DomNode) -> String {
+  const TYPES: &[&str] = &[
+    "hidden",
+    "text",
+    "search",
+    "tel",
+    "url",
+    "email",
+    "password",
+    "date",
+    "month",
+    "week",
+    "time",
+    "datetime-local",
+    "number",
+    "range",
+    "color",
+    "checkbox",
+    "radio",
+    "file",
+    "submit",
+    "image",
+    "reset",
+    "button",
+  ];
+  attribute(element, "type")
+    .map(|value| value.to_ascii_lowercase())
+    .filter(|value| TYPES.contains(&value.as_str()))
+    .unwrap_or_else(|| "text".to_string())
+}
+
+fn ancestors(element: &DomNode) -> impl Iterator<Item = DomNode> {
+  std::iter::successors(element.parent_element(), DomNode::parent_element)
+}
+
+/// `:checked`: checked checkboxes and radio buttons, and selected options.
+pub(super) fn is_checked(element: &DomNode) -> bool {
+  match html_name(element) {
+    Some("input") => {
+      matches!(input_type(element).as_str(), "checkbox" | "radio")
+        && has_attribute(element, "checked")
+    }
+    Some("option") => is_selected_option(element),
+    _ => false,
+  }
+}
+
+/// Computes an option's selectedness as a `select` element does after parsing: a
+/// single-choice list keeps only its last `selected` option, and a drop-down box falls
+/// back to its first enabled option.
+fn is_selected_option(option: &DomNode) -> bool {
+  let Some(select) = option_select(option) else {
+    return has_attribute(option, "selected");
+  };
+  let multiple = has_attribute(&select, "multiple");
+  if multiple {
+    return has_attribute(option, "selected");
+  }
+
+  let options: Vec<DomNode> = select
+    .children()
+    .into_iter()
+    .flat_map(|child| match html_name(&child) {
+      Some("option") => vec![child],
+      Some("optgroup") => child
+        .children()
+        .into_iter()
+        .filter(|option| html_name(option) == Some("option"))
+        .collect(),
+      _ => Vec::new(),
+    })
+    .collect();
+  let selected = options
+    .iter()
+    .rev()
+    .find(|candidate| has_attribute(candidate, "selected"));
+  let display_size = attribute(&select, "size")
+    .and_then(|size| size.trim().parse::<u32>().ok())
+    .filter(|size| *size > 0)
+    .unwrap_or(1);
+  let selected = match selected {
+    Some(selected) => Some(selected),
+    None if display_size == 1 => options.iter().find(|candidate| !is_disabled(candidate)),
+    None => None,
+  };
+  selected.is_some_and(|selected| selected.is_same_node(option))
+}
+
+/// The `select` element an option belongs to, directly or through an `optgroup`.
+fn option_select(option: &DomNode) -> Option<DomNode> {
+  let parent = option.parent_element()?;
+  match html_name(&parent) {
+    Some("select") => Some(parent),
+    Some("optgroup") => parent
+      .parent_element()
+      .filter(|select| html_name(select) == Some("select")),
+    _ => None,
+  }
+}
+
+/// `:disabled`: form controls that are disabled directly, through an `optgroup`, or by
+/// an ancestor `fieldset` (outside that fieldset's first `legend`).
+pub(super) fn is_disabled(element: &DomNode) -> bool {
+  match html_name(element) {
+    Some("button" | "input" | "select" | "textarea" | "fieldset") => {
+      has_attribute(element, "disabled") || is_in_disabled_fieldset(element)
+    }
+    Some("optgroup") => has_attribute(element, "disabled"),
+    Some("option") => {
+      has_attribute(element, "disabled")
+        || element.parent_element().is_some_and(|parent| {
+          html_name(&parent) == Some("optgroup") && has_attribute(&parent, "disabled")
+        })
+    }
+    _ => false,
+  }
+}
+
+fn is_in_disabled_fieldset(element: &DomNode) -> bool {
+  let mut child = element.clone();
+  for ancestor in ancestors(element) {
+    if html_name(&ancestor) == Some("fieldset") && has_attribute(&ancestor, "disabled") {
+      let first_legend = ancestor
+        .children()
+        .into_iter()
+        .find(|candidate| html_name(candidate) == Some("legend"));
+      if !first_legend.is_some_and(|legend| legend.is_same_node(&child)) {
+        return true;
+      }
+    }
+    child = ancestor;
+  }
+  false
+}
+
+/// `:enabled`: elements that can be disabled and are not.
+pub(super) fn is_enabled(element: &DomNode) -> bool {
+  matches!(
+    html_name(element),
+    Some("button" | "input" | "select" | "textarea" | "optgroup" | "option" | "fieldset")
+  ) && !is_disabled(element)
+}
+
+/// Whether the element is an `input`, `select` or `textarea` that `required` applies to.
+fn can_be_required(element: &DomNode) -> bool {
+  match html_name(element) {
+    Some("input") => !matches!(
+      input_type(element).as_str(),
+      "hidden" | "range" | "color" | "submit" | "image" | "reset" | "button"
+    ),
+    Some("select" | "textarea") => true,
+    _ => false,
+  }
+}
+
+pub(super) fn is_required(element: &DomNode) -> bool {
+  can_be_required(element) && has_attribute(element, "required")
+}
+
+/// `:optional`: any `input`, `select` or `textarea` that is not `:required`.
+pub(super) fn is_optional(element: &DomNode) -> bool {
+  matches!(html_name(element), Some("input" | "select" | "textarea")) && !is_required(element)
+}
+
+/// `:read-write`: mutable text controls and editable content. Everything else is
+/// `:read-only`.
+pub(super) fn is_read_write(element: &DomNode) -> bool {
+  match html_name(element) {
+    Some("input") => {
+      matches!(
+        input_type(element).as_str(),
+        "text"
+          | "search"
+          | "url"
+          | "tel"
+          | "email"
+          | "password"
+          | "date"
+          | "month"
+          | "week"
+          | "time"
+          | "datetime-local"
+          | "number"
+      ) && !has_attribute(element, "readonly")
+        && !is_disabled(element)
+    }
+    Some("textarea") => !has_attribute(element, "readonly") && !is_disabled(element),
+    _ => is_editable(element),
+  }
+}
+
+/// Follows `contenteditable` up the tree, where invalid values inherit.
+fn is_editable(element: &DomNode) -> bool {
+  for candidate in std::iter::once(element.clone()).chain(ancestors(element)) {
+    if html_name(&candidate).is_none() {
+      continue;
+    }
+    match attribute(&candidate, "contenteditable")
+      .map(|value| value.to_ascii_lowercase())
+      .as_deref()
+    {
+      Some("" | "true" | "plaintext-only") => return true,
+      Some("false") => return false,
+      _ => {}
+    }
+  }
+  false
+}
+
+/// `:default`: initially checked checkboxes and radio buttons, initially selected
+/// options, and the default button of each form.
+pub(super) fn is_default(element: &DomNode) -> bool {
+  match html_name(element) {
+    Some("option") => has_attribute(element, "selected"),
+    Some("input") => match input_type(element).as_str() {
+      "checkbox" | "radio" => has_attribute(element, "checked"),
+      "submit" | "image" => is_default_button(element),
+      _ => false,
+    },
+    Some("button") => is_submit_button(element) && is_default_button(element),
+    _ => false,
+  }
+}
+
+fn is_submit_button(element: &DomNode) -> bool {
+  match html_name(element) {
+    Some("button") => !matches!(
+      attribute(element, "type")
+        .map(|value| value.to_ascii_lowercase())
+        .as_deref(),
+      Some("reset" | "button")
+    ),
+    Some("input") => matches!(input_type(element).as_str(), "submit" | "image"),
+    _ => false,
+  }
+}
+
+/// Whether `button` is the first submit button in tree order owned by its form.
+fn is_default_button(button: &DomNode) -> bool {
+  let Some(form) = form_owner(button) else {
+    return false;
+  };
+  // Controls outside the form can only belong to it through a `form` attribute
+  // naming its id, which it only answers to as the first element with that id.
+  let id = attribute(&form, "id").filter(|id| {
+    let root = form.get_root_node();
+    root
+      .get_element_by_id(id.clone())
+      .is_some_and(|node| node.is_same_node(&form))
+  });
+  let owned = |node: &DomNode| match attribute(node, "form") {
+    Some(name) => id.as_ref() == Some(&name),
+    None => ancestors(node)
+      .find(|ancestor| html_name(ancestor) == Some("form"))
+      .is_some_and(|owner| owner.is_same_node(&form)),
+  };
+  let root = match id {
+    Some(_) => button.get_root_node(),
+    None => form.clone(),
+  };
+  descendants(&root.0)
+    .map(DomNode)
+    .find(|node| is_submit_button(node) && owned(node))
+    .is_some_and(|node| node.is_same_node(button))
+}
+
+/// The `form` named by the `form` attribute, or else the nearest ancestor `form`.
+fn form_owner(element: &DomNode) -> Option<DomNode> {
+  match attribute(element, "form") {
+    Some(id) => element
+      .get_root_node()
+      .get_element_by_id(id)
+      .filter(|node| html_name(node) == Some("form")),
+    None => ancestors(element).find(|ancestor| html_name(ancestor) == Some("form")),
+  }
+}
+
+/// `:any-link` and `:link`: `a` and `area` elements with an `href`.
+pub(super) fn is_link(element: &DomNode) -> bool {
+  matches!(html_name(element), Some("a" | "area")) && has_attribute(element, "href")
+}
+
+/// `:defined`: everything except HTML elements that would need a custom element
+/// definition, which a parsed tree never has.
+pub(super) fn is_defined(element: &DomNode) -> bool {
+  let Some(name) = html_name(element) else {
+    return true;
+  };
+  !is_custom_element_name(name) && !has_attribute(element, "is")
+}
+
+fn is_custom_element_name(name: &str) -> bool {
+  name.starts_with(|c: char| c.is_ascii_lowercase())
+    && name.contains('-')
+    && !matches!(
+      name,
+      "annotation-xml"
+        | "color-profile"
+        | "font-face"
+        | "font-face-src"
+        | "font-face-uri"
+        | "font-face-format"
+        | "font-face-name"
+        | "missing-glyph"
+    )
+}
+
+/// `:lang()`: whether the element's language matches any of the lowercased ranges.
+pub(super) fn matches_lang(element: &DomNode, ranges: &[String]) -> bool {
+  let Some(language) = language(element) else {
+    return false;
+  };
+  let language = language.to_ascii_lowercase();
+  ranges
+    .iter()
+    .any(|range| matches_language_range(range, &language))
+}
+
+/// The language from the nearest `xml:lang` or `lang` attribute, `xml:lang` winning on
+/// the same element.
+fn language(element: &DomNode) -> Option<String> {
+  std::iter::once(element.clone())
+    .chain(ancestors(element))
+    .find_map(|candidate| match &candidate.0.data {
+      NodeData::Element { attrs, .. } => {
+        let attrs = attrs.borrow();
+        let find = |ns| {
+          attrs
+            .iter()
+            .find(|attr| attr.name.ns == ns && attr.name.local.as_ref() == "lang")
+            .map(|attr| attr.value.to_string())
+        };
+        find(ns!(xml)).or_else(|| find(ns!()))
+      }
+      _ => None,
+    })
+}
+
+/// RFC 4647 extended filtering, as used by `:lang()`: `de` and `de-*-DE` both match
+/// `de-Latn-DE`, and `*-CH` matches any language tag with a `CH` subtag.
+fn matches_language_range(range: &str, tag: &str) -> bool {
+  if range.is_empty() || tag.is_empty() {
+    return range == tag;
+  }
+  let range: Vec<&str> = range.split('-').collect();
+  let tag: Vec<&str> = tag.split('-').collect();
+  if range[0] != "*" && range[0] != tag[0] {
+    return false;
+  }
+  let (mut r, mut t) = (1, 1);
+  while r < range.len() {
+    if range[r] == "*" {
+      r += 1;
+    } else if t >= tag.len() {
+      return false;
+    } else if range[r] == tag[t] {
+      r += 1;
+      t += 1;
+    } else if tag[t].len() == 1 {
+      return false;
+    } else {
+      t += 1;
+    }
+  }
+  true
+}
+
+/// `:dir()`: whether the element's directionality is `direction`.
+pub(super) fn matches_dir(element: &DomNode, direction: &str) -> bool {
+  directionality(element) == direction
+}
+
+/// Resolves the directionality of an element from `dir` attributes, `bdi` elements and
+/// `dir=auto` text, defaulting to `ltr` at the root.
+fn directionality(element: &DomNode) -> &'static str {
+  for candidate in std::iter::once(element.clone()).chain(ancestors(element)) {
+    let Some(name) = html_name(&candidate) else {
+      continue;
+    };
+    match attribute(&candidate, "dir")
+      .map(|value| value.to_ascii_lowercase())
+      .as_deref()
+    {
+      Some("ltr") => return "ltr",
+      Some("rtl") => return "rtl",
+      Some("auto") => return auto_directionality(&candidate),
+      _ if name == "bdi" => return auto_directionality(&candidate),
+      _ if name == "input" && input_type(&candidate) == "tel" => return "ltr",
+      _ => {}
+    }
+  }
+  "ltr"
+}
+
+/// The direction of the first strong character in the element's text, skipping
+/// descendants that set their own direction, or `ltr` if there is none.
+fn auto_directionality(element: &DomNode) -> &'static str {
+  let text = match html_name(element) {
+    Some("input") => attribute(element, "value").unwrap_or_default(),
+    Some("textarea") => element.text_content_getter(),
+    _ => {
+      let mut text = String::new();
+      let mut stack: Vec<DomNode> = element.child_nodes().into_iter().rev().collect();
+      while let Some(node) = stack.pop() {
+        match &node.0.data {
+          NodeData::Text { contents } => text.push_str(&contents.borrow()),
+          NodeData::Element { .. } => {
+            let skip = matches!(
+              html_name(&node),
+              Some("bdi" | "script" | "style" | "textarea")
+            ) || matches!(
+              attribute(&node, "dir")
+                .map(|value| value.to_ascii_lowercase())
+                .as_deref(),
+              Some("ltr" | "rtl" | "auto")
+            );
+            if !skip {
+              stack.extend(node.child_nodes().into_iter().rev());
+            }
+          }
+          _ => {}
+        }
+      }
+      text
+    }
+  };
+  text
+    .chars()
+    .find(|c| c.is_alphabetic())
+    .map(|c| if is_rtl_script(c) { "rtl" } else { "ltr" })
+    .unwrap_or("ltr")
+}
+
+/// Approximates the bidi classes R and AL, for letters, by the blocks of right-to-left
+/// scripts.
+fn is_rtl_script(c: char) -> bool {
+  matches!(
+    c,
+    '\u{0590}'..='\u{08FF}'
+      | '\u{FB1D}'..='\u{FDFF}'
+      | '\u{FE70}'..='\u{FEFF}'
+      | '\u{10800}'..='\u{10FFF}'
+      | '\u{1E800}'..='\u{1EFFF}'
+  )
+}
@@ -1,18 +1,1213 @@
+use html5ever::{local_name, namespace_url, ns, LocalName, Namespace, QualName};
+use markup5ever::data::NAMED_ENTITIES;
 use markup5ever_rcdom::{Handle, NodeData};
-use std::io::{Result, Write};
+use std::collections::HashMap;
+use std::io::{self, Result, Write};
+use std::sync::OnceLock;
+
+use crate::node::get_parent;
+
+/// Options for writing a tree as HTML. The defaults write the tree exactly as it is.
+///
+/// ```
+/// use domparser::{parse, SerializeOptions};
+///
+/// let root = parse("<ul>\n  <li>One</li>\n  <li>Two</li>\n</ul>".to_string());
+/// let list = root.select("ul".to_string()).unwrap().unwrap();
+/// let options = SerializeOptions {
+///   collapse_whitespace: true,
+///   omit_optional_tags: true,
+///   ..Default::default()
+/// };
+/// assert_eq!(list.outer_html_with_options(&options), "<ul><li>One<li>Two</ul>");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SerializeOptions {
+  /// Write `<noscript>` content as raw text, as a document parsed with scripting
+  /// enabled holds it.
+  pub scripting_enabled: bool,
+  /// Put each child of an element holding only block-level elements on a line of its
+  /// own, indented by this many spaces per level. Elements with text or inline
+  /// children are written on one line.
+  pub indent: Option<usize>,
+  /// Collapse runs of whitespace in text to a single space, and drop text that is only
+  /// whitespace between block-level elements. Whitespace inside `<pre>`, `<textarea>`
+  /// and raw text elements is kept.
+  pub collapse_whitespace: bool,
+  /// Leave out end tags the parser would imply, such as `</li>` before another `<li>`
+  /// or `</p>` at the end of its parent.
+  pub omit_optional_tags: bool,
+  /// Leave out comments.
+  pub remove_comments: bool,
+  /// Write attribute values without quotes when nothing in them needs quoting.
+  pub unquoted_attributes: bool,
+  /// Write empty attributes, and boolean attributes such as `disabled="disabled"`, by
+  /// name alone.
+  pub shorten_boolean_attributes: bool,
+  /// How characters in text and attribute values are written as character references.
+  pub character_references: CharacterReferences,
+}
+
+/// Which characters are written as character references. Text in `<script>`, `<style>`
+/// and other raw text elements, and comments, are always written as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CharacterReferences {
+  /// Only what HTML needs: `&`, U+00A0 as `&nbsp;`, `"` in attribute values, and `<`
+  /// and `>` in text.
+  #[default]
+  Minimal,
+  /// Also every character outside ASCII, as a numeric reference such as `&#233;`, for
+  /// output that must be 7-bit clean.
+  Ascii,
+  /// Also characters outside ASCII that have a named reference, such as `&eacute;`.
+  /// Others are written as they are.
+  Named,
+}
+
+impl SerializeOptions {
+  fn reformats(&self) -> bool {
+    self.indent.is_some() || self.collapse_whitespace
+  }
+}
+
+/// Writes `node` as HTML, or only its children unless `include_node` is set. A
+/// document is always written as its children.
+pub fn serialize_html<Wr: Write>(
+  writer: &mut Wr,
+  node: &Handle,
+  include_node: bool,
+  options: &SerializeOptions,
+) -> Result<()> {
+  HtmlWriter {
+    writer,
+    options,
+    preserved: 0,
+    escape: None,
+  }
+  .write(node, include_node)
+}
+
+/// Writes `node` as HTML like `serialize_html`, but with text and attribute values
+/// escaped by `escape` rather than as `options.character_references` asks. It is
+/// called with each text or attribute value, and whether it is an attribute value.
+pub fn serialize_html_with_escape<Wr: Write>(
+  writer: &mut Wr,
+  node: &Handle,
+  include_node: bool,
+  options: &SerializeOptions,
+  escape: &mut dyn FnMut(&str, bool) -> String,
+) -> Result<()> {
+  HtmlWriter {
+    writer,
+    options,
+    preserved: 0,
+    escape: Some(escape),
+  }
+  .write(node, include_node)
+}
+
+/// Writes `node` as well-formed XML, or only its children unless `include_node` is
+/// set. Elements and attributes are written with the namespace declarations they need,
+/// void HTML elements as `<br />` and other empty elements as `<g/>`. A document is
+/// always written as its children.
+///
+/// ```
+/// use domparser::parse;
+///
+/// let root = parse("<p>a &amp; b<br><svg><circle r=1 /></svg>".to_string());
+/// let p = root.select("p".to_string()).unwrap().unwrap();
+/// assert_eq!(
+///   p.outer_xml(),
+///   "<p xmlns=\"http://www.w3.org/1999/xhtml\">a &amp; b<br />\
+///    <svg xmlns=\"http://www.w3.org/2000/svg\"><circle r=\"1\"/></svg></p>",
+/// );
+/// ```
+pub fn serialize_xml<Wr: Write>(writer: &mut Wr, node: &Handle, include_node: bool) -> Result<()> {
+  XmlWriter {
+    writer,
+    scope: Vec::new(),
+    generated_prefixes: 0,
+  }
+  .write(node, include_node)
+}
 
 pub fn serialize_text_only<Wr: Write>(handle: &Handle, writer: &mut Wr) -> Result<()> {
-  match &handle.data {
-    NodeData::Text { contents } => {
-      writer.write_all(contents.borrow().as_bytes())?;
-      Ok(())
+  let mut stack = vec![handle.clone()];
+  while let Some(node) = stack.pop() {
+    match &node.data {
+      NodeData::Text { contents } => writer.write_all(contents.borrow().as_bytes())?,
+      NodeData::Element { .. } | NodeData::Document => {
+        stack.extend(node.children.borrow().iter().rev().cloned())
+      }
+      _ => {}
+    }
+  }
+  Ok(())
+}
+
+enum Step {
+  Open(Child),
+  /// The end tag of an element, with the depth of its start tag when its children
+  /// are on lines of their own.
+  Close {
+    name: QualName,
+    preserves: bool,
+    broken_at: Option<usize>,
+    omit: bool,
+  },
+}
+
+/// A node to write, with how it is laid out among its siblings.
+struct Child {
+  node: Handle,
+  depth: usize,
+  on_new_line: bool,
+  omit_end_tag: bool,
+}
+
+struct HtmlWriter<'a, Wr: Write> {
+  writer: &'a mut Wr,
+  options: &'a SerializeOptions,
+  /// How many of the open elements keep their whitespace.
+  preserved: usize,
+  escape: Option<&'a mut Escape<'a>>,
+}
+
+/// Escapes a text or attribute value, told whether it is an attribute value.
+pub type Escape<'a> = dyn FnMut(&str, bool) -> String + 'a;
+
+/// The HTML serialization of a node, written a part at a time. It stops between nodes
+/// once a chunk is full and picks up there on the next call, so that the output can be
+/// passed on at the pace it is consumed.
+///
+/// Nodes still to be written are held until then. Changes made to the tree in between
+/// may or may not show in the rest of the output.
+///
+/// ```
+/// use domparser::parse;
+/// use domparser::serializer::HtmlSerializer;
+/// use domparser::SerializeOptions;
+///
+/// let root = parse("<p>one</p><p>two</p>".to_string());
+/// let body = root.select("body".to_string()).unwrap().unwrap();
+/// let mut serializer = HtmlSerializer::new(&body.0, false, SerializeOptions::default());
+/// let mut chunks = Vec::new();
+/// loop {
+///   let mut chunk = Vec::new();
+///   let more = serializer.write_chunk(&mut chunk, 8, None);
+///   chunks.push(String::from_utf8(chunk).unwrap());
+///   if !more {
+///     break;
+///   }
+/// }
+/// assert_eq!(chunks, ["<p>one</p>", "<p>two</p>"]);
+/// ```
+pub struct HtmlSerializer {
+  steps: Vec<Step>,
+  preserved: usize,
+  options: SerializeOptions,
+}
+
+impl HtmlSerializer {
+  /// Prepares to write `node` as HTML, or only its children unless `include_node` is
+  /// set, like `serialize_html`.
+  pub fn new(node: &Handle, include_node: bool, options: SerializeOptions) -> Self {
+    let mut writer = HtmlWriter {
+      writer: &mut io::sink(),
+      options: &options,
+      preserved: 0,
+      escape: None,
+    };
+    let steps = writer.start(node, include_node);
+    let preserved = writer.preserved;
+    HtmlSerializer {
+      steps,
+      preserved,
+      options,
+    }
+  }
+
+  /// Appends the next nodes to `chunk` until it holds at least `size` bytes, escaping
+  /// text and attribute values with `escape` if given, like
+  /// `serialize_html_with_escape`. Returns whether there is more to write.
+  pub fn write_chunk<'a>(
+    &'a mut self,
+    chunk: &'a mut Vec<u8>,
+    size: usize,
+    escape: Option<&'a mut Escape<'a>>,
+  ) -> bool {
+    let mut writer = HtmlWriter {
+      writer: chunk,
+      options: &self.options,
+      preserved: self.preserved,
+      escape,
+    };
+    while writer.writer.len() < size {
+      let Some(step) = self.steps.pop() else {
+        break;
+      };
+      writer
+        .step(&mut self.steps, step)
+        .expect("writing to a vector cannot fail");
+    }
+    self.preserved = writer.preserved;
+    !self.steps.is_empty()
+  }
+}
+
+impl<Wr: Write> HtmlWriter<'_, Wr> {
+  fn write(mut self, root: &Handle, include_node: bool) -> Result<()> {
+    let mut steps = self.start(root, include_node);
+    while let Some(step) = steps.pop() {
+      self.step(&mut steps, step)?;
+    }
+    Ok(())
+  }
+
+  /// Counts the ancestors of `root` that keep their whitespace, and returns the first
+  /// steps.
+  fn start(&mut self, root: &Handle, include_node: bool) -> Vec<Step> {
+    let mut ancestor = if include_node {
+      get_parent(root)
+    } else {
+      Some(root.clone())
+    };
+    while let Some(node) = ancestor {
+      self.preserved += preserves_whitespace(&node) as usize;
+      ancestor = get_parent(&node);
+    }
+
+    let mut steps = Vec::new();
+    if include_node && !matches!(root.data, NodeData::Document) {
+      steps.push(Step::Open(Child {
+        node: root.clone(),
+        depth: 0,
+        on_new_line: false,
+        omit_end_tag: false,
+      }));
+    } else {
+      let broken = self.options.indent.is_some() && self.has_block_layout(root);
+      self.push_children(&mut steps, root, 0, broken);
+      // The first child starts where the output does.
+      if let Some(Step::Open(first)) = steps.last_mut() {
+        first.on_new_line = false;
+      }
+    }
+    steps
+  }
+
+  fn step(&mut self, steps: &mut Vec<Step>, step: Step) -> Result<()> {
+    match step {
+      Step::Open(child) => self.open(steps, child)?,
+      Step::Close {
+        name,
+        preserves,
+        broken_at,
+        omit,
+      } => {
+        self.preserved -= preserves as usize;
+        if let Some(depth) = broken_at {
+          self.new_line(depth)?;
+        }
+        if !omit {
+          self.writer.write_all(b"</")?;
+          self.writer.write_all(name.local.as_bytes())?;
+          self.writer.write_all(b">")?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn open(&mut self, steps: &mut Vec<Step>, child: Child) -> Result<()> {
+    if child.on_new_line {
+      self.new_line(child.depth)?;
+    }
+    let node = &child.node;
+    match &node.data {
+      NodeData::Element { name, attrs, .. } => {
+        self.start_tag(name, &attrs.borrow())?;
+        if is_void(name) {
+          return Ok(());
+        }
+        let preserves = preserves_whitespace(node);
+        self.preserved += preserves as usize;
+        let broken = self.options.indent.is_some() && self.has_block_layout(node);
+        let mark = steps.len();
+        steps.push(Step::Close {
+          name: name.clone(),
+          preserves,
+          broken_at: None,
+          omit: child.omit_end_tag,
+        });
+        // Children that stay on the line of the start tag keep its depth.
+        let depth = child.depth + broken as usize;
+        self.push_children(steps, node, depth, broken);
+        // The end tag goes on a line of its own only below children that do.
+        if broken && steps.len() > mark + 1 {
+          if let Step::Close { broken_at, .. } = &mut steps[mark] {
+            *broken_at = Some(child.depth);
+          }
+        }
+      }
+      NodeData::Text { contents } => self.text(node, &contents.borrow())?,
+      NodeData::Comment { contents } => {
+        self.writer.write_all(b"<!--")?;
+        self.writer.write_all(contents.as_bytes())?;
+        self.writer.write_all(b"-->")?;
+      }
+      NodeData::Doctype { name, .. } => {
+        self.writer.write_all(b"<!DOCTYPE ")?;
+        self.writer.write_all(name.as_bytes())?;
+        self.writer.write_all(b">")?;
+      }
+      NodeData::ProcessingInstruction { target, contents } => {
+        self.writer.write_all(b"<?")?;
+        self.writer.write_all(target.as_bytes())?;
+        self.writer.write_all(b" ")?;
+        self.writer.write_all(contents.as_bytes())?;
+        self.writer.write_all(b">")?;
+      }
+      NodeData::Document => {}
+    }
+    Ok(())
+  }
+
+  /// Queues the children of `parent` that are written, the first on top. With
+  /// `broken` set, each goes on a line of its own at `depth`.
+  fn push_children(&self, steps: &mut Vec<Step>, parent: &Handle, depth: usize, broken: bool) {
+    let block = self.options.reformats() && self.has_block_layout(parent);
+    let children: Vec<Handle> = parent
+      .children
+      .borrow()
+      .iter()
+      .filter(|child| !self.is_dropped(child, block))
+      .cloned()
+      .collect();
+    for (index, node) in children.iter().enumerate().rev() {
+      let omit_end_tag =
+        self.options.omit_optional_tags && can_omit_end_tag(node, children.get(index + 1), parent);
+      steps.push(Step::Open(Child {
+        node: node.clone(),
+        depth,
+        on_new_line: broken,
+        omit_end_tag,
+      }));
+    }
+  }
+
+  /// Whether `node` is left out: a comment when comments are removed, or whitespace
+  /// between blocks when whitespace is reformatted.
+  fn is_dropped(&self, node: &Handle, parent_is_block: bool) -> bool {
+    match &node.data {
+      NodeData::Comment { .. } => self.options.remove_comments,
+      NodeData::Text { contents } => parent_is_block && is_whitespace(&contents.borrow()),
+      _ => false,
+    }
+  }
+
+  /// Whether the children of `node` are block-level elements, with nothing but
+  /// whitespace, comments and the like between them, so that the whitespace can be
+  /// changed without changing how the document renders.
+  fn has_block_layout(&self, node: &Handle) -> bool {
+    if self.preserved > 0 || preserves_whitespace(node) {
+      return false;
+    }
+    let children = node.children.borrow();
+    children
+      .iter()
+      .any(|child| matches!(child.data, NodeData::Element { .. }))
+      && children.iter().all(|child| match &child.data {
+        NodeData::Element { name, .. } => is_block(name),
+        NodeData::Text { contents } => is_whitespace(&contents.borrow()),
+        _ => true,
+      })
+  }
+
+  fn new_line(&mut self, depth: usize) -> Result<()> {
+    const SPACES: &[u8] = &[b' '; 64];
+    let mut indent = self.options.indent.unwrap_or_default() * depth;
+    self.writer.write_all(b"\n")?;
+    while indent > 0 {
+      let chunk = indent.min(SPACES.len());
+      self.writer.write_all(&SPACES[..chunk])?;
+      indent -= chunk;
+    }
+    Ok(())
+  }
+
+  fn start_tag(&mut self, name: &QualName, attrs: &[html5ever::Attribute]) -> Result<()> {
+    self.writer.write_all(b"<")?;
+    self.writer.write_all(name.local.as_bytes())?;
+    for attr in attrs {
+      self.writer.write_all(b" ")?;
+      match attr.name.ns {
+        ns!() => {}
+        ns!(xml) => self.writer.write_all(b"xml:")?,
+        ns!(xmlns) if attr.name.local != local_name!("xmlns") => {
+          self.writer.write_all(b"xmlns:")?
+        }
+        ns!(xmlns) => {}
+        ns!(xlink) => self.writer.write_all(b"xlink:")?,
+        _ => self.writer.write_all(b"unknown_namespace:")?,
+      }
+      self.writer.write_all(attr.name.local.as_bytes())?;
+      let value: &str = &attr.value;
+      if self.options.shorten_boolean_attributes
+        && (value.is_empty() || is_boolean_attribute(name, &attr.name.local, value))
+      {
+        continue;
+      }
+      if self.options.unquoted_attributes {
+        // The escaped value is what is written, and a custom escape may add quotes.
+        let escaped = self.escaped_string(value, true);
+        if can_be_unquoted(&escaped) {
+          write!(self.writer, "={}", escaped)?;
+        } else {
+          write!(self.writer, "=\"{}\"", escaped)?;
+        }
+      } else {
+        self.writer.write_all(b"=\"")?;
+        self.escaped(value, true)?;
+        self.writer.write_all(b"\"")?;
+      }
+    }
+    self.writer.write_all(b">")
+  }
+
+  fn text(&mut self, node: &Handle, text: &str) -> Result<()> {
+    let parent = get_parent(node);
+    let raw = parent
+      .as_ref()
+      .is_some_and(|parent| self.is_raw_text(parent));
+    let collapsed;
+    let text = if self.options.collapse_whitespace && self.preserved == 0 {
+      collapsed = collapse_whitespace(text);
+      &collapsed
+    } else {
+      text
+    };
+    if raw {
+      self.writer.write_all(text.as_bytes())
+    } else {
+      self.escaped(text, false)
+    }
+  }
+
+  /// Whether the text in `element` is written without escaping.
+  fn is_raw_text(&self, element: &Handle) -> bool {
+    let NodeData::Element { name, .. } = &element.data else {
+      return false;
+    };
+    if name.ns != ns!(html) {
+      return false;
+    }
+    match name.local {
+      local_name!("style")
+      | local_name!("script")
+      | local_name!("xmp")
+      | local_name!("iframe")
+      | local_name!("noembed")
+      | local_name!("noframes")
+      | local_name!("plaintext") => true,
+      local_name!("noscript") => self.options.scripting_enabled,
+      _ => false,
+    }
+  }
+
+  fn escaped(&mut self, text: &str, attr_mode: bool) -> Result<()> {
+    match &mut self.escape {
+      Some(escape) => self.writer.write_all(escape(text, attr_mode).as_bytes()),
+      None => escape_into(
+        self.writer,
+        text,
+        attr_mode,
+        self.options.character_references,
+      ),
+    }
+  }
+
+  fn escaped_string(&mut self, text: &str, attr_mode: bool) -> String {
+    if let Some(escape) = &mut self.escape {
+      return escape(text, attr_mode);
+    }
+    let mut escaped = Vec::with_capacity(text.len());
+    escape_into(
+      &mut escaped,
+      text,
+      attr_mode,
+      self.options.character_references,
+    )
+    .expect("writing to a vector cannot fail");
+    String::from_utf8(escaped).expect("escaping only splits text between characters")
+  }
+}
+
+/// Escapes text, or an attribute value in `attr_mode`, as the HTML fragment
+/// serialization algorithm does, with the given character references for other
+/// characters.
+fn escape_into<W: Write + ?Sized>(
+  writer: &mut W,
+  text: &str,
+  attr_mode: bool,
+  references: CharacterReferences,
+) -> Result<()> {
+  let mut written = 0;
+  for (index, c) in text.char_indices() {
+    let escaped: &[u8] = match c {
+      '&' => b"&amp;",
+      '\u{00A0}' => b"&nbsp;",
+      '"' if attr_mode => b"&quot;",
+      '<' if !attr_mode => b"&lt;",
+      '>' if !attr_mode => b"&gt;",
+      _ if c.is_ascii() => continue,
+      _ => {
+        let name = match references {
+          CharacterReferences::Minimal => continue,
+          CharacterReferences::Ascii => None,
+          CharacterReferences::Named => match named_reference(c) {
+            Some(name) => Some(name),
+            None => continue,
+          },
+        };
+        writer.write_all(&text.as_bytes()[written..index])?;
+        match name {
+          Some(name) => write!(writer, "&{}", name)?,
+          None => write!(writer, "&#{};", c as u32)?,
+        }
+        written = index + c.len_utf8();
+        continue;
+      }
+    };
+    writer.write_all(&text.as_bytes()[written..index])?;
+    writer.write_all(escaped)?;
+    written = index + c.len_utf8();
+  }
+  writer.write_all(&text.as_bytes()[written..])
+}
+
+enum XmlStep {
+  Open(Handle),
+  /// The end tag of an element, with how many namespace bindings were in scope before
+  /// its start tag.
+  Close {
+    name: String,
+    scope: usize,
+  },
+}
+
+/// A namespace bound to a prefix, or made the default namespace when there is none.
+struct Binding {
+  prefix: Option<String>,
+  namespace: Namespace,
+}
+
+struct XmlWriter<'a, Wr: Write> {
+  writer: &'a mut Wr,
+  /// The namespace bindings of the open elements, innermost last.
+  scope: Vec<Binding>,
+  generated_prefixes: usize,
+}
+
+impl<Wr: Write> XmlWriter<'_, Wr> {
+  fn write(mut self, root: &Handle, include_node: bool) -> Result<()> {
+    let mut steps = Vec::new();
+    if include_node && !matches!(root.data, NodeData::Document) {
+      steps.push(XmlStep::Open(root.clone()));
+    } else {
+      push_xml_children(&mut steps, root);
+    }
+
+    while let Some(step) = steps.pop() {
+      match step {
+        XmlStep::Open(node) => self.open(&mut steps, &node)?,
+        XmlStep::Close { name, scope } => {
+          self.scope.truncate(scope);
+          self.writer.write_all(b"</")?;
+          self.writer.write_all(name.as_bytes())?;
+          self.writer.write_all(b">")?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn open(&mut self, steps: &mut Vec<XmlStep>, node: &Handle) -> Result<()> {
+    match &node.data {
+      NodeData::Element { name, attrs, .. } => self.element(steps, node, name, &attrs.borrow())?,
+      NodeData::Text { contents } => self.text(node, &contents.borrow())?,
+      NodeData::Comment { contents } => {
+        // A comment may not hold `--` or end with `-`.
+        let mut contents = contents.to_string();
+        while contents.contains("--") {
+          contents = contents.replace("--", "- -");
+        }
+        if contents.ends_with('-') {
+          contents.push(' ');
+        }
+        self.writer.write_all(b"<!--")?;
+        self.writer.write_all(contents.as_bytes())?;
+        self.writer.write_all(b"-->")?;
+      }
+      NodeData::Doctype {
+        name,
+        public_id,
+        system_id,
+      } => {
+        self.writer.write_all(b"<!DOCTYPE ")?;
+        self.writer.write_all(name.as_bytes())?;
+        if !public_id.is_empty() {
+          write!(self.writer, " PUBLIC \"{}\"", public_id)?;
+          if !system_id.is_empty() {
+            write!(self.writer, " \"{}\"", system_id)?;
+          }
+        } else if !system_id.is_empty() {
+          write!(self.writer, " SYSTEM \"{}\"", system_id)?;
+        }
+        self.writer.write_all(b">")?;
+      }
+      NodeData::ProcessingInstruction { target, contents } => {
+        self.writer.write_all(b"<?")?;
+        self.writer.write_all(target.as_bytes())?;
+        if !contents.is_empty() {
+          self.writer.write_all(b" ")?;
+          self.writer.write_all(contents.as_bytes())?;
+        }
+        self.writer.write_all(b"?>")?;
+      }
+      NodeData::Document => {}
+    }
+    Ok(())
+  }
+
+  fn element(
+    &mut self,
+    steps: &mut Vec<XmlStep>,
+    node: &Handle,
+    name: &QualName,
+    attrs: &[html5ever::Attribute],
+  ) -> Result<()> {
+    let scope = self.scope.len();
+    // Declarations among the attributes are in scope for the element's own name.
+    let mut declares_default = false;
+    for attr in attrs {
+      if let Some(prefix) = declared_prefix(attr) {
+        declares_default |= prefix.is_none();
+        self.scope.push(Binding {
+          prefix: prefix.map(str::to_string),
+          namespace: Namespace::from(&*attr.value),
+        });
+      }
+    }
+
+    let mut declarations = Vec::new();
+    let qualified = match &name.prefix {
+      Some(prefix) if name.ns != ns!() => {
+        if self.lookup(Some(prefix)) != Some(&name.ns) {
+          self.declare(&mut declarations, Some(prefix.to_string()), &name.ns);
+        }
+        format!("{}:{}", prefix, name.local)
+      }
+      _ => {
+        if self.lookup(None).unwrap_or(&ns!()) != &name.ns {
+          self.declare(&mut declarations, None, &name.ns);
+        } else {
+          declares_default = false;
+        }
+        name.local.to_string()
+      }
+    };
+
+    let mut written = Vec::with_capacity(attrs.len());
+    for attr in attrs {
+      let attr_name = match attr.name.ns {
+        // The element's namespace replaces a default namespace declared for another.
+        _ if declares_default && declared_prefix(attr) == Some(None) => continue,
+        ns!() => attr.name.local.to_string(),
+        ns!(xml) => format!("xml:{}", attr.name.local),
+        ns!(xmlns) if attr.name.local == local_name!("xmlns") => "xmlns".to_string(),
+        ns!(xmlns) => format!("xmlns:{}", attr.name.local),
+        ref namespace => {
+          let prefix = self.prefix_for(&mut declarations, namespace, attr.name.prefix.as_deref());
+          format!("{}:{}", prefix, attr.name.local)
+        }
+      };
+      written.push((attr_name, &attr.value));
+    }
+
+    self.writer.write_all(b"<")?;
+    self.writer.write_all(qualified.as_bytes())?;
+    for (prefix, namespace) in &declarations {
+      match prefix {
+        Some(prefix) => write!(self.writer, " xmlns:{}=\"", prefix)?,
+        None => self.writer.write_all(b" xmlns=\"")?,
+      }
+      self.escaped(namespace, true)?;
+      self.writer.write_all(b"\"")?;
+    }
+    for (attr_name, value) in written {
+      self.writer.write_all(b" ")?;
+      self.writer.write_all(attr_name.as_bytes())?;
+      self.writer.write_all(b"=\"")?;
+      self.escaped(value, true)?;
+      self.writer.write_all(b"\"")?;
+    }
+
+    // HTML elements other than void ones keep their end tag, so that the markup still
+    // reads the same as HTML.
+    let html = name.ns == ns!(html);
+    if html && is_void(name) {
+      self.scope.truncate(scope);
+      return self.writer.write_all(b" />");
+    }
+    if !html && node.children.borrow().is_empty() {
+      self.scope.truncate(scope);
+      return self.writer.write_all(b"/>");
+    }
+    self.writer.write_all(b">")?;
+    steps.push(XmlStep::Close {
+      name: qualified,
+      scope,
+    });
+    push_xml_children(steps, node);
+    Ok(())
+  }
+
+  /// The namespace bound to `prefix`, or the default namespace for `None`.
+  fn lookup(&self, prefix: Option<&str>) -> Option<&Namespace> {
+    self
+      .scope
+      .iter()
+      .rev()
+      .find(|binding| binding.prefix.as_deref() == prefix)
+      .map(|binding| &binding.namespace)
+  }
+
+  fn declare(
+    &mut self,
+    declarations: &mut Vec<(Option<String>, Namespace)>,
+    prefix: Option<String>,
+    namespace: &Namespace,
+  ) {
+    declarations.push((prefix.clone(), namespace.clone()));
+    self.scope.push(Binding {
+      prefix,
+      namespace: namespace.clone(),
+    });
+  }
+
+  /// A prefix bound to `namespace`, declaring the preferred one, or a new one when it is
+  /// taken, if none is.
+  fn prefix_for(
+    &mut self,
+    declarations: &mut Vec<(Option<String>, Namespace)>,
+    namespace: &Namespace,
+    preferred: Option<&str>,
+  ) -> String {
+    let bound = self.scope.iter().rev().find_map(|binding| {
+      let prefix = binding.prefix.as_deref()?;
+      (&binding.namespace == namespace && self.lookup(Some(prefix)) == Some(namespace))
+        .then(|| prefix.to_string())
+    });
+    if let Some(prefix) = bound {
+      return prefix;
+    }
+    let prefix = match preferred {
+      Some(prefix) if prefix != "xmlns" && self.lookup(Some(prefix)).is_none() => {
+        prefix.to_string()
+      }
+      _ => loop {
+        self.generated_prefixes += 1;
+        let prefix = format!("ns{}", self.generated_prefixes);
+        if self.lookup(Some(&prefix)).is_none() {
+          break prefix;
+        }
+      },
+    };
+    self.declare(declarations, Some(prefix.clone()), namespace);
+    prefix
+  }
+
+  fn text(&mut self, node: &Handle, text: &str) -> Result<()> {
+    let in_script = get_parent(node).is_some_and(|parent| {
+      matches!(
+        html_name(&parent),
+        Some(&local_name!("script") | &local_name!("style"))
+      )
+    });
+    // Scripts and styles are kept readable in a CDATA section rather than escaped.
+    if in_script && text.contains(['<', '&']) {
+      self.writer.write_all(b"<![CDATA[")?;
+      self
+        .writer
+        .write_all(text.replace("]]>", "]]]]><![CDATA[>").as_bytes())?;
+      return self.writer.write_all(b"]]>");
+    }
+    self.escaped(text, false)
+  }
+
+  fn escaped(&mut self, text: &str, attr_mode: bool) -> Result<()> {
+    let mut written = 0;
+    for (index, c) in text.char_indices() {
+      let escaped: &[u8] = match c {
+        '&' => b"&amp;",
+        '<' => b"&lt;",
+        '>' => b"&gt;",
+        '"' if attr_mode => b"&quot;",
+        // Parsers normalize these in attribute values, and carriage returns in text.
+        '\t' if attr_mode => b"&#9;",
+        '\n' if attr_mode => b"&#10;",
+        '\r' => b"&#13;",
+        _ => continue,
+      };
+      self.writer.write_all(&text.as_bytes()[written..index])?;
+      self.writer.write_all(escaped)?;
+      written = index + c.len_utf8();
+    }
+    self.writer.write_all(&text.as_bytes()[written..])
+  }
+}
+
+fn push_xml_children(steps: &mut Vec<XmlStep>, parent: &Handle) {
+  steps.extend(
+    parent
+      .children
+      .borrow()
+      .iter()
+      .rev()
+      .cloned()
+      .map(XmlStep::Open),
+  );
+}
+
+/// The prefix an attribute declares a namespace for, or `Some(None)` if it declares
+/// the default namespace. HTML elements hold declarations as plain attributes.
+fn declared_prefix(attr: &html5ever::Attribute) -> Option<Option<&str>> {
+  let local: &str = &attr.name.local;
+  match attr.name.ns {
+    ns!() | ns!(xmlns) if local == "xmlns" => Some(None),
+    ns!(xmlns) => Some(Some(local)),
+    ns!() => local.strip_prefix("xmlns:").map(Some),
+    _ => None,
+  }
+}
+
+/// The shortest name of a character reference for `c`, with its `;`, preferring
+/// lowercase names such as `&amp;` over `&AMP;`.
+fn named_reference(c: char) -> Option<&'static str> {
+  static NAMES: OnceLock<HashMap<char, &'static str>> = OnceLock::new();
+  let names = NAMES.get_or_init(|| {
+    let mut names = HashMap::new();
+    for (&name, &(first, second)) in NAMED_ENTITIES.entries() {
+      let Some(c) = char::from_u32(first) else {
+        continue;
+      };
+      if second != 0 || !name.ends_with(';') {
+        continue;
+      }
+      let key = |name: &str| {
+        (
+          name.len(),
+          name.contains(char::is_uppercase),
+          name.to_string(),
+        )
+      };
+      names
+        .entry(c)
+        .and_modify(|current: &mut &'static str| {
+          if key(name) < key(current) {
+            *current = name;
+          }
+        })
+        .or_insert(name);
+    }
+    names
+  });
+  names.get(&c).copied()
+}
+
+fn html_name(node: &Handle) -> Option<&LocalName> {
+  match &node.data {
+    NodeData::Element { name, .. } if name.ns == ns!(html) => Some(&name.local),
+    _ => None,
+  }
+}
+
+fn is_void(name: &QualName) -> bool {
+  name.ns == ns!(html)
+    && matches!(
+      name.local,
+      local_name!("area")
+        | local_name!("base")
+        | local_name!("basefont")
+        | local_name!("bgsound")
+        | local_name!("br")
+        | local_name!("col")
+        | local_name!("embed")
+        | local_name!("frame")
+        | local_name!("hr")
+        | local_name!("img")
+        | local_name!("input")
+        | local_name!("keygen")
+        | local_name!("link")
+        | local_name!("meta")
+        | local_name!("param")
+        | local_name!("source")
+        | local_name!("track")
+        | local_name!("wbr")
+    )
+}
+
+/// Whether whitespace in `node` is significant, in preformatted text or raw text.
+fn preserves_whitespace(node: &Handle) -> bool {
+  matches!(
+    html_name(node),
+    Some(
+      &local_name!("pre")
+        | &local_name!("textarea")
+        | &local_name!("listing")
+        | &local_name!("plaintext")
+        | &local_name!("xmp")
+        | &local_name!("script")
+        | &local_name!("style")
+    )
+  )
+}
+
+/// Whether whitespace around the element does not render, as it is a block or holds
+/// no content that renders.
+fn is_block(name: &QualName) -> bool {
+  name.ns == ns!(html)
+    && matches!(
+      name.local,
+      local_name!("address")
+        | local_name!("article")
+        | local_name!("aside")
+        | local_name!("base")
+        | local_name!("blockquote")
+        | local_name!("body")
+        | local_name!("caption")
+        | local_name!("col")
+        | local_name!("colgroup")
+        | local_name!("dd")
+        | local_name!("details")
+        | local_name!("dialog")
+        | local_name!("div")
+        | local_name!("dl")
+        | local_name!("dt")
+        | local_name!("fieldset")
+        | local_name!("figcaption")
+        | local_name!("figure")
+        | local_name!("footer")
+        | local_name!("form")
+        | local_name!("h1")
+        | local_name!("h2")
+        | local_name!("h3")
+        | local_name!("h4")
+        | local_name!("h5")
+        | local_name!("h6")
+        | local_name!("head")
+        | local_name!("header")
+        | local_name!("hgroup")
+        | local_name!("hr")
+        | local_name!("html")
+        | local_name!("legend")
+        | local_name!("li")
+        | local_name!("link")
+        | local_name!("main")
+        | local_name!("menu")
+        | local_name!("meta")
+        | local_name!("nav")
+        | local_name!("noscript")
+        | local_name!("ol")
+        | local_name!("optgroup")
+        | local_name!("option")
+        | local_name!("p")
+        | local_name!("pre")
+        | local_name!("script")
+        | local_name!("search")
+        | local_name!("section")
+        | local_name!("source")
+        | local_name!("style")
+        | local_name!("summary")
+        | local_name!("table")
+        | local_name!("tbody")
+        | local_name!("td")
+        | local_name!("template")
+        | local_name!("tfoot")
+        | local_name!("th")
+        | local_name!("thead")
+        | local_name!("title")
+        | local_name!("tr")
+        | local_name!("track")
+        | local_name!("ul")
+    )
+}
+
+/// Whether the end tag of `node` may be left out, given the sibling written after it,
+/// following the optional tag rules of the HTML spec.
+fn can_omit_end_tag(node: &Handle, next: Option<&Handle>, parent: &Handle) -> bool {
+  let Some(name) = html_name(node) else {
+    return false;
+  };
+  let next_name = next.and_then(html_name);
+  let next_is = |names: &[LocalName]| next_name.is_some_and(|next| names.contains(next));
+  let next_is_comment = next.is_some_and(|next| matches!(next.data, NodeData::Comment { .. }));
+  let next_starts_with_space = next.is_some_and(|next| match &next.data {
+    NodeData::Text { contents } => contents
+      .borrow()
+      .starts_with(|c: char| c.is_ascii_whitespace()),
+    _ => false,
+  });
+  let last = next.is_none();
+  match *name {
+    local_name!("html") | local_name!("body") => !next_is_comment,
+    local_name!("head") | local_name!("colgroup") | local_name!("caption") => {
+      !next_is_comment && !next_starts_with_space
+    }
+    local_name!("li") => last || next_is(&[local_name!("li")]),
+    local_name!("dt") => next_is(&[local_name!("dt"), local_name!("dd")]),
+    local_name!("dd") => last || next_is(&[local_name!("dt"), local_name!("dd")]),
+    local_name!("rt") | local_name!("rp") => {
+      last || next_is(&[local_name!("rt"), local_name!("rp")])
+    }
+    local_name!("optgroup") => last || next_is(&[local_name!("optgroup"), local_name!("hr")]),
+    local_name!("option") => {
+      last
+        || next_is(&[
+          local_name!("option"),
+          local_name!("optgroup"),
+          local_name!("hr"),
+        ])
+    }
+    local_name!("thead") => next_is(&[local_name!("tbody"), local_name!("tfoot")]),
+    local_name!("tbody") => last || next_is(&[local_name!("tbody"), local_name!("tfoot")]),
+    local_name!("tfoot") => last,
+    local_name!("tr") => last || next_is(&[local_name!("tr")]),
+    local_name!("td") | local_name!("th") => {
+      last || next_is(&[local_name!("td"), local_name!("th")])
     }
-    NodeData::Element { .. } | NodeData::Document => {
-      for child in handle.children.borrow().iter() {
-        serialize_text_only(child, writer)?
+    local_name!("p") => {
+      if last {
+        // The end of a transparent parent does not close the paragraph.
+        return match &parent.data {
+          NodeData::Element { name, .. } => {
+            name.ns == ns!(html)
+              && !name.local.contains('-')
+              && !matches!(
+                name.local,
+                local_name!("a")
+                  | local_name!("audio")
+                  | local_name!("del")
+                  | local_name!("ins")
+                  | local_name!("map")
+                  | local_name!("noscript")
+                  | local_name!("video")
+              )
+          }
+          _ => true,
+        };
+      }
+      next_is(&[
+        local_name!("address"),
+        local_name!("article"),
+        local_name!("aside"),
+        local_name!("blockquote"),
+        local_name!("details"),
+        local_name!("dialog"),
+        local_name!("div"),
+        local_name!("dl"),
+        local_name!("fieldset"),
+        local_name!("figcaption"),
+        local_name!("figure"),
+        local_name!("footer"),
+        local_name!("form"),
+        local_name!("h1"),
+        local_name!("h2"),
+        local_name!("h3"),
+        local_name!("h4"),
+        local_name!("h5"),
+        local_name!("h6"),
+        local_name!("header"),
+        local_name!("hgroup"),
+        local_name!("hr"),
+        local_name!("main"),
+        local_name!("menu"),
+        local_name!("nav"),
+        local_name!("ol"),
+        local_name!("p"),
+        local_name!("pre"),
+        local_name!("search"),
+        local_name!("section"),
+        local_name!("table"),
+        local_name!("ul"),
+      ])
+    }
+    _ => false,
+  }
+}
+
+/// Whether an attribute of `element` only matters by being present, given its value.
+/// A value other than the empty string or the attribute's own name is kept, as in
+/// `hidden="until-found"`.
+fn is_boolean_attribute(element: &QualName, name: &LocalName, value: &str) -> bool {
+  element.ns == ns!(html)
+    && value.eq_ignore_ascii_case(name)
+    && matches!(
+      name.as_ref(),
+      "allowfullscreen"
+        | "async"
+        | "autofocus"
+        | "autoplay"
+        | "checked"
+        | "controls"
+        | "default"
+        | "defer"
+        | "disabled"
+        | "formnovalidate"
+        | "hidden"
+        | "inert"
+        | "ismap"
+        | "itemscope"
+        | "loop"
+        | "multiple"
+        | "muted"
+        | "nomodule"
+        | "novalidate"
+        | "open"
+        | "playsinline"
+        | "readonly"
+        | "required"
+        | "reversed"
+        | "selected"
+    )
+}
+
+/// Whether an attribute value reads back the same without quotes.
+fn can_be_unquoted(value: &str) -> bool {
+  !value.is_empty()
+    && !value.contains([
+      ' ', '\t', '\n', '\u{000C}', '\r', '"', '\'', '=', '<', '>', '`',
+    ])
+}
+
+fn is_whitespace(text: &str) -> bool {
+  text.chars().all(|c| c.is_ascii_whitespace())
+}
+
+fn collapse_whitespace(text: &str) -> String {
+  let mut collapsed = String::with_capacity(text.len());
+  let mut in_space = false;
+  for c in text.chars() {
+    if c.is_ascii_whitespace() {
+      if !in_space {
+        collapsed.push(' ');
       }
-      Ok(())
+      in_space = true;
+    } else {
+      collapsed.push(c);
+      in_space = false;
     }
-    _ => Ok(()),
   }
+  collapsed
 }
@@ -0,0 +1,796 @@
+use html5ever::{namespace_url, ns, QualName};
+use markup5ever_rcdom::{Handle, Node, NodeData};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use super::{
+  string_to_number, ArithmeticOp, Axis, CompareOp, Expr, Function, NodeTest, PathStart, Step,
+  XPathError, XPathNode, XPathValue,
+};
+use crate::DomNode;
+
+/// Evaluates `expr` with `context` as the context node. `resolver` maps namespace
+/// prefixes to URIs; unresolved prefixes fall back to a few well-known ones.
+pub(crate) fn evaluate(
+  expr: &Expr,
+  context: &DomNode,
+  resolver: &dyn Fn(&str) -> Option<String>,
+) -> Result<XPathValue, XPathError> {
+  let evaluator = Evaluator {
+    resolver,
+    root: context.get_root_node().0,
+    html_document: context.is_html_document(),
+    order: RefCell::new(None),
+  };
+  evaluator.eval(
+    expr,
+    &Context {
+      node: XPathNode::Node(context.clone()),
+      position: 1,
+      size: 1,
+    },
+  )
+}
+
+struct Context {
+  node: XPathNode,
+  position: usize,
+  size: usize,
+}
+
+struct Evaluator<'a> {
+  resolver: &'a dyn Fn(&str) -> Option<String>,
+  root: Handle,
+  /// Whether the context belongs to an HTML document, where HTML element and attribute
+  /// names match case-insensitively.
+  html_document: bool,
+  /// Pre-order index of every node in the context tree, built the first time a node-set
+  /// has to be put in document order.
+  order: RefCell<Option<HashMap<*const Node, usize>>>,
+}
+
+impl Evaluator<'_> {
+  fn eval(&self, expr: &Expr, context: &Context) -> Result<XPathValue, XPathError> {
+    Ok(match expr {
+      Expr::Or(left, right) => XPathValue::Boolean(
+        self.eval(left, context)?.to_boolean() || self.eval(right, context)?.to_boolean(),
+      ),
+      Expr::And(left, right) => XPathValue::Boolean(
+        self.eval(left, context)?.to_boolean() && self.eval(right, context)?.to_boolean(),
+      ),
+      Expr::Compare(op, left, right) => {
+        let left = self.eval(left, context)?;
+        let right = self.eval(right, context)?;
+        XPathValue::Boolean(compare(*op, &left, &right))
+      }
+      Expr::Arithmetic(op, left, right) => {
+        let left = self.eval(left, context)?.to_number();
+        let right = self.eval(right, context)?.to_number();
+        XPathValue::Number(match op {
+          ArithmeticOp::Add => left + right,
+          ArithmeticOp::Subtract => left - right,
+          ArithmeticOp::Multiply => left * right,
+          ArithmeticOp::Divide => left / right,
+          ArithmeticOp::Modulo => left % right,
+        })
+      }
+      Expr::Negate(operand) => XPathValue::Number(-self.eval(operand, context)?.to_number()),
+      Expr::Union(left, right) => {
+        let mut nodes = self.eval_node_set(left, context, "The operands of '|'")?;
+        nodes.extend(self.eval_node_set(right, context, "The operands of '|'")?);
+        self.sort_and_dedup(&mut nodes);
+        XPathValue::NodeSet(nodes)
+      }
+      Expr::Literal(value) => XPathValue::String(value.clone()),
+      Expr::Number(value) => XPathValue::Number(*value),
+      Expr::Function(function, args) => self.call(*function, args, context)?,
+      Expr::Filter(primary, predicates) => {
+        let nodes = self.eval_node_set(primary, context, "A filtered expression")?;
+        XPathValue::NodeSet(self.filter(nodes, predicates)?)
+      }
+      Expr::Path(start, steps) => {
+        let mut nodes = match start {
+          PathStart::Context => vec![context.node.clone()],
+          PathStart::Root => vec![XPathNode::Node(root_of(&context.node))],
+          PathStart::Expr(expr) => {
+            self.eval_node_set(expr, context, "The start of a location path")?
+          }
+        };
+        for step in steps {
+          nodes = self.step(&nodes, step)?;
+        }
+        XPathValue::NodeSet(nodes)
+      }
+    })
+  }
+
+  fn eval_node_set(
+    &self,
+    expr: &Expr,
+    context: &Context,
+    subject: &str,
+  ) -> Result<Vec<XPathNode>, XPathError> {
+    match self.eval(expr, context)? {
+      XPathValue::NodeSet(nodes) => Ok(nodes),
+      _ => Err(XPathError::Type {
+        message: format!("{} must be a node-set", subject),
+      }),
+    }
+  }
+
+  /// Applies a location step to every node of `nodes`, returning the union of the
+  /// results in document order.
+  fn step(&self, nodes: &[XPathNode], step: &Step) -> Result<Vec<XPathNode>, XPathError> {
+    let test = match &step.test {
+      NodeTest::Name {
+        prefix: Some(prefix),
+        local,
+      } => ResolvedTest::Name(Some(self.resolve(prefix)?), local.as_deref()),
+      NodeTest::Name {
+        prefix: None,
+        local,
+      } => ResolvedTest::Name(None, local.as_deref()),
+      NodeTest::Node => ResolvedTest::Node,
+      NodeTest::Text => ResolvedTest::Text,
+      NodeTest::Comment => ResolvedTest::Comment,
+      NodeTest::ProcessingInstruction(target) => {
+        ResolvedTest::ProcessingInstruction(target.as_deref())
+      }
+    };
+
+    let mut result = Vec::new();
+    for node in nodes {
+      let candidates = axis_nodes(node, step.axis)
+        .into_iter()
+        .filter(|candidate| test.matches(candidate, step.axis, self.html_document))
+        .collect();
+      result.extend(self.filter(candidates, &step.predicates)?);
+    }
+    if nodes.len() == 1 {
+      // A single context node yields each node once, in axis order.
+      if step.axis.is_reverse() {
+        result.reverse();
+      }
+    } else {
+      self.sort_and_dedup(&mut result);
+    }
+    Ok(result)
+  }
+
+  /// Keeps the nodes that satisfy every predicate, where positions are counted in the
+  /// order `nodes` is given in.
+  fn filter(
+    &self,
+    mut nodes: Vec<XPathNode>,
+    predicates: &[Expr],
+  ) -> Result<Vec<XPathNode>, XPathError> {
+    for predicate in predicates {
+      let size = nodes.len();
+      let mut kept = Vec::new();
+      for (index, node) in nodes.into_iter().enumerate() {
+        let context = Context {
+          node,
+          position: index + 1,
+          size,
+        };
+        let keep = match self.eval(predicate, &context)? {
+          XPathValue::Number(number) => number == context.position as f64,
+          value => value.to_boolean(),
+        };
+        if keep {
+          kept.push(context.node);
+        }
+      }
+      nodes = kept;
+    }
+    Ok(nodes)
+  }
+
+  fn call(
+    &self,
+    function: Function,
+    args: &[Expr],
+    context: &Context,
+  ) -> Result<XPathValue, XPathError> {
+    let string_arg = |index: usize| -> Result<String, XPathError> {
+      match args.get(index) {
+        Some(arg) => Ok(self.eval(arg, context)?.to_string_value()),
+        None => Ok(context.node.string_value()),
+      }
+    };
+    let number_arg = |index: usize| -> Result<f64, XPathError> {
+      Ok(self.eval(&args[index], context)?.to_number())
+    };
+    // The first node of an optional node-set argument, defaulting to the context node.
+    let node_arg = || -> Result<Option<XPathNode>, XPathError> {
+      match args.first() {
+        Some(arg) => Ok(
+          self
+            .eval_node_set(arg, context, "The function argument")?
+            .into_iter()
+            .next(),
+        ),
+        None => Ok(Some(context.node.clone())),
+      }
+    };
+
+    Ok(match function {
+      Function::Last => XPathValue::Number(context.size as f64),
+      Function::Position => XPathValue::Number(context.position as f64),
+      Function::Count => XPathValue::Number(
+        self
+          .eval_node_set(&args[0], context, "The argument of count()")?
+          .len() as f64,
+      ),
+      Function::Id => {
+        let ids: HashSet<String> = match self.eval(&args[0], context)? {
+          XPathValue::NodeSet(nodes) => nodes
+            .iter()
+            .flat_map(|node| {
+              split_whitespace(&node.string_value())
+                .map(String::from)
+                .collect::<Vec<_>>()
+            })
+            .collect(),
+          value => split_whitespace(&value.to_string_value())
+            .map(String::from)
+            .collect(),
+        };
+        XPathValue::NodeSet(elements_by_id(&root_of(&context.node), &ids))
+      }
+      Function::LocalName => XPathValue::String(
+        node_arg()?
+          .map(|node| match qualified_name(&node) {
+            Some(name) => name.local.to_string(),
+            None => pi_target(&node).unwrap_or_default(),
+          })
+          .unwrap_or_default(),
+      ),
+      Function::NamespaceUri => XPathValue::String(
+        node_arg()?
+          .and_then(|node| qualified_name(&node).map(|name| name.ns.to_string()))
+          .unwrap_or_default(),
+      ),
+      Function::Name => XPathValue::String(
+        node_arg()?
+          .map(|node| match qualified_name(&node) {
+            Some(name) => match &name.prefix {
+              Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, name.local),
+              _ => name.local.to_string(),
+            },
+            None => pi_target(&node).unwrap_or_default(),
+          })
+          .unwrap_or_default(),
+      ),
+      Function::String => XPathValue::String(string_arg(0)?),
+      Function::Concat => {
+        let mut result = String::new();
+        for arg in args {
+          result.push_str(&self.eval(arg, context)?.to_string_value());
+        }
+        XPathValue::String(result)
+      }
+      Function::StartsWith => XPathValue::Boolean(string_arg(0)?.starts_with(&string_arg(1)?)),
+      Function::Contains => XPathValue::Boolean(string_arg(0)?.contains(&string_arg(1)?)),
+      Function::SubstringBefore => {
+        let haystack = string_arg(0)?;
+        let needle = string_arg(1)?;
+        XPathValue::String(
+          haystack
+            .find(&needle)
+            .map(|index| haystack[..index].to_string())
+            .unwrap_or_default(),
+        )
+      }
+      Function::SubstringAfter => {
+        let haystack = string_arg(0)?;
+        let needle = string_arg(1)?;
+        XPathValue::String(
+          haystack
+            .find(&needle)
+            .map(|index| haystack[index + needle.len()..].to_string())
+            .unwrap_or_default(),
+        )
+      }
+      Function::Substring => {
+        let string = string_arg(0)?;
+        let start = round(number_arg(1)?);
+        let end = if args.len() > 2 {
+          start + round(number_arg(2)?)
+        } else {
+          f64::INFINITY
+        };
+        XPathValue::String(
+          string
+            .chars()
+            .enumerate()
+            .filter(|(index, _)| {
+              let position = (*index + 1) as f64;
+              position >= start && position < end
+            })
+            .map(|(_, c)| c)
+            .collect(),
+        )
+      }
+      Function::StringLength => XPathValue::Number(string_arg(0)?.chars().count() as f64),
+      Function::NormalizeSpace => XPathValue::String(
+        split_whitespace(&string_arg(0)?)
+          .collect::<Vec<_>>()
+          .join(" "),
+      ),
+      Function::Translate => {
+        let string = string_arg(0)?;
+        let from: Vec<char> = string_arg(1)?.chars().collect();
+        let to: Vec<char> = string_arg(2)?.chars().collect();
+        XPathValue::String(
+          string
+            .chars()
+            .filter_map(|c| match from.iter().position(|&f| f == c) {
+              Some(index) => to.get(index).copied(),
+              None => Some(c),
+            })
+            .collect(),
+        )
+      }
+      Function::Boolean => XPathValue::Boolean(self.eval(&args[0], context)?.to_boolean()),
+      Function::Not => XPathValue::Boolean(!self.eval(&args[0], context)?.to_boolean()),
+      Function::True => XPathValue::Boolean(true),
+      Function::False => XPathValue::Boolean(false),
+      Function::Lang => {
+        let lang = string_arg(0)?;
+        XPathValue::Boolean(language_of(&context.node).is_some_and(|actual| {
+          actual.eq_ignore_ascii_case(&lang)
+            || (actual.len() > lang.len()
+              && actual.is_char_boundary(lang.len())
+              && actual[..lang.len()].eq_ignore_ascii_case(&lang)
+              && actual[lang.len()..].starts_with('-'))
+        }))
+      }
+      Function::Number => XPathValue::Number(match args.first() {
+        Some(arg) => self.eval(arg, context)?.to_number(),
+        None => string_to_number(&context.node.string_value()),
+      }),
+      Function::Sum => XPathValue::Number(
+        self
+          .eval_node_set(&args[0], context, "The argument of sum()")?
+          .iter()
+          .map(|node| string_to_number(&node.string_value()))
+          .sum(),
+      ),
+      Function::Floor => XPathValue::Number(number_arg(0)?.floor()),
+      Function::Ceiling => XPathValue::Number(number_arg(0)?.ceil()),
+      Function::Round => XPathValue::Number(round(number_arg(0)?)),
+    })
+  }
+
+  fn resolve(&self, prefix: &str) -> Result<String, XPathError> {
+    if prefix == "xml" {
+      return Ok(ns!(xml).to_string());
+    }
+    if let Some(uri) = (self.resolver)(prefix) {
+      return Ok(uri);
+    }
+    let uri = match prefix {
+      "html" => ns!(html),
+      "svg" => ns!(svg),
+      "math" => ns!(mathml),
+      "xlink" => ns!(xlink),
+      "xmlns" => ns!(xmlns),
+      _ => {
+        return Err(XPathError::Namespace {
+          prefix: prefix.to_string(),
+        })
+      }
+    };
+    Ok(uri.to_string())
+  }
+
+  fn sort_and_dedup(&self, nodes: &mut Vec<XPathNode>) {
+    let mut order = self.order.borrow_mut();
+    let order = order.get_or_insert_with(|| document_order(&self.root));
+    let index = |handle: &Handle| {
+      order
+        .get(&Rc::as_ptr(handle))
+        .copied()
+        .unwrap_or(usize::MAX)
+    };
+    // Attributes sort after their owner element and before its children.
+    nodes.sort_by_cached_key(|node| match node {
+      XPathNode::Node(node) => (index(&node.0), 0),
+      XPathNode::Attribute(element, name) => {
+        let slot = match &element.0.data {
+          NodeData::Element { attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .position(|attr| &attr.name == name)
+            .map_or(usize::MAX, |position| position + 1),
+          _ => usize::MAX,
+        };
+        (index(&element.0), slot)
+      }
+    });
+    nodes.dedup_by(|a, b| a.is_same(b));
+  }
+}
+
+/// A node test with its namespace prefix resolved to a URI.
+enum ResolvedTest<'a> {
+  Name(Option<String>, Option<&'a str>),
+  Node,
+  Text,
+  Comment,
+  ProcessingInstruction(Option<&'a str>),
+}
+
+impl ResolvedTest<'_> {
+  fn matches(&self, node: &XPathNode, axis: Axis, html_document: bool) -> bool {
+    let node = match (node, self) {
+      (XPathNode::Attribute(element, name), ResolvedTest::Name(namespace, local)) => {
+        if axis != Axis::Attribute {
+          return false;
+        }
+        return match (namespace, local) {
+          (None, None) => true,
+          (Some(namespace), None) => name.ns.as_ref() == namespace,
+          (None, Some(local)) => {
+            name.ns == ns!()
+              && if html_document && is_html_element(element) {
+                name.local.as_ref().eq_ignore_ascii_case(local)
+              } else {
+                name.local.as_ref() == *local
+              }
+          }
+          (Some(namespace), Some(local)) => {
+            name.ns.as_ref() == namespace && name.local.as_ref() == *local
+          }
+        };
+      }
+      (XPathNode::Attribute(..), ResolvedTest::Node) => return true,
+      (XPathNode::Attribute(..), _) => return false,
+      (XPathNode::Node(node), _) => node,
+    };
+    match self {
+      ResolvedTest::Name(namespace, local) => {
+        let NodeData::Element { name, .. } = &node.0.data else {
+          return false;
+        };
+        if axis == Axis::Namespace || node.node_type() != 1 {
+          return false;
+        }
+        match (namespace, local) {
+          (None, None) => true,
+          (Some(namespace), None) => name.ns.as_ref() == namespace,
+          // Unprefixed names match elements of any namespace, so `//svg` finds inline
+          // SVG without a resolver. HTML elements match case-insensitively in HTML
+          // documents.
+          (None, Some(local)) => {
+            if html_document && name.ns == ns!(html) {
+              name.local.as_ref().eq_ignore_ascii_case(local)
+            } else {
+              name.local.as_ref() == *local
+            }
+          }
+          (Some(namespace), Some(local)) => {
+            name.ns.as_ref() == namespace && name.local.as_ref() == *local
+          }
+        }
+      }
+      ResolvedTest::Node => true,
+      ResolvedTest::Text => matches!(node.0.data, NodeData::Text { .. }),
+      ResolvedTest::Comment => matches!(node.0.data, NodeData::Comment { .. }),
+      ResolvedTest::ProcessingInstruction(expected) => match &node.0.data {
+        NodeData::ProcessingInstruction { target, .. } => {
+          expected.is_none_or(|expected| target.as_ref() == expected)
+        }
+        _ => false,
+      },
+    }
+  }
+}
+
+/// Lists the nodes on `axis` from `node`, in axis order: reverse document order for
+/// reverse axes, document order otherwise.
+fn axis_nodes(node: &XPathNode, axis: Axis) -> Vec<XPathNode> {
+  let tree_node = match node {
+    XPathNode::Node(node) => node,
+    XPathNode::Attribute(element, _) => {
+      return match axis {
+        Axis::SelfNode | Axis::DescendantOrSelf => vec![node.clone()],
+        Axis::Parent => vec![XPathNode::Node(element.clone())],
+        Axis::Ancestor => ancestors(element, true),
+        Axis::AncestorOrSelf => {
+          let mut nodes = vec![node.clone()];
+          nodes.extend(ancestors(element, true));
+          nodes
+        }
+        Axis::Following => {
+          let mut nodes = descendants(element);
+          nodes.extend(following(element));
+          nodes
+        }
+        Axis::Preceding => preceding(element),
+        _ => Vec::new(),
+      };
+    }
+  };
+  match axis {
+    Axis::SelfNode => vec![node.clone()],
+    Axis::Child => children(tree_node).map(XPathNode::Node).collect(),
+    Axis::Descendant => descendants(tree_node),
+    Axis::DescendantOrSelf => {
+      let mut nodes = vec![node.clone()];
+      nodes.extend(descendants(tree_node));
+      nodes
+    }
+    Axis::Parent => tree_node
+      .parent_node()
+      .map(XPathNode::Node)
+      .into_iter()
+      .collect(),
+    Axis::Ancestor => ancestors(tree_node, false),
+    Axis::AncestorOrSelf => ancestors(tree_node, true),
+    Axis::FollowingSibling => siblings(tree_node, false)
+      .into_iter()
+      .map(XPathNode::Node)
+      .collect(),
+    Axis::PrecedingSibling => siblings(tree_node, true)
+      .into_iter()
+      .map(XPathNode::Node)
+      .collect(),
+    Axis::Following => following(tree_node),
+    Axis::Preceding => preceding(tree_node),
+    Axis::Attribute => match &tree_node.0.data {
+      NodeData::Element { attrs, .. } if tree_node.node_type() == 1 => attrs
+        .borrow()
+        .iter()
+        .filter(|attr| attr.name.ns != ns!(xmlns))
+        .map(|attr| XPathNode::Attribute(tree_node.clone(), attr.name.clone()))
+        .collect(),
+      _ => Vec::new(),
+    },
+    Axis::Namespace => Vec::new(),
+  }
+}
+
+/// Child nodes as XPath sees them: doctypes are not part of its data model.
+fn children(node: &DomNode) -> impl Iterator<Item = DomNode> {
+  node
+    .child_nodes()
+    .into_iter()
+    .filter(|child| !matches!(child.0.data, NodeData::Doctype { .. }))
+}
+
+fn descendants(node: &DomNode) -> Vec<XPathNode> {
+  let mut result = Vec::new();
+  let mut stack: Vec<DomNode> = children(node).collect();
+  stack.reverse();
+  while let Some(current) = stack.pop() {
+    let start = stack.len();
+    stack.extend(children(&current));
+    stack[start..].reverse();
+    result.push(XPathNode::Node(current));
+  }
+  result
+}
+
+/// Ancestors from the nearest outwards, optionally starting with `node` itself.
+fn ancestors(node: &DomNode, include_self: bool) -> Vec<XPathNode> {
+  let mut result = Vec::new();
+  if include_self {
+    result.push(XPathNode::Node(node.clone()));
+  }
+  let mut current = node.parent_node();
+  while let Some(ancestor) = current {
+    current = ancestor.parent_node();
+    result.push(XPathNode::Node(ancestor));
+  }
+  result
+}
+
+/// Following siblings in document order, or preceding siblings nearest first.
+fn siblings(node: &DomNode, preceding: bool) -> Vec<DomNode> {
+  let Some(parent) = node.parent_node() else {
+    return Vec::new();
+  };
+  let mut all: Vec<DomNode> = children(&parent).collect();
+  let Some(index) = all.iter().position(|sibling| sibling.is_same_node(node)) else {
+    return Vec::new();
+  };
+  if preceding {
+    all.truncate(index);
+    all.reverse();
+    all
+  } else {
+    all.split_off(index + 1)
+  }
+}
+
+fn following(node: &DomNode) -> Vec<XPathNode> {
+  let mut result = Vec::new();
+  let mut current = Some(node.clone());
+  while let Some(node) = current {
+    for sibling in siblings(&node, false) {
+      let subtree = descendants(&sibling);
+      result.push(XPathNode::Node(sibling));
+      result.extend(subtree);
+    }
+    current = node.parent_node();
+  }
+  result
+}
+
+fn preceding(node: &DomNode) -> Vec<XPathNode> {
+  let mut result = Vec::new();
+  let mut current = Some(node.clone());
+  while let Some(node) = current {
+    for sibling in siblings(&node, true) {
+      let mut subtree = descendants(&sibling);
+      subtree.reverse();
+      result.extend(subtree);
+      result.push(XPathNode::Node(sibling));
+    }
+    current = node.parent_node();
+  }
+  result
+}
+
+fn root_of(node: &XPathNode) -> DomNode {
+  match node {
+    XPathNode::Node(node) | XPathNode::Attribute(node, _) => node.get_root_node(),
+  }
+}
+
+fn document_order(root: &Handle) -> HashMap<*const Node, usize> {
+  let mut order = HashMap::new();
+  let mut stack = vec![root.clone()];
+  while let Some(node) = stack.pop() {
+    order.insert(Rc::as_ptr(&node), order.len());
+    stack.extend(node.children.borrow().iter().rev().cloned());
+  }
+  order
+}
+
+fn elements_by_id(root: &DomNode, ids: &HashSet<String>) -> Vec<XPathNode> {
+  let mut found = HashSet::new();
+  let mut result = Vec::new();
+  let mut stack = vec![root.0.clone()];
+  while let Some(node) = stack.pop() {
+    if let NodeData::Element { attrs, .. } = &node.data {
+      let attrs = attrs.borrow();
+      if let Some(id) = attrs.iter().find(|attr| attr.name.local.as_ref() == "id") {
+        let id = id.value.to_string();
+        if ids.contains(&id) && found.insert(id) {
+          result.push(XPathNode::Node(DomNode(node.clone())));
+        }
+      }
+    }
+    stack.extend(node.children.borrow().iter().rev().cloned());
+  }
+  result
+}
+
+fn qualified_name(node: &XPathNode) -> Option<QualName> {
+  match node {
+    XPathNode::Attribute(_, name) => Some(name.clone()),
+    XPathNode::Node(node) => match &node.0.data {
+      NodeData::Element { name, .. } if node.node_type() == 1 => Some(name.clone()),
+      _ => None,
+    },
+  }
+}
+
+fn pi_target(node: &XPathNode) -> Option<String> {
+  match node {
+    XPathNode::Node(node) => match &node.0.data {
+      NodeData::ProcessingInstruction { target, .. } => Some(target.to_string()),
+      _ => None,
+    },
+    XPathNode::Attribute(..) => None,
+  }
+}
+
+/// The `xml:lang` (or, in HTML, `lang`) of the nearest element that declares one.
+fn language_of(node: &XPathNode) -> Option<String> {
+  let mut current = match node {
+    XPathNode::Node(node) => Some(node.clone()),
+    XPathNode::Attribute(element, _) => Some(element.clone()),
+  };
+  while let Some(node) = current {
+    if let NodeData::Element { attrs, .. } = &node.0.data {
+      let attrs = attrs.borrow();
+      let lang = attrs
+        .iter()
+        .find(|attr| attr.name.ns == ns!(xml) && attr.name.local.as_ref() == "lang")
+        .or_else(|| {
+          attrs
+            .iter()
+            .find(|attr| attr.name.ns == ns!() && attr.name.local.as_ref() == "lang")
+        });
+      if let Some(lang) = lang {
+        return Some(lang.value.to_string());
+      }
+    }
+    current = node.parent_node();
+  }
+  None
+}
+
+fn is_html_element(node: &DomNode) -> bool {
+  matches!(&node.0.data, NodeData::Element { name, .. } if name.ns == ns!(html))
+}
+
+fn split_whitespace(string: &str) -> impl Iterator<Item = &str> {
+  string
+    .split([' ', '\t', '\n', '\r'])
+    .filter(|part| !part.is_empty())
+}
+
+/// Rounds half up, as the XPath `round()` function does.
+fn round(number: f64) -> f64 {
+  if number.is_nan() || number.is_infinite() {
+    number
+  } else if (-0.5..0.0).contains(&number) {
+    -0.0
+  } else {
+    (number + 0.5).floor()
+  }
+}
+
+fn compare(op: CompareOp, left: &XPathValue, right: &XPathValue) -> bool {
+  match (left, right) {
+    (XPathValue::NodeSet(_), XPathValue::Boolean(_))
+    | (XPathValue::Boolean(_), XPathValue::NodeSet(_)) => compare_atoms(
+      op,
+      &XPathValue::Boolean(left.to_boolean()),
+      &XPathValue::Boolean(right.to_boolean()),
+    ),
+    (XPathValue::NodeSet(left), XPathValue::NodeSet(right)) => {
+      let right: Vec<XPathValue> = right
+        .iter()
+        .map(|node| XPathValue::String(node.string_value()))
+        .collect();
+      left.iter().any(|node| {
+        let value = XPathValue::String(node.string_value());
+        right.iter().any(|other| compare_atoms(op, &value, other))
+      })
+    }
+    (XPathValue::NodeSet(nodes), other) => nodes
+      .iter()
+      .any(|node| compare_atoms(op, &atomize(node, other), other)),
+    (other, XPathValue::NodeSet(nodes)) => nodes
+      .iter()
+      .any(|node| compare_atoms(op, other, &atomize(node, other))),
+    _ => compare_atoms(op, left, right),
+  }
+}
+
+/// Converts a node to the type it is compared against.
+fn atomize(node: &XPathNode, other: &XPathValue) -> XPathValue {
+  let value = node.string_value();
+  match other {
+    XPathValue::Number(_) => XPathValue::Number(string_to_number(&value)),
+    _ => XPathValue::String(value),
+  }
+}
+
+fn compare_atoms(op: CompareOp, left: &XPathValue, right: &XPathValue) -> bool {
+  match op {
+    CompareOp::Equal | CompareOp::NotEqual => {
+      let equal = match (left, right) {
+        (XPathValue::Boolean(_), _) | (_, XPathValue::Boolean(_)) => {
+          left.to_boolean() == right.to_boolean()
+        }
+        (XPathValue::Number(_), _) | (_, XPathValue::Number(_)) => {
+          left.to_number() == right.to_number()
+        }
+        _ => left.to_string_value() == right.to_string_value(),
+      };
+      equal == (op == CompareOp::Equal)
+    }
+    CompareOp::Less => left.to_number() < right.to_number(),
+    CompareOp::LessOrEqual => left.to_number() <= right.to_number(),
+    CompareOp::Greater => left.to_number() > right.to_number(),
+    CompareOp::GreaterOrEqual => left.to_number() >= right.to_number(),
+  }
+}
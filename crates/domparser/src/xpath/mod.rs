@@ -0,0 +1,408 @@
+mod eval;
+mod parser;
+
+use html5ever::QualName;
+use markup5ever_rcdom::NodeData;
+use std::fmt;
+
+use crate::DomNode;
+
+pub(crate) use eval::evaluate;
+
+/// The result of evaluating an XPath expression.
+#[derive(Clone)]
+pub enum XPathValue {
+  /// Nodes in document order, without duplicates.
+  NodeSet(Vec<XPathNode>),
+  Number(f64),
+  String(String),
+  Boolean(bool),
+}
+
+impl XPathValue {
+  /// Converts the value as the XPath `string()` function does.
+  pub fn to_string_value(&self) -> String {
+    match self {
+      XPathValue::NodeSet(nodes) => nodes
+        .first()
+        .map(XPathNode::string_value)
+        .unwrap_or_default(),
+      XPathValue::Number(number) => number_to_string(*number),
+      XPathValue::String(string) => string.clone(),
+      XPathValue::Boolean(boolean) => boolean.to_string(),
+    }
+  }
+
+  /// Converts the value as the XPath `number()` function does.
+  pub fn to_number(&self) -> f64 {
+    match self {
+      XPathValue::Number(number) => *number,
+      XPathValue::Boolean(boolean) => {
+        if *boolean {
+          1.0
+        } else {
+          0.0
+        }
+      }
+      _ => string_to_number(&self.to_string_value()),
+    }
+  }
+
+  /// Converts the value as the XPath `boolean()` function does.
+  pub fn to_boolean(&self) -> bool {
+    match self {
+      XPathValue::NodeSet(nodes) => !nodes.is_empty(),
+      XPathValue::Number(number) => *number != 0.0 && !number.is_nan(),
+      XPathValue::String(string) => !string.is_empty(),
+      XPathValue::Boolean(boolean) => *boolean,
+    }
+  }
+}
+
+/// A node selected by an XPath expression.
+///
+/// The tree has no attribute nodes of its own, so attributes are identified by their
+/// owner element and qualified name.
+#[derive(Clone)]
+pub enum XPathNode {
+  Node(DomNode),
+  Attribute(DomNode, QualName),
+}
+
+impl XPathNode {
+  /// Returns the tree node, or `None` for an attribute.
+  pub fn node(&self) -> Option<&DomNode> {
+    match self {
+      XPathNode::Node(node) => Some(node),
+      XPathNode::Attribute(..) => None,
+    }
+  }
+
+  /// The XPath string-value: the text of all descendant text nodes for elements and
+  /// documents, and the data or value for everything else.
+  pub fn string_value(&self) -> String {
+    match self {
+      XPathNode::Node(node) => match &node.0.data {
+        NodeData::Document | NodeData::Element { .. } => {
+          let mut value = String::new();
+          let mut stack = vec![node.0.clone()];
+          while let Some(current) = stack.pop() {
+            if let NodeData::Text { contents } = &current.data {
+              value.push_str(&contents.borrow());
+            }
+            stack.extend(current.children.borrow().iter().rev().cloned());
+          }
+          value
+        }
+        NodeData::Text { contents } => contents.borrow().to_string(),
+        NodeData::Comment { contents } => contents.to_string(),
+        NodeData::ProcessingInstruction { contents, .. } => contents.to_string(),
+        NodeData::Doctype { .. } => String::new(),
+      },
+      XPathNode::Attribute(element, name) => match &element.0.data {
+        NodeData::Element { attrs, .. } => attrs
+          .borrow()
+          .iter()
+          .find(|attr| &attr.name == name)
+          .map(|attr| attr.value.to_string())
+          .unwrap_or_default(),
+        _ => String::new(),
+      },
+    }
+  }
+
+  pub(crate) fn is_same(&self, other: &XPathNode) -> bool {
+    match (self, other) {
+      (XPathNode::Node(a), XPathNode::Node(b)) => a.is_same_node(b),
+      (XPathNode::Attribute(a, a_name), XPathNode::Attribute(b, b_name)) => {
+        a.is_same_node(b) && a_name == b_name
+      }
+      _ => false,
+    }
+  }
+}
+
+/// An error produced while parsing or evaluating an XPath expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XPathError {
+  /// The expression is not valid XPath 1.0.
+  Syntax {
+    message: String,
+    /// Character offset in the expression where the error was detected.
+    position: usize,
+  },
+  /// A namespace prefix used in the expression could not be resolved.
+  Namespace { prefix: String },
+  /// An operand had the wrong type, such as a location step applied to a number.
+  Type { message: String },
+}
+
+impl fmt::Display for XPathError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      XPathError::Syntax { message, position } => {
+        write!(f, "{} at position {}", message, position)
+      }
+      XPathError::Namespace { prefix } => {
+        write!(f, "The namespace prefix '{}' is not defined", prefix)
+      }
+      XPathError::Type { message } => f.write_str(message),
+    }
+  }
+}
+
+impl std::error::Error for XPathError {}
+
+/// A parsed expression.
+#[derive(Debug, Clone)]
+pub(crate) enum Expr {
+  Or(Box<Expr>, Box<Expr>),
+  And(Box<Expr>, Box<Expr>),
+  Compare(CompareOp, Box<Expr>, Box<Expr>),
+  Arithmetic(ArithmeticOp, Box<Expr>, Box<Expr>),
+  Negate(Box<Expr>),
+  Union(Box<Expr>, Box<Expr>),
+  Literal(String),
+  Number(f64),
+  Function(Function, Vec<Expr>),
+  /// A primary expression followed by predicates, such as `(//a)[1]`.
+  Filter(Box<Expr>, Vec<Expr>),
+  Path(PathStart, Vec<Step>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CompareOp {
+  Equal,
+  NotEqual,
+  Less,
+  LessOrEqual,
+  Greater,
+  GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArithmeticOp {
+  Add,
+  Subtract,
+  Multiply,
+  Divide,
+  Modulo,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum PathStart {
+  /// A relative path, starting from the context node.
+  Context,
+  /// An absolute path, starting from the root of the context node's tree.
+  Root,
+  /// A path continuing from a filter expression, such as `id('a')/b`.
+  Expr(Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Step {
+  pub(crate) axis: Axis,
+  pub(crate) test: NodeTest,
+  pub(crate) predicates: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Axis {
+  Ancestor,
+  AncestorOrSelf,
+  Attribute,
+  Child,
+  Descendant,
+  DescendantOrSelf,
+  Following,
+  FollowingSibling,
+  Namespace,
+  Parent,
+  Preceding,
+  PrecedingSibling,
+  SelfNode,
+}
+
+impl Axis {
+  pub(crate) fn from_name(name: &str) -> Option<Axis> {
+    Some(match name {
+      "ancestor" => Axis::Ancestor,
+      "ancestor-or-self" => Axis::AncestorOrSelf,
+      "attribute" => Axis::Attribute,
+      "child" => Axis::Child,
+      "descendant" => Axis::Descendant,
+      "descendant-or-self" => Axis::DescendantOrSelf,
+      "following" => Axis::Following,
+      "following-sibling" => Axis::FollowingSibling,
+      "namespace" => Axis::Namespace,
+      "parent" => Axis::Parent,
+      "preceding" => Axis::Preceding,
+      "preceding-sibling" => Axis::PrecedingSibling,
+      "self" => Axis::SelfNode,
+      _ => return None,
+    })
+  }
+
+  /// Whether the axis lists nodes in reverse document order, which is the order
+  /// predicate positions count in.
+  pub(crate) fn is_reverse(self) -> bool {
+    matches!(
+      self,
+      Axis::Ancestor | Axis::AncestorOrSelf | Axis::Preceding | Axis::PrecedingSibling
+    )
+  }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum NodeTest {
+  /// `*`, `prefix:*` or a (qualified) name. `local` is `None` for wildcards.
+  Name {
+    prefix: Option<String>,
+    local: Option<String>,
+  },
+  /// `node()`
+  Node,
+  /// `text()`
+  Text,
+  /// `comment()`
+  Comment,
+  /// `processing-instruction()`, optionally restricted to a target.
+  ProcessingInstruction(Option<String>),
+}
+
+/// The XPath 1.0 core function library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Function {
+  Last,
+  Position,
+  Count,
+  Id,
+  LocalName,
+  NamespaceUri,
+  Name,
+  String,
+  Concat,
+  StartsWith,
+  Contains,
+  SubstringBefore,
+  SubstringAfter,
+  Substring,
+  StringLength,
+  NormalizeSpace,
+  Translate,
+  Boolean,
+  Not,
+  True,
+  False,
+  Lang,
+  Number,
+  Sum,
+  Floor,
+  Ceiling,
+  Round,
+}
+
+impl Function {
+  pub(crate) fn from_name(name: &str) -> Option<Function> {
+    Some(match name {
+      "last" => Function::Last,
+      "position" => Function::Position,
+      "count" => Function::Count,
+      "id" => Function::Id,
+      "local-name" => Function::LocalName,
+      "namespace-uri" => Function::NamespaceUri,
+      "name" => Function::Name,
+      "string" => Function::String,
+      "concat" => Function::Concat,
+      "starts-with" => Function::StartsWith,
+      "contains" => Function::Contains,
+      "substring-before" => Function::SubstringBefore,
+      "substring-after" => Function::SubstringAfter,
+      "substring" => Function::Substring,
+      "string-length" => Function::StringLength,
+      "normalize-space" => Function::NormalizeSpace,
+      "translate" => Function::Translate,
+      "boolean" => Function::Boolean,
+      "not" => Function::Not,
+      "true" => Function::True,
+      "false" => Function::False,
+      "lang" => Function::Lang,
+      "number" => Function::Number,
+      "sum" => Function::Sum,
+      "floor" => Function::Floor,
+      "ceiling" => Function::Ceiling,
+      "round" => Function::Round,
+      _ => return None,
+    })
+  }
+
+  /// The minimum and maximum number of arguments, `None` meaning unbounded.
+  pub(crate) fn arity(self) -> (usize, Option<usize>) {
+    match self {
+      Function::Last | Function::Position | Function::True | Function::False => (0, Some(0)),
+      Function::LocalName
+      | Function::NamespaceUri
+      | Function::Name
+      | Function::String
+      | Function::StringLength
+      | Function::NormalizeSpace
+      | Function::Number => (0, Some(1)),
+      Function::Count
+      | Function::Id
+      | Function::Boolean
+      | Function::Not
+      | Function::Lang
+      | Function::Sum
+      | Function::Floor
+      | Function::Ceiling
+      | Function::Round => (1, Some(1)),
+      Function::StartsWith
+      | Function::Contains
+      | Function::SubstringBefore
+      | Function::SubstringAfter => (2, Some(2)),
+      Function::Substring => (2, Some(3)),
+      Function::Translate => (3, Some(3)),
+      Function::Concat => (2, None),
+    }
+  }
+}
+
+impl Expr {
+  pub(crate) fn parse(input: &str) -> Result<Expr, XPathError> {
+    parser::Parser::new(input)?.parse_expression()
+  }
+}
+
+/// Formats a number as the XPath `string()` function does: integers without a decimal
+/// point, and never in exponent notation.
+pub(crate) fn number_to_string(number: f64) -> String {
+  if number.is_nan() {
+    "NaN".to_string()
+  } else if number.is_infinite() {
+    if number > 0.0 {
+      "Infinity".to_string()
+    } else {
+      "-Infinity".to_string()
+    }
+  } else if number == 0.0 {
+    "0".to_string()
+  } else {
+    number.to_string()
+  }
+}
+
+/// Parses a string as the XPath `number()` function does, returning NaN for anything
+/// that is not an optionally negative decimal number.
+pub(crate) fn string_to_number(string: &str) -> f64 {
+  let trimmed = string.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\r'));
+  let digits = trimmed.strip_prefix('-').unwrap_or(trimmed);
+  let valid = !digits.is_empty()
+    && digits != "."
+    && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+    && digits.matches('.').count() <= 1;
+  if valid {
+    trimmed.parse().unwrap_or(f64::NAN)
+  } else {
+    f64::NAN
+  }
+}
@@ -0,0 +1,675 @@
+use super::{ArithmeticOp, Axis, CompareOp, Expr, Function, NodeTest, PathStart, Step, XPathError};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  LeftParen,
+  RightParen,
+  LeftBracket,
+  RightBracket,
+  Dot,
+  DotDot,
+  At,
+  Comma,
+  ColonColon,
+  Slash,
+  DoubleSlash,
+  Pipe,
+  Plus,
+  Minus,
+  Equal,
+  NotEqual,
+  Less,
+  LessOrEqual,
+  Greater,
+  GreaterOrEqual,
+  /// `*` used as the multiplication operator.
+  Multiply,
+  And,
+  Or,
+  Mod,
+  Div,
+  Literal(String),
+  Number(f64),
+  Variable(String),
+  NameTest {
+    prefix: Option<String>,
+    local: Option<String>,
+  },
+  NodeType(String),
+  FunctionName(String),
+  AxisName(String),
+}
+
+impl Token {
+  /// Whether a `*` or name following this token must be an operator, per the lexical
+  /// disambiguation rules of XPath 1.0 §3.7.
+  fn ends_operand(&self) -> bool {
+    !matches!(
+      self,
+      Token::At
+        | Token::ColonColon
+        | Token::LeftParen
+        | Token::LeftBracket
+        | Token::Comma
+        | Token::Slash
+        | Token::DoubleSlash
+        | Token::Pipe
+        | Token::Plus
+        | Token::Minus
+        | Token::Equal
+        | Token::NotEqual
+        | Token::Less
+        | Token::LessOrEqual
+        | Token::Greater
+        | Token::GreaterOrEqual
+        | Token::Multiply
+        | Token::And
+        | Token::Or
+        | Token::Mod
+        | Token::Div
+    )
+  }
+}
+
+/// How deeply parentheses, predicates and function arguments may nest. Parsing and
+/// evaluation recurse into them, so this bounds the stack they use.
+const MAX_NESTING: usize = 32;
+
+/// How many operators an expression may have. Each one adds a level to the expression
+/// tree, which evaluation recurses through.
+const MAX_OPERATORS: usize = 1024;
+
+/// A recursive descent parser for XPath 1.0 expressions, run over a token list.
+///
+/// Positions reported in errors are character offsets into the input.
+pub(super) struct Parser {
+  tokens: Vec<(Token, usize)>,
+  pos: usize,
+  end: usize,
+  /// The number of parentheses, predicates and argument lists the parser is inside.
+  nesting: usize,
+  /// The number of operators parsed so far.
+  operators: usize,
+}
+
+impl Parser {
+  pub(super) fn new(input: &str) -> Result<Self, XPathError> {
+    let chars: Vec<char> = input.chars().collect();
+    Ok(Parser {
+      tokens: tokenize(&chars)?,
+      pos: 0,
+      end: chars.len(),
+      nesting: 0,
+      operators: 0,
+    })
+  }
+
+  pub(super) fn parse_expression(&mut self) -> Result<Expr, XPathError> {
+    let expr = self.parse_or()?;
+    match self.peek() {
+      None => Ok(expr),
+      Some(_) => Err(self.error("Unexpected token")),
+    }
+  }
+
+  fn parse_or(&mut self) -> Result<Expr, XPathError> {
+    let mut left = self.parse_and()?;
+    while self.eat(&Token::Or) {
+      self.count_operator()?;
+      left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+    }
+    Ok(left)
+  }
+
+  fn parse_and(&mut self) -> Result<Expr, XPathError> {
+    let mut left = self.parse_equality()?;
+    while self.eat(&Token::And) {
+      self.count_operator()?;
+      left = Expr::And(Box::new(left), Box::new(self.parse_equality()?));
+    }
+    Ok(left)
+  }
+
+  fn parse_equality(&mut self) -> Result<Expr, XPathError> {
+    let mut left = self.parse_relational()?;
+    loop {
+      let op = match self.peek() {
+        Some(Token::Equal) => CompareOp::Equal,
+        Some(Token::NotEqual) => CompareOp::NotEqual,
+        _ => return Ok(left),
+      };
+      self.pos += 1;
+      self.count_operator()?;
+      left = Expr::Compare(op, Box::new(left), Box::new(self.parse_relational()?));
+    }
+  }
+
+  fn parse_relational(&mut self) -> Result<Expr, XPathError> {
+    let mut left = self.parse_additive()?;
+    loop {
+      let op = match self.peek() {
+        Some(Token::Less) => CompareOp::Less,
+        Some(Token::LessOrEqual) => CompareOp::LessOrEqual,
+        Some(Token::Greater) => CompareOp::Greater,
+        Some(Token::GreaterOrEqual) => CompareOp::GreaterOrEqual,
+        _ => return Ok(left),
+      };
+      self.pos += 1;
+      self.count_operator()?;
+      left = Expr::Compare(op, Box::new(left), Box::new(self.parse_additive()?));
+    }
+  }
+
+  fn parse_additive(&mut self) -> Result<Expr, XPathError> {
+    let mut left = self.parse_multiplicative()?;
+    loop {
+      let op = match self.peek() {
+        Some(Token::Plus) => ArithmeticOp::Add,
+        Some(Token::Minus) => ArithmeticOp::Subtract,
+        _ => return Ok(left),
+      };
+      self.pos += 1;
+      self.count_operator()?;
+      left = Expr::Arithmetic(op, Box::new(left), Box::new(self.parse_multiplicative()?));
+    }
+  }
+
+  fn parse_multiplicative(&mut self) -> Result<Expr, XPathError> {
+    let mut left = self.parse_unary()?;
+    loop {
+      let op = match self.peek() {
+        Some(Token::Multiply) => ArithmeticOp::Multiply,
+        Some(Token::Div) => ArithmeticOp::Divide,
+        Some(Token::Mod) => ArithmeticOp::Modulo,
+        _ => return Ok(left),
+      };
+      self.pos += 1;
+      self.count_operator()?;
+      left = Expr::Arithmetic(op, Box::new(left), Box::new(self.parse_unary()?));
+    }
+  }
+
+  fn parse_unary(&mut self) -> Result<Expr, XPathError> {
+    let mut negations = 0;
+    while self.eat(&Token::Minus) {
+      self.count_operator()?;
+      negations += 1;
+    }
+    let mut expr = self.parse_union()?;
+    for _ in 0..negations {
+      expr = Expr::Negate(Box::new(expr));
+    }
+    Ok(expr)
+  }
+
+  fn parse_union(&mut self) -> Result<Expr, XPathError> {
+    let mut left = self.parse_path()?;
+    while self.eat(&Token::Pipe) {
+      self.count_operator()?;
+      left = Expr::Union(Box::new(left), Box::new(self.parse_path()?));
+    }
+    Ok(left)
+  }
+
+  fn parse_path(&mut self) -> Result<Expr, XPathError> {
+    match self.peek() {
+      Some(
+        Token::Literal(_)
+        | Token::Number(_)
+        | Token::Variable(_)
+        | Token::FunctionName(_)
+        | Token::LeftParen,
+      ) => {
+        let primary = self.parse_primary()?;
+        let predicates = self.parse_predicates()?;
+        let filter = if predicates.is_empty() {
+          primary
+        } else {
+          Expr::Filter(Box::new(primary), predicates)
+        };
+        let mut steps = Vec::new();
+        if !self.parse_step_separator(&mut steps) {
+          return Ok(filter);
+        }
+        self.parse_relative_path(&mut steps)?;
+        Ok(Expr::Path(PathStart::Expr(Box::new(filter)), steps))
+      }
+      Some(Token::Slash) => {
+        self.pos += 1;
+        let mut steps = Vec::new();
+        if self.at_step_start() {
+          self.parse_relative_path(&mut steps)?;
+        }
+        Ok(Expr::Path(PathStart::Root, steps))
+      }
+      Some(Token::DoubleSlash) => {
+        self.pos += 1;
+        let mut steps = vec![descendant_or_self_step()];
+        self.parse_relative_path(&mut steps)?;
+        Ok(Expr::Path(PathStart::Root, steps))
+      }
+      _ => {
+        let mut steps = Vec::new();
+        self.parse_relative_path(&mut steps)?;
+        Ok(Expr::Path(PathStart::Context, steps))
+      }
+    }
+  }
+
+  fn parse_relative_path(&mut self, steps: &mut Vec<Step>) -> Result<(), XPathError> {
+    loop {
+      steps.push(self.parse_step()?);
+      if !self.parse_step_separator(steps) {
+        return Ok(());
+      }
+    }
+  }
+
+  /// Consumes a `/` or `//` between steps, expanding `//` to
+  /// `/descendant-or-self::node()/`.
+  fn parse_step_separator(&mut self, steps: &mut Vec<Step>) -> bool {
+    match self.peek() {
+      Some(Token::Slash) => {
+        self.pos += 1;
+        true
+      }
+      Some(Token::DoubleSlash) => {
+        self.pos += 1;
+        steps.push(descendant_or_self_step());
+        true
+      }
+      _ => false,
+    }
+  }
+
+  fn at_step_start(&self) -> bool {
+    matches!(
+      self.peek(),
+      Some(
+        Token::Dot
+          | Token::DotDot
+          | Token::At
+          | Token::AxisName(_)
+          | Token::NameTest { .. }
+          | Token::NodeType(_)
+      )
+    )
+  }
+
+  fn parse_step(&mut self) -> Result<Step, XPathError> {
+    let axis = match self.peek() {
+      Some(Token::Dot) => {
+        self.pos += 1;
+        return Ok(Step {
+          axis: Axis::SelfNode,
+          test: NodeTest::Node,
+          predicates: Vec::new(),
+        });
+      }
+      Some(Token::DotDot) => {
+        self.pos += 1;
+        return Ok(Step {
+          axis: Axis::Parent,
+          test: NodeTest::Node,
+          predicates: Vec::new(),
+        });
+      }
+      Some(Token::At) => {
+        self.pos += 1;
+        Axis::Attribute
+      }
+      Some(Token::AxisName(name)) => {
+        let axis =
+          Axis::from_name(name).ok_or_else(|| self.error(format!("Unknown axis '{}'", name)))?;
+        self.pos += 1;
+        self.expect(&Token::ColonColon, "Expected '::'")?;
+        axis
+      }
+      _ => Axis::Child,
+    };
+    let test = self.parse_node_test()?;
+    let predicates = self.parse_predicates()?;
+    Ok(Step {
+      axis,
+      test,
+      predicates,
+    })
+  }
+
+  fn parse_node_test(&mut self) -> Result<NodeTest, XPathError> {
+    match self.peek().cloned() {
+      Some(Token::NameTest { prefix, local }) => {
+        self.pos += 1;
+        Ok(NodeTest::Name { prefix, local })
+      }
+      Some(Token::NodeType(name)) => {
+        self.pos += 1;
+        self.expect(&Token::LeftParen, "Expected '('")?;
+        let test = match name.as_str() {
+          "node" => NodeTest::Node,
+          "text" => NodeTest::Text,
+          "comment" => NodeTest::Comment,
+          _ => match self.peek() {
+            Some(Token::Literal(target)) => {
+              let target = target.clone();
+              self.pos += 1;
+              NodeTest::ProcessingInstruction(Some(target))
+            }
+            _ => NodeTest::ProcessingInstruction(None),
+          },
+        };
+        self.expect(&Token::RightParen, "Expected ')'")?;
+        Ok(test)
+      }
+      _ => Err(self.error("Expected a node test")),
+    }
+  }
+
+  fn parse_predicates(&mut self) -> Result<Vec<Expr>, XPathError> {
+    let mut predicates = Vec::new();
+    while self.eat(&Token::LeftBracket) {
+      predicates.push(self.parse_nested()?);
+      self.expect(&Token::RightBracket, "Expected ']'")?;
+    }
+    Ok(predicates)
+  }
+
+  fn parse_primary(&mut self) -> Result<Expr, XPathError> {
+    let start = self.pos;
+    match self.peek().cloned() {
+      Some(Token::Literal(value)) => {
+        self.pos += 1;
+        Ok(Expr::Literal(value))
+      }
+      Some(Token::Number(value)) => {
+        self.pos += 1;
+        Ok(Expr::Number(value))
+      }
+      Some(Token::Variable(name)) => {
+        Err(self.error(format!("Variable references are not supported: ${}", name)))
+      }
+      Some(Token::LeftParen) => {
+        self.pos += 1;
+        let expr = self.parse_nested()?;
+        self.expect(&Token::RightParen, "Expected ')'")?;
+        Ok(expr)
+      }
+      Some(Token::FunctionName(name)) => {
+        let function = Function::from_name(&name)
+          .ok_or_else(|| self.error(format!("Unknown function '{}'", name)))?;
+        self.pos += 1;
+        self.expect(&Token::LeftParen, "Expected '('")?;
+        let mut args = Vec::new();
+        if !self.eat(&Token::RightParen) {
+          loop {
+            args.push(self.parse_nested()?);
+            if self.eat(&Token::RightParen) {
+              break;
+            }
+            self.expect(&Token::Comma, "Expected ',' or ')'")?;
+          }
+        }
+        let (min, max) = function.arity();
+        if args.len() < min || max.is_some_and(|max| args.len() > max) {
+          return Err(self.error_at(
+            self.position_of(start),
+            format!("Wrong number of arguments to {}()", name),
+          ));
+        }
+        Ok(Expr::Function(function, args))
+      }
+      _ => Err(self.error("Expected an expression")),
+    }
+  }
+
+  /// Parses an expression inside parentheses, a predicate or an argument list.
+  fn parse_nested(&mut self) -> Result<Expr, XPathError> {
+    if self.nesting == MAX_NESTING {
+      return Err(self.error("Expression is nested too deeply"));
+    }
+    self.nesting += 1;
+    let expr = self.parse_or();
+    self.nesting -= 1;
+    expr
+  }
+
+  /// Counts an operator just consumed, failing once there are too many.
+  fn count_operator(&mut self) -> Result<(), XPathError> {
+    if self.operators == MAX_OPERATORS {
+      return Err(self.error_at(
+        self.position_of(self.pos - 1),
+        "Expression has too many operators",
+      ));
+    }
+    self.operators += 1;
+    Ok(())
+  }
+
+  fn eat(&mut self, token: &Token) -> bool {
+    if self.peek() == Some(token) {
+      self.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn expect(&mut self, token: &Token, message: &str) -> Result<(), XPathError> {
+    if self.eat(token) {
+      Ok(())
+    } else {
+      Err(self.error(message))
+    }
+  }
+
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos).map(|(token, _)| token)
+  }
+
+  fn position_of(&self, index: usize) -> usize {
+    self
+      .tokens
+      .get(index)
+      .map(|(_, position)| *position)
+      .unwrap_or(self.end)
+  }
+
+  fn error(&self, message: impl Into<String>) -> XPathError {
+    self.error_at(self.position_of(self.pos), message)
+  }
+
+  fn error_at(&self, position: usize, message: impl Into<String>) -> XPathError {
+    XPathError::Syntax {
+      message: message.into(),
+      position,
+    }
+  }
+}
+
+fn descendant_or_self_step() -> Step {
+  Step {
+    axis: Axis::DescendantOrSelf,
+    test: NodeTest::Node,
+    predicates: Vec::new(),
+  }
+}
+
+fn tokenize(chars: &[char]) -> Result<Vec<(Token, usize)>, XPathError> {
+  let error = |message: &str, position: usize| XPathError::Syntax {
+    message: message.to_string(),
+    position,
+  };
+  let mut tokens: Vec<(Token, usize)> = Vec::new();
+  let mut pos = 0;
+  while pos < chars.len() {
+    let start = pos;
+    let c = chars[pos];
+    let next = chars.get(pos + 1).copied();
+    let operand_before = tokens.last().is_some_and(|(token, _)| token.ends_operand());
+    let token = match c {
+      ' ' | '\t' | '\n' | '\r' => {
+        pos += 1;
+        continue;
+      }
+      '(' => Token::LeftParen,
+      ')' => Token::RightParen,
+      '[' => Token::LeftBracket,
+      ']' => Token::RightBracket,
+      ',' => Token::Comma,
+      '@' => Token::At,
+      '|' => Token::Pipe,
+      '+' => Token::Plus,
+      '-' => Token::Minus,
+      '=' => Token::Equal,
+      '!' if next == Some('=') => {
+        pos += 1;
+        Token::NotEqual
+      }
+      '<' if next == Some('=') => {
+        pos += 1;
+        Token::LessOrEqual
+      }
+      '<' => Token::Less,
+      '>' if next == Some('=') => {
+        pos += 1;
+        Token::GreaterOrEqual
+      }
+      '>' => Token::Greater,
+      '/' if next == Some('/') => {
+        pos += 1;
+        Token::DoubleSlash
+      }
+      '/' => Token::Slash,
+      ':' if next == Some(':') => {
+        pos += 1;
+        Token::ColonColon
+      }
+      '.' if next == Some('.') => {
+        pos += 1;
+        Token::DotDot
+      }
+      '.' if !next.is_some_and(|c| c.is_ascii_digit()) => Token::Dot,
+      '*' if operand_before => Token::Multiply,
+      '*' => Token::NameTest {
+        prefix: None,
+        local: None,
+      },
+      '"' | '\'' => {
+        let Some(length) = chars[pos + 1..].iter().position(|&q| q == c) else {
+          return Err(error("Unterminated string literal", start));
+        };
+        let value = chars[pos + 1..pos + 1 + length].iter().collect();
+        pos += length + 1;
+        Token::Literal(value)
+      }
+      '0'..='9' | '.' => {
+        let mut end = pos;
+        while end < chars.len() && chars[end].is_ascii_digit() {
+          end += 1;
+        }
+        if chars.get(end) == Some(&'.') {
+          end += 1;
+          while end < chars.len() && chars[end].is_ascii_digit() {
+            end += 1;
+          }
+        }
+        let text: String = chars[pos..end].iter().collect();
+        pos = end - 1;
+        Token::Number(text.parse().map_err(|_| error("Invalid number", start))?)
+      }
+      '$' => {
+        let (name, end) =
+          read_qname(chars, pos + 1).ok_or_else(|| error("Expected a variable name", start + 1))?;
+        pos = end - 1;
+        Token::Variable(name)
+      }
+      c if is_name_start_char(c) => {
+        let name_end = read_ncname(chars, pos);
+        let name: String = chars[pos..name_end].iter().collect();
+        if operand_before {
+          pos = name_end - 1;
+          match name.as_str() {
+            "and" => Token::And,
+            "or" => Token::Or,
+            "mod" => Token::Mod,
+            "div" => Token::Div,
+            _ => return Err(error("Expected an operator", start)),
+          }
+        } else {
+          let mut end = name_end;
+          let mut prefix = None;
+          let mut local = Some(name.clone());
+          if chars.get(end) == Some(&':') && chars.get(end + 1) != Some(&':') {
+            match chars.get(end + 1) {
+              Some('*') => {
+                prefix = Some(name.clone());
+                local = None;
+                end += 2;
+              }
+              Some(&c) if is_name_start_char(c) => {
+                let local_end = read_ncname(chars, end + 1);
+                prefix = Some(name.clone());
+                local = Some(chars[end + 1..local_end].iter().collect());
+                end = local_end;
+              }
+              _ => return Err(error("Expected a local name", end + 1)),
+            }
+          }
+          pos = end - 1;
+
+          let mut lookahead = end;
+          while matches!(chars.get(lookahead), Some(' ' | '\t' | '\n' | '\r')) {
+            lookahead += 1;
+          }
+          let qualified = match &prefix {
+            Some(prefix) => format!("{}:{}", prefix, local.as_deref().unwrap_or("*")),
+            None => name.clone(),
+          };
+          match chars.get(lookahead) {
+            Some('(') if prefix.is_none() && is_node_type(&name) => Token::NodeType(name),
+            Some('(') if local.is_some() => Token::FunctionName(qualified),
+            Some(':') if prefix.is_none() && chars.get(lookahead + 1) == Some(&':') => {
+              Token::AxisName(name)
+            }
+            _ => Token::NameTest { prefix, local },
+          }
+        }
+      }
+      _ => return Err(error(&format!("Unexpected character '{}'", c), start)),
+    };
+    pos += 1;
+    tokens.push((token, start));
+  }
+  Ok(tokens)
+}
+
+fn read_ncname(chars: &[char], start: usize) -> usize {
+  let mut end = start;
+  while end < chars.len() && is_name_char(chars[end]) {
+    end += 1;
+  }
+  end
+}
+
+fn read_qname(chars: &[char], start: usize) -> Option<(String, usize)> {
+  if !chars.get(start).is_some_and(|&c| is_name_start_char(c)) {
+    return None;
+  }
+  let mut end = read_ncname(chars, start);
+  if chars.get(end) == Some(&':') && chars.get(end + 1).is_some_and(|&c| is_name_start_char(c)) {
+    end = read_ncname(chars, end + 1);
+  }
+  Some((chars[start..end].iter().collect(), end))
+}
+
+fn is_node_type(name: &str) -> bool {
+  matches!(name, "comment" | "text" | "processing-instruction" | "node")
+}
+
+fn is_name_start_char(c: char) -> bool {
+  c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name_char(c: char) -> bool {
+  is_name_start_char(c) || c.is_ascii_digit() || c == '-' || c == '.'
+}
@@ -0,0 +1,53 @@
+use domparser::LimitError;
+use napi::bindgen_prelude::{FnArgs, Function, JsObjectValue, Object, Unknown};
+use napi::{Env, JsValue};
+
+/// Throws a `DOMException` with the given name, as browsers do from the equivalent DOM
+/// APIs. When `position` is set it is exposed as a property of the same name.
+pub(crate) fn dom_exception(
+  env: &Env,
+  message: String,
+  name: &str,
+  position: Option<usize>,
+) -> napi::Error {
+  let exception = env.get_global().and_then(|global| {
+    let constructor: Function<FnArgs<(String, String)>, Unknown> =
+      global.get_named_property("DOMException")?;
+    let mut exception: Object = constructor
+      .new_instance(FnArgs::from((message.clone(), name.to_string())))?
+      .coerce_to_object()?;
+    if let Some(position) = position {
+      exception.set_named_property("position", position as u32)?;
+    }
+    Ok(exception)
+  });
+  // `DOMException` is not a native error object, so napi-rs would re-wrap it if returned
+  // as the error value. Throw it directly and report the exception as pending instead.
+  match exception.and_then(|exception| env.throw(exception)) {
+    Ok(()) => napi::Error::new(napi::Status::PendingException, message),
+    // Runtimes without a global `DOMException` still get a descriptive error.
+    Err(_) => napi::Error::new(napi::Status::InvalidArg, message),
+  }
+}
+
+/// Throws a JavaScript `TypeError`.
+pub(crate) fn type_error(env: &Env, message: &str) -> napi::Error {
+  match env.throw_type_error(message, None) {
+    Ok(()) => napi::Error::new(napi::Status::PendingException, message),
+    Err(e) => e,
+  }
+}
+
+/// A JavaScript `RangeError` for input that exceeds the parse limits. It is returned
+/// as the error value rather than thrown, so that it can also reject a promise.
+pub(crate) fn limit_error(env: &Env, error: LimitError) -> napi::Error {
+  let message = error.to_string();
+  let range_error = env.get_global().and_then(|global| {
+    let constructor: Function<String, Unknown> = global.get_named_property("RangeError")?;
+    constructor.new_instance(message.clone())
+  });
+  match range_error {
+    Ok(range_error) => napi::Error::from(range_error),
+    Err(_) => napi::Error::new(napi::Status::GenericFailure, message),
+  }
+}
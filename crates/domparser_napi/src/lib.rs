@@ -1,14 +1,150 @@
 #[macro_use]
 extern crate napi_derive;
 
-use domparser::parse as parse_core;
+use domparser::{
+  parse_bytes_with_limits, parse_fragment_with_limits, parse_from_string_with_limits,
+  parse_with_diagnostics as parse_with_diagnostics_core, parse_with_limits, DomNode, LimitError,
+  MimeType,
+};
+use napi::bindgen_prelude::{AsyncTask, Buffer, ClassInstance, Either};
+use napi::Env;
 use node_repr::NodeRepr;
+use parser::{ParseOptions, ParseResult};
+use tasks::ParseTask;
 
+mod error;
 mod node_repr;
+mod parser;
+mod selector;
+mod serializer;
+mod tasks;
+mod xpath;
 
 /// Parse string input to a html tree, return the root node.
-///
+/// A `Buffer` is decoded with the encoding sniffed from its bytes.
 #[napi]
-pub fn parse(html: String) -> NodeRepr {
-  NodeRepr(parse_core(html))
+pub fn parse(
+  env: Env,
+  html: Either<String, Buffer>,
+  options: Option<ParseOptions>,
+) -> napi::Result<NodeRepr> {
+  let transport_charset = options
+    .as_ref()
+    .and_then(|options| options.transport_charset.clone());
+  let limits = options.as_ref().and_then(|options| options.limits);
+  let options = ParseOptions::resolve(&env, options)?;
+  parse_input(
+    html,
+    &options,
+    transport_charset.as_deref(),
+    limits.map(Into::into),
+  )
+  .map(NodeRepr)
+  .map_err(|error| error::limit_error(&env, error))
+}
+
+/// Parse input like `parse` on the thread pool, so that large documents do not block
+/// the event loop. Resolves to the root node.
+#[napi(js_name = "parseAsync", ts_return_type = "Promise<NodeRepr>")]
+pub fn parse_async(
+  env: Env,
+  html: Either<String, Buffer>,
+  options: Option<ParseOptions>,
+) -> napi::Result<AsyncTask<ParseTask>> {
+  let transport_charset = options
+    .as_ref()
+    .and_then(|options| options.transport_charset.clone());
+  let limits = options.as_ref().and_then(|options| options.limits);
+  let options = ParseOptions::resolve(&env, options)?;
+  Ok(AsyncTask::new(ParseTask {
+    // The buffer is copied, as JavaScript may change it while the task runs.
+    html: match html {
+      Either::A(html) => Either::A(html),
+      Either::B(bytes) => Either::B(bytes.to_vec()),
+    },
+    options,
+    transport_charset,
+    limits: limits.map(Into::into),
+  }))
+}
+
+/// Parses a document given as a string or as bytes, within `limits` if any.
+fn parse_input<B: AsRef<[u8]>>(
+  html: Either<String, B>,
+  options: &domparser::ParseOptions,
+  transport_charset: Option<&str>,
+  limits: Option<domparser::ParseLimits>,
+) -> Result<DomNode, LimitError> {
+  let limits = limits.unwrap_or_default();
+  match html {
+    Either::A(html) => parse_with_limits(html, options, limits),
+    Either::B(bytes) => parse_bytes_with_limits(bytes.as_ref(), transport_charset, options, limits),
+  }
+}
+
+/// Parse an XML document of the given MIME type, "application/xml" by default. A
+/// document that is not well-formed becomes a `<parsererror>` document. Of `options`,
+/// only `limits` applies.
+#[napi(js_name = "parseXml")]
+pub fn parse_xml(
+  env: Env,
+  xml: String,
+  mime_type: Option<String>,
+  options: Option<ParseOptions>,
+) -> napi::Result<NodeRepr> {
+  let mime_type = match mime_type.as_deref() {
+    None => MimeType::ApplicationXml,
+    Some(name) => match MimeType::from_mime(name) {
+      Some(mime_type) if !mime_type.is_html() => mime_type,
+      _ => {
+        return Err(error::type_error(
+          &env,
+          &format!("Unsupported XML mime type: {}", name),
+        ))
+      }
+    },
+  };
+  let limits = options.and_then(|options| options.limits);
+  parse_from_string_with_limits(
+    xml,
+    mime_type,
+    &Default::default(),
+    limits.map(Into::into).unwrap_or_default(),
+  )
+  .map(NodeRepr)
+  .map_err(|error| error::limit_error(&env, error))
+}
+
+/// Parse string input like `parse`, also returning the parse errors with their
+/// line and column.
+#[napi(js_name = "parseWithDiagnostics")]
+pub fn parse_with_diagnostics(
+  env: Env,
+  html: String,
+  options: Option<ParseOptions>,
+) -> napi::Result<ParseResult> {
+  let options = ParseOptions::resolve(&env, options)?;
+  Ok(parse_with_diagnostics_core(html, &options).into())
+}
+
+/// Parse string input as the content of `context`, an element or a tag name, the
+/// way `innerHTML` reads it. Returns a document fragment holding the nodes.
+#[napi(js_name = "parseFragment")]
+pub fn parse_fragment(
+  env: Env,
+  html: String,
+  context: Either<String, ClassInstance<NodeRepr>>,
+  options: Option<ParseOptions>,
+) -> napi::Result<NodeRepr> {
+  let limits = options
+    .as_ref()
+    .and_then(|options| options.limits)
+    .map_or_else(Default::default, Into::into);
+  let options = ParseOptions::resolve(&env, options)?;
+  match context {
+    Either::A(tag_name) => parse_fragment_with_limits(html, tag_name.as_str(), &options, limits),
+    Either::B(element) => parse_fragment_with_limits(html, &element.0, &options, limits),
+  }
+  .map(NodeRepr)
+  .map_err(|error| error::limit_error(&env, error))
 }
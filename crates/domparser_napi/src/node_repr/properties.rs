@@ -1,4 +1,8 @@
 use super::NodeRepr;
+use crate::error::limit_error;
+use crate::parser::SourceCodeLocation;
+use domparser::QuirksMode;
+use napi::Env;
 use std::collections::HashMap;
 
 #[napi]
@@ -227,8 +231,11 @@ impl NodeRepr {
 
   /// Sets the HTML serialization of the element's descendants.
   #[napi(setter, js_name = "innerHTML")]
-  pub fn set_inner_html(&self, html: String) {
-    self.0.set_inner_html(html);
+  pub fn set_inner_html(&self, env: Env, html: String) -> napi::Result<()> {
+    self
+      .0
+      .set_inner_html(html)
+      .map_err(|error| limit_error(&env, error))
   }
 
   /// Returns the HTML serialization of the element and its descendants.
@@ -239,8 +246,11 @@ impl NodeRepr {
 
   /// Sets the HTML serialization of the element and its descendants.
   #[napi(setter, js_name = "outerHTML")]
-  pub fn set_outer_html(&self, html: String) {
-    self.0.set_outer_html(html);
+  pub fn set_outer_html(&self, env: Env, html: String) -> napi::Result<()> {
+    self
+      .0
+      .set_outer_html(html)
+      .map_err(|error| limit_error(&env, error))
   }
 
   /// Returns the top-level document object for this node.
@@ -334,8 +344,11 @@ impl NodeRepr {
 
   /// Parses the specified text as HTML or XML and inserts the resulting nodes into the DOM tree at a specified position.
   #[napi(js_name = "insertAdjacentHTML")]
-  pub fn insert_adjacent_html(&self, position: String, html: String) {
-    self.0.insert_adjacent_html(position, html);
+  pub fn insert_adjacent_html(&self, env: Env, position: String, html: String) -> napi::Result<()> {
+    self
+      .0
+      .insert_adjacent_html(position, html)
+      .map_err(|error| limit_error(&env, error))
   }
 
   /// Inserts a given text node at a given position relative to the element it is invoked upon.
@@ -373,4 +386,45 @@ impl NodeRepr {
   pub fn compare_document_position(&self, other: &NodeRepr) -> u32 {
     self.0.compare_document_position(&other.0)
   }
+
+  /// Where this node came from in the parsed source, for documents parsed with
+  /// `sourceCodeLocationInfo`, or null.
+  #[napi(getter, js_name = "sourceCodeLocation")]
+  pub fn source_code_location(&self) -> Option<SourceCodeLocation> {
+    self.0.source_range().map(Into::into)
+  }
+
+  /// The name of the encoding the document was decoded from, such as "UTF-8".
+  #[napi(getter, js_name = "characterSet")]
+  pub fn character_set(&self) -> String {
+    self.0.character_set()
+  }
+
+  /// The MIME type of the document, such as "text/html" or "image/svg+xml".
+  #[napi(getter, js_name = "contentType")]
+  pub fn content_type(&self) -> String {
+    self.0.content_type()
+  }
+
+  /// The rendering mode of the document: "BackCompat" in quirks mode, "CSS1Compat"
+  /// otherwise.
+  #[napi(getter, js_name = "compatMode")]
+  pub fn compat_mode(&self) -> String {
+    self.0.compat_mode()
+  }
+
+  /// The quirks mode of the document, from its doctype unless the parse forced one.
+  #[napi(
+    getter,
+    js_name = "quirksMode",
+    ts_return_type = "'quirks' | 'limited-quirks' | 'no-quirks'"
+  )]
+  pub fn quirks_mode(&self) -> String {
+    match self.0.quirks_mode() {
+      QuirksMode::Quirks => "quirks",
+      QuirksMode::LimitedQuirks => "limited-quirks",
+      QuirksMode::NoQuirks => "no-quirks",
+    }
+    .to_string()
+  }
 }
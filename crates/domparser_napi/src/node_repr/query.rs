@@ -1,19 +1,44 @@
 use super::NodeRepr;
+use crate::selector::{syntax_error, SelectorRepr};
+use crate::serializer::{html_string, HtmlChunks, SerializeOptions};
+use crate::xpath::{xpath_error, XPathResultRepr, ANY_TYPE};
+use napi::bindgen_prelude::{Either, Function, JsObjectValue, Object};
+use napi::Env;
 
 #[napi]
 impl NodeRepr {
   /// Selects the first element that matches the specified selector string.
   #[napi]
-  pub fn select(&self, selectors: String) -> Option<NodeRepr> {
-    self.0.select(selectors).map(NodeRepr)
+  pub fn select(&self, env: Env, selectors: String) -> napi::Result<Option<NodeRepr>> {
+    self
+      .0
+      .select(selectors)
+      .map(|node| node.map(NodeRepr))
+      .map_err(|e| syntax_error(&env, e))
   }
 
   /// Selects all elements that match the specified selector string.
   #[napi]
-  pub fn select_all(&self, selectors: String) -> Vec<NodeRepr> {
+  pub fn select_all(&self, env: Env, selectors: String) -> napi::Result<Vec<NodeRepr>> {
     self
       .0
       .select_all(selectors)
+      .map(|nodes| nodes.into_iter().map(NodeRepr).collect())
+      .map_err(|e| syntax_error(&env, e))
+  }
+
+  /// Selects the first element that matches the precompiled selector.
+  #[napi(js_name = "selectCompiled")]
+  pub fn select_compiled(&self, selector: &SelectorRepr) -> Option<NodeRepr> {
+    self.0.select_compiled(&selector.0).map(NodeRepr)
+  }
+
+  /// Selects all elements that match the precompiled selector.
+  #[napi(js_name = "selectAllCompiled")]
+  pub fn select_all_compiled(&self, selector: &SelectorRepr) -> Vec<NodeRepr> {
+    self
+      .0
+      .select_all_compiled(&selector.0)
       .into_iter()
       .map(NodeRepr)
       .collect()
@@ -43,16 +68,36 @@ impl NodeRepr {
     self.0.has_child_nodes()
   }
 
-  /// Returns the HTML serialization of the element and its descendants.
+  /// Returns the HTML serialization of the element and its descendants, formatted or
+  /// minified as `options` ask.
+  #[napi]
+  pub fn outer_html(&self, env: Env, options: Option<SerializeOptions>) -> napi::Result<String> {
+    html_string(&env, &self.0, true, options)
+  }
+
+  /// Returns the HTML serialization of the element's descendants, formatted or
+  /// minified as `options` ask.
   #[napi]
-  pub fn outer_html(&self) -> String {
-    self.0.outer_html()
+  pub fn inner_html(&self, env: Env, options: Option<SerializeOptions>) -> napi::Result<String> {
+    html_string(&env, &self.0, false, options)
+  }
+
+  /// Returns the HTML serialization of the element and its descendants as chunks,
+  /// formatted or minified as `options` ask, each written when it is asked for.
+  #[napi(js_name = "_htmlChunks")]
+  pub fn html_chunks(
+    &self,
+    env: Env,
+    options: Option<SerializeOptions>,
+  ) -> napi::Result<HtmlChunks> {
+    HtmlChunks::new(&env, &self.0, options)
   }
 
-  /// Returns the HTML serialization of the element's descendants.
+  /// Returns the XML serialization of the node and its descendants, declaring the
+  /// namespaces they are in.
   #[napi]
-  pub fn inner_html(&self) -> String {
-    self.0.inner_html()
+  pub fn outer_xml(&self) -> String {
+    self.0.outer_xml()
   }
 
   /// Returns the text content of the node and its descendants.
@@ -63,19 +108,22 @@ impl NodeRepr {
 
   /// Returns the first Element within the document that matches the specified selector, or group of selectors.
   #[napi(js_name = "querySelector")]
-  pub fn query_selector(&self, selectors: String) -> Option<NodeRepr> {
-    self.0.query_selector(selectors).map(NodeRepr)
+  pub fn query_selector(&self, env: Env, selectors: String) -> napi::Result<Option<NodeRepr>> {
+    self
+      .0
+      .query_selector(selectors)
+      .map(|node| node.map(NodeRepr))
+      .map_err(|e| syntax_error(&env, e))
   }
 
   /// Returns a static (not live) NodeList representing a list of the document's elements that match the specified group of selectors.
   #[napi(js_name = "querySelectorAll")]
-  pub fn query_selector_all(&self, selectors: String) -> Vec<NodeRepr> {
+  pub fn query_selector_all(&self, env: Env, selectors: String) -> napi::Result<Vec<NodeRepr>> {
     self
       .0
       .query_selector_all(selectors)
-      .into_iter()
-      .map(NodeRepr)
-      .collect()
+      .map(|nodes| nodes.into_iter().map(NodeRepr).collect())
+      .map_err(|e| syntax_error(&env, e))
   }
 
   /// Returns a boolean value indicating whether the specified element has the specified attribute or not.
@@ -168,13 +216,59 @@ impl NodeRepr {
 
   /// Returns a boolean value indicating whether the element would be selected by the specified selector string.
   #[napi(js_name = "matches")]
-  pub fn matches(&self, selectors: String) -> bool {
-    self.0.matches(selectors)
+  pub fn matches(&self, env: Env, selectors: String) -> napi::Result<bool> {
+    self.0.matches(selectors).map_err(|e| syntax_error(&env, e))
+  }
+
+  /// Returns a boolean value indicating whether the element would be selected by the precompiled selector.
+  #[napi(js_name = "matchesCompiled")]
+  pub fn matches_compiled(&self, selector: &SelectorRepr) -> bool {
+    self.0.matches_compiled(&selector.0)
   }
 
   /// Returns the closest ancestor of the current element (or the current element itself) which matches the selectors given in parameter.
   #[napi]
-  pub fn closest(&self, selectors: String) -> Option<NodeRepr> {
-    self.0.closest(selectors).map(NodeRepr)
+  pub fn closest(&self, env: Env, selectors: String) -> napi::Result<Option<NodeRepr>> {
+    self
+      .0
+      .closest(selectors)
+      .map(|node| node.map(NodeRepr))
+      .map_err(|e| syntax_error(&env, e))
+  }
+
+  /// Returns the closest ancestor of the current element (or the current element itself) which matches the precompiled selector.
+  #[napi(js_name = "closestCompiled")]
+  pub fn closest_compiled(&self, selector: &SelectorRepr) -> Option<NodeRepr> {
+    self.0.closest_compiled(&selector.0).map(NodeRepr)
+  }
+
+  /// Evaluates an XPath 1.0 expression against `contextNode`, or this node if it is
+  /// omitted. `resolver` maps namespace prefixes to URIs, either as a function or as an
+  /// object with a `lookupNamespaceURI` method.
+  #[napi(
+    ts_args_type = "expression: string, contextNode?: NodeRepr | null, resolver?: ((prefix: string) => string | null) | { lookupNamespaceURI(prefix: string): string | null } | null, type?: number, result?: XPathResult | null"
+  )]
+  pub fn evaluate(
+    &self,
+    env: Env,
+    expression: String,
+    context_node: Option<&NodeRepr>,
+    resolver: Option<Either<Function<String, Option<String>>, Object>>,
+    result_type: Option<u32>,
+    _result: Option<&XPathResultRepr>,
+  ) -> napi::Result<XPathResultRepr> {
+    let context = context_node.map_or(&self.0, |node| &node.0);
+    let value = context
+      .evaluate_xpath_with_resolver(&expression, |prefix| match &resolver {
+        Some(Either::A(function)) => function.call(prefix.to_string()).ok().flatten(),
+        Some(Either::B(object)) => object
+          .get_named_property::<Function<String, Option<String>>>("lookupNamespaceURI")
+          .and_then(|lookup| lookup.apply(object, prefix.to_string()))
+          .ok()
+          .flatten(),
+        None => None,
+      })
+      .map_err(|e| xpath_error(&env, e))?;
+    XPathResultRepr::new(&env, value, result_type.unwrap_or(ANY_TYPE))
   }
 }
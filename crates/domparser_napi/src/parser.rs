@@ -0,0 +1,249 @@
+use domparser::{DocumentParser, QuirksMode, SourceRange, SourceSpan};
+use napi::bindgen_prelude::{Buffer, Either};
+use napi::Env;
+
+use crate::error::{dom_exception, limit_error, type_error};
+use crate::node_repr::NodeRepr;
+
+/// Options for `parse` and `DOMParser.parseFromString`.
+#[napi(object)]
+pub struct ParseOptions {
+  /// Parse as if scripting were enabled, which makes `<noscript>` content raw text.
+  /// Defaults to true.
+  pub scripting_enabled: Option<bool>,
+  /// Parse the document as the `srcdoc` of an `iframe`. Defaults to false.
+  pub iframe_srcdoc: Option<bool>,
+  /// Forces the document into this quirks mode, whatever its doctype says. The tree
+  /// builder also starts in it, which decides how an `iframe` `srcdoc` without a
+  /// doctype is built; other documents are still built as their doctype asks.
+  #[napi(ts_type = "'quirks' | 'limited-quirks' | 'no-quirks'")]
+  pub quirks_mode: Option<String>,
+  /// Report every parse error the spec describes, at some cost in speed. Defaults to
+  /// false.
+  pub exact_errors: Option<bool>,
+  /// Record where each node came from in the input, for `sourceCodeLocation`.
+  /// Defaults to false.
+  pub source_code_location_info: Option<bool>,
+  /// The charset of the `Content-Type` header a `Buffer` was served with. A byte
+  /// order mark still takes precedence.
+  pub transport_charset: Option<String>,
+  /// Limits for untrusted input, which also apply to markup later parsed into the
+  /// document. Exceeding one throws a `RangeError`.
+  pub limits: Option<ParseLimits>,
+}
+
+/// Limits on what a parse may produce. Every limit is off unless set.
+#[napi(object)]
+#[derive(Clone, Copy)]
+pub struct ParseLimits {
+  /// The most ancestors a node may have, counting the document.
+  pub max_depth: Option<u32>,
+  /// The most nodes a single parse may create.
+  pub max_nodes: Option<u32>,
+  /// The most attributes an element may have.
+  pub max_attributes: Option<u32>,
+  /// The longest input, in bytes, that is parsed at all.
+  pub max_input_size: Option<u32>,
+}
+
+impl From<ParseLimits> for domparser::ParseLimits {
+  fn from(limits: ParseLimits) -> Self {
+    domparser::ParseLimits {
+      max_depth: limits.max_depth.map(|limit| limit as usize),
+      max_nodes: limits.max_nodes.map(|limit| limit as usize),
+      max_attributes: limits.max_attributes.map(|limit| limit as usize),
+      max_input_size: limits.max_input_size.map(|limit| limit as usize),
+    }
+  }
+}
+
+impl ParseOptions {
+  /// Converts optional options from JavaScript, using the defaults when absent.
+  pub(crate) fn resolve(
+    env: &Env,
+    options: Option<ParseOptions>,
+  ) -> napi::Result<domparser::ParseOptions> {
+    match options {
+      Some(options) => options.to_core(env),
+      None => Ok(Default::default()),
+    }
+  }
+
+  fn to_core(&self, env: &Env) -> napi::Result<domparser::ParseOptions> {
+    let defaults = domparser::ParseOptions::default();
+    let quirks_mode = match self.quirks_mode.as_deref() {
+      None => None,
+      Some("quirks") => Some(QuirksMode::Quirks),
+      Some("limited-quirks") => Some(QuirksMode::LimitedQuirks),
+      Some("no-quirks") => Some(QuirksMode::NoQuirks),
+      Some(other) => return Err(type_error(env, &format!("Invalid quirksMode '{}'", other))),
+    };
+    Ok(domparser::ParseOptions {
+      scripting_enabled: self.scripting_enabled.unwrap_or(defaults.scripting_enabled),
+      iframe_srcdoc: self.iframe_srcdoc.unwrap_or(defaults.iframe_srcdoc),
+      quirks_mode,
+      exact_errors: self.exact_errors.unwrap_or(defaults.exact_errors),
+      source_locations: self
+        .source_code_location_info
+        .unwrap_or(defaults.source_locations),
+    })
+  }
+}
+
+/// Parses a document fed in chunks, such as the chunks of a readable stream.
+#[napi(js_name = "DocumentParser")]
+pub struct DocumentParserRepr(Option<DocumentParser>);
+
+#[napi]
+impl DocumentParserRepr {
+  /// Creates a parser. `options.transportCharset` applies to `Buffer` chunks.
+  #[napi(constructor)]
+  pub fn new(env: Env, options: Option<ParseOptions>) -> napi::Result<Self> {
+    let transport_charset = options
+      .as_ref()
+      .and_then(|options| options.transport_charset.clone());
+    let limits = options
+      .as_ref()
+      .and_then(|options| options.limits)
+      .map_or_else(Default::default, Into::into);
+    let options = ParseOptions::resolve(&env, options)?;
+    let mut parser = DocumentParser::with_limits(&options, limits);
+    if let Some(charset) = transport_charset {
+      parser = parser.transport_charset(&charset);
+    }
+    Ok(DocumentParserRepr(Some(parser)))
+  }
+
+  /// Parses the next chunk of the document. `Buffer` chunks are decoded with the
+  /// encoding sniffed from the first 1024 bytes.
+  #[napi]
+  pub fn feed(&mut self, env: Env, chunk: Either<String, Buffer>) -> napi::Result<()> {
+    let parser = self.0.as_mut().ok_or_else(|| finished_error(&env))?;
+    match chunk {
+      Either::A(text) => parser.feed(&text),
+      Either::B(bytes) => parser.feed_bytes(&bytes),
+    }
+    Ok(())
+  }
+
+  /// Parses the rest of the input and returns the document, throwing a `RangeError`
+  /// if it exceeded `options.limits`. The parser cannot be fed afterwards.
+  #[napi]
+  pub fn finish(&mut self, env: Env) -> napi::Result<NodeRepr> {
+    let parser = self.0.take().ok_or_else(|| finished_error(&env))?;
+    parser
+      .try_finish()
+      .map(NodeRepr)
+      .map_err(|error| limit_error(&env, error))
+  }
+}
+
+fn finished_error(env: &Env) -> napi::Error {
+  dom_exception(
+    env,
+    "The parser has already finished".to_string(),
+    "InvalidStateError",
+    None,
+  )
+}
+
+/// A parse error reported by the HTML tokenizer or tree builder.
+#[napi(object)]
+pub struct ParseError {
+  pub message: String,
+  /// 1-based line where the parser was when it detected the error.
+  pub line: u32,
+  /// 1-based column where the parser was when it detected the error.
+  pub column: u32,
+}
+
+/// A parsed document with the errors found while parsing it.
+#[napi(object, object_from_js = false)]
+pub struct ParseResult {
+  pub document: NodeRepr,
+  pub errors: Vec<ParseError>,
+}
+
+impl From<domparser::ParseResult> for ParseResult {
+  fn from(result: domparser::ParseResult) -> Self {
+    ParseResult {
+      document: NodeRepr(result.document),
+      errors: result
+        .errors
+        .into_iter()
+        .map(|error| ParseError {
+          message: error.message,
+          line: error.line as u32,
+          column: error.column as u32,
+        })
+        .collect(),
+    }
+  }
+}
+
+/// A span of the parsed source. Lines and columns are 1-based, and offsets index
+/// into the parsed string.
+#[napi(object)]
+pub struct Location {
+  pub start_line: u32,
+  pub start_col: u32,
+  pub start_offset: u32,
+  pub end_line: u32,
+  pub end_col: u32,
+  pub end_offset: u32,
+}
+
+impl From<SourceSpan> for Location {
+  fn from(span: SourceSpan) -> Self {
+    Location {
+      start_line: span.start.line as u32,
+      start_col: span.start.column as u32,
+      start_offset: span.start.utf16_offset as u32,
+      end_line: span.end.line as u32,
+      end_col: span.end.column as u32,
+      end_offset: span.end.utf16_offset as u32,
+    }
+  }
+}
+
+/// Where a node came from in the parsed source, in the shape parse5 uses.
+#[napi(object)]
+pub struct SourceCodeLocation {
+  pub start_line: u32,
+  pub start_col: u32,
+  pub start_offset: u32,
+  pub end_line: u32,
+  pub end_col: u32,
+  pub end_offset: u32,
+  /// The start tag of an element.
+  pub start_tag: Option<Location>,
+  /// The end tag of an element, if it was closed by one rather than implied.
+  pub end_tag: Option<Location>,
+}
+
+impl From<SourceRange> for SourceCodeLocation {
+  fn from(range: SourceRange) -> Self {
+    let Location {
+      start_line,
+      start_col,
+      start_offset,
+      end_line,
+      end_col,
+      end_offset,
+    } = SourceSpan {
+      start: range.start,
+      end: range.end,
+    }
+    .into();
+    SourceCodeLocation {
+      start_line,
+      start_col,
+      start_offset,
+      end_line,
+      end_col,
+      end_offset,
+      start_tag: range.start_tag.map(Into::into),
+      end_tag: range.end_tag.map(Into::into),
+    }
+  }
+}
@@ -0,0 +1,81 @@
+use domparser::{NamespaceMap, Selector, SelectorError, Specificity};
+use napi::Env;
+use std::collections::HashMap;
+
+use crate::error::dom_exception;
+use crate::node_repr::NodeRepr;
+
+/// A selector list parsed once, which can be reused to query many nodes and documents.
+#[napi(js_name = "Selector")]
+pub struct SelectorRepr(pub(crate) Selector);
+
+#[napi]
+impl SelectorRepr {
+  /// Parses the selector list, throwing a `SyntaxError` if it is not valid.
+  ///
+  /// `namespaces` maps prefixes usable as `prefix|name` to namespace URIs, like CSS
+  /// `@namespace` rules; the empty prefix sets the default namespace.
+  #[napi(constructor)]
+  pub fn new(
+    env: Env,
+    selectors: String,
+    namespaces: Option<HashMap<String, String>>,
+  ) -> napi::Result<Self> {
+    let mut map = NamespaceMap::new();
+    for (prefix, uri) in namespaces.unwrap_or_default() {
+      if prefix.is_empty() {
+        map.set_default(uri);
+      } else {
+        map.insert(prefix, uri);
+      }
+    }
+    Selector::parse_with_namespaces(&selectors, &map)
+      .map(SelectorRepr)
+      .map_err(|e| syntax_error(&env, e))
+  }
+
+  /// The specificity `[a, b, c]` of each selector in the list, in source order.
+  #[napi(getter, ts_return_type = "Array<[number, number, number]>")]
+  pub fn specificities(&self) -> Vec<Vec<u32>> {
+    self.0.specificities().into_iter().map(to_array).collect()
+  }
+
+  /// Returns the selector in the list that `node` matches with the highest specificity,
+  /// the first one winning ties, or null if none matches. As in a style sheet, `:scope`
+  /// matches the root element.
+  #[napi(js_name = "matchDetails")]
+  pub fn match_details(&self, node: &NodeRepr) -> Option<SelectorMatch> {
+    self.0.match_details(&node.0).map(|found| SelectorMatch {
+      index: found.index as u32,
+      selector: found.selector,
+      specificity: to_array(found.specificity),
+    })
+  }
+}
+
+/// The selector of a list that a node matched.
+#[napi(object)]
+pub struct SelectorMatch {
+  /// Position of the selector in the list.
+  pub index: u32,
+  /// The selector as written.
+  pub selector: String,
+  #[napi(ts_type = "[number, number, number]")]
+  pub specificity: Vec<u32>,
+}
+
+fn to_array(specificity: Specificity) -> Vec<u32> {
+  vec![specificity.a, specificity.b, specificity.c]
+}
+
+/// Converts a selector parse error into a `DOMException` named `SyntaxError`, as browsers
+/// throw from `querySelector` and friends. The offending character offset is exposed as
+/// its `position` property.
+pub(crate) fn syntax_error(env: &Env, error: SelectorError) -> napi::Error {
+  dom_exception(
+    env,
+    format!("Invalid selector: {}", error),
+    "SyntaxError",
+    Some(error.position),
+  )
+}
@@ -0,0 +1,216 @@
+use domparser::serializer::{serialize_html, serialize_html_with_escape, HtmlSerializer};
+use domparser::{CharacterReferences, DomNode};
+use napi::bindgen_prelude::{Buffer, Either, FnArgs, Function, FunctionRef};
+use napi::Env;
+use std::io::Write;
+
+use crate::error::type_error;
+
+/// A function that escapes a text or attribute value, told whether it is an attribute
+/// value.
+type EscapeFunction<'a> = Function<'a, FnArgs<(String, bool)>, String>;
+
+/// Options for `outerHtml` and `innerHtml`. By default the tree is written as it is.
+#[napi(object)]
+#[derive(Default)]
+pub struct SerializeOptions<'a> {
+  /// Put each child of an element holding only block-level elements on a line of its
+  /// own, indented by this many spaces per level.
+  pub indent: Option<u32>,
+  /// Collapse runs of whitespace in text, and drop whitespace between block-level
+  /// elements. Whitespace in `<pre>`, `<textarea>`, scripts and styles is kept.
+  pub collapse_whitespace: Option<bool>,
+  /// Leave out end tags the parser would imply, such as `</li>` and `</p>`.
+  pub omit_optional_tags: Option<bool>,
+  /// Leave out comments.
+  pub remove_comments: Option<bool>,
+  /// Write attribute values without quotes when nothing in them needs quoting.
+  pub unquoted_attributes: Option<bool>,
+  /// Write empty and boolean attributes, such as `disabled="disabled"`, by name alone.
+  pub shorten_boolean_attributes: Option<bool>,
+  /// How text and attribute values are escaped. `"minimal"`, the default, escapes only
+  /// what HTML needs; `"ascii"` also writes every non-ASCII character as a numeric
+  /// reference, and `"named"` those with a named reference by name. A function is
+  /// called with each text or attribute value instead, and returns it escaped.
+  #[napi(
+    ts_type = "'minimal' | 'ascii' | 'named' | ((text: string, inAttribute: boolean) => string)"
+  )]
+  pub character_references: Option<Either<String, EscapeFunction<'a>>>,
+}
+
+impl SerializeOptions<'_> {
+  /// Converts options from JavaScript for serializing on the thread pool, where an
+  /// escape function cannot be called.
+  pub(crate) fn resolve(
+    env: &Env,
+    options: Option<SerializeOptions>,
+  ) -> napi::Result<domparser::SerializeOptions> {
+    let options = options.unwrap_or_default();
+    if let Some(Either::B(_)) = options.character_references {
+      return Err(type_error(
+        env,
+        "characterReferences cannot be a function off the main thread",
+      ));
+    }
+    options.to_core(env)
+  }
+
+  fn to_core(&self, env: &Env) -> napi::Result<domparser::SerializeOptions> {
+    let character_references = match &self.character_references {
+      None | Some(Either::B(_)) => CharacterReferences::Minimal,
+      Some(Either::A(policy)) => match policy.as_str() {
+        "minimal" => CharacterReferences::Minimal,
+        "ascii" => CharacterReferences::Ascii,
+        "named" => CharacterReferences::Named,
+        other => {
+          return Err(type_error(
+            env,
+            &format!("Invalid characterReferences '{}'", other),
+          ))
+        }
+      },
+    };
+    Ok(domparser::SerializeOptions {
+      scripting_enabled: true,
+      indent: self.indent.map(|indent| indent as usize),
+      collapse_whitespace: self.collapse_whitespace.unwrap_or_default(),
+      omit_optional_tags: self.omit_optional_tags.unwrap_or_default(),
+      remove_comments: self.remove_comments.unwrap_or_default(),
+      unquoted_attributes: self.unquoted_attributes.unwrap_or_default(),
+      shorten_boolean_attributes: self.shorten_boolean_attributes.unwrap_or_default(),
+      character_references,
+    })
+  }
+}
+
+/// Writes `node` as HTML, or only its children unless `include_node` is set, escaping
+/// text with the function in `options` if there is one. An exception it throws stops
+/// the serialization.
+pub(crate) fn write_html<W: Write>(
+  env: &Env,
+  writer: &mut W,
+  node: &DomNode,
+  include_node: bool,
+  options: Option<SerializeOptions>,
+) -> napi::Result<()> {
+  let options = options.unwrap_or_default();
+  let core = options.to_core(env)?;
+  let result = match options.character_references {
+    Some(Either::B(escape)) => {
+      let mut thrown = None;
+      let result = serialize_html_with_escape(
+        writer,
+        &node.0,
+        include_node,
+        &core,
+        &mut |text, in_attribute| {
+          if thrown.is_some() {
+            return String::new();
+          }
+          escape
+            .call((text.to_string(), in_attribute).into())
+            .unwrap_or_else(|error| {
+              thrown = Some(error);
+              String::new()
+            })
+        },
+      );
+      if let Some(error) = thrown {
+        return Err(error);
+      }
+      result
+    }
+    _ => serialize_html(writer, &node.0, include_node, &core),
+  };
+  result.map_err(|error| napi::Error::from_reason(error.to_string()))
+}
+
+/// Returns `node` as HTML, like `write_html`.
+pub(crate) fn html_string(
+  env: &Env,
+  node: &DomNode,
+  include_node: bool,
+  options: Option<SerializeOptions>,
+) -> napi::Result<String> {
+  let mut bytes = Vec::new();
+  write_html(env, &mut bytes, node, include_node, options)?;
+  Ok(String::from_utf8(bytes).unwrap())
+}
+
+/// How many bytes are collected before they are passed to JavaScript.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The HTML serialization of a node, handed to JavaScript a chunk at a time as it asks
+/// for the next one.
+#[napi]
+pub struct HtmlChunks {
+  serializer: HtmlSerializer,
+  escape: Option<FunctionRef<FnArgs<(String, bool)>, String>>,
+  done: bool,
+}
+
+impl HtmlChunks {
+  pub(crate) fn new(
+    env: &Env,
+    node: &DomNode,
+    options: Option<SerializeOptions>,
+  ) -> napi::Result<Self> {
+    let options = options.unwrap_or_default();
+    let escape = match &options.character_references {
+      Some(Either::B(escape)) => Some(escape.create_ref()?),
+      _ => None,
+    };
+    Ok(HtmlChunks {
+      serializer: HtmlSerializer::new(&node.0, true, options.to_core(env)?),
+      escape,
+      done: false,
+    })
+  }
+}
+
+#[napi]
+impl HtmlChunks {
+  /// Returns the next `Buffer` of about 64 KiB, ending between characters, or `null`
+  /// once everything is written. An exception thrown by the escape function stops the
+  /// serialization and is rethrown.
+  #[napi]
+  pub fn next(&mut self, env: Env) -> napi::Result<Option<Buffer>> {
+    if self.done {
+      return Ok(None);
+    }
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    let more = match &self.escape {
+      Some(escape) => {
+        let escape = escape.borrow_back(&env)?;
+        let mut thrown = None;
+        let more = self.serializer.write_chunk(
+          &mut chunk,
+          CHUNK_SIZE,
+          Some(&mut |text, in_attribute| {
+            if thrown.is_some() {
+              return String::new();
+            }
+            escape
+              .call((text.to_string(), in_attribute).into())
+              .unwrap_or_else(|error| {
+                thrown = Some(error);
+                String::new()
+              })
+          }),
+        );
+        if let Some(error) = thrown {
+          self.done = true;
+          return Err(error);
+        }
+        more
+      }
+      None => self.serializer.write_chunk(&mut chunk, CHUNK_SIZE, None),
+    };
+    self.done = !more;
+    Ok(if chunk.is_empty() {
+      None
+    } else {
+      Some(chunk.into())
+    })
+  }
+}
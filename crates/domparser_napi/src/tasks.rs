@@ -0,0 +1,261 @@
+use domparser::{DomNode, LimitError, ParseLimits, ParseOptions, Selector, SendableDocument};
+use napi::bindgen_prelude::{AsyncTask, Either};
+use napi::{Env, Task};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error::limit_error;
+use crate::node_repr::NodeRepr;
+use crate::selector::syntax_error;
+use crate::serializer::SerializeOptions;
+
+/// Parses a document on the thread pool.
+pub struct ParseTask {
+  pub(crate) html: Either<String, Vec<u8>>,
+  pub(crate) options: ParseOptions,
+  pub(crate) transport_charset: Option<String>,
+  pub(crate) limits: Option<ParseLimits>,
+}
+
+impl Task for ParseTask {
+  type Output = Result<SendableDocument, LimitError>;
+  type JsValue = NodeRepr;
+
+  fn compute(&mut self) -> napi::Result<Self::Output> {
+    let html = match &mut self.html {
+      Either::A(html) => Either::A(std::mem::take(html)),
+      Either::B(bytes) => Either::B(bytes.as_slice()),
+    };
+    let document = match crate::parse_input(
+      html,
+      &self.options,
+      self.transport_charset.as_deref(),
+      self.limits,
+    ) {
+      Ok(document) => document,
+      Err(error) => return Ok(Err(error)),
+    };
+    document
+      .into_sendable()
+      .map(Ok)
+      .map_err(|_| napi::Error::from_reason("The parsed document is still in use"))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> napi::Result<NodeRepr> {
+    match output {
+      Ok(document) => Ok(NodeRepr(document.into_node())),
+      Err(error) => Err(limit_error(&env, error)),
+    }
+  }
+}
+
+/// Serializes a copy of a node on the thread pool.
+pub struct OuterHtmlTask {
+  copy: Option<SendableDocument>,
+  options: domparser::SerializeOptions,
+}
+
+impl Task for OuterHtmlTask {
+  type Output = String;
+  type JsValue = String;
+
+  fn compute(&mut self) -> napi::Result<String> {
+    let copy = self.copy.take().expect("a task is computed once");
+    Ok(copy.into_node().outer_html_with_options(&self.options))
+  }
+
+  fn resolve(&mut self, _env: Env, html: String) -> napi::Result<String> {
+    Ok(html)
+  }
+}
+
+/// Matches a selector against a copy of a node's whole tree on the thread pool, then
+/// finds the matches in the original by their position.
+pub struct SelectTask {
+  copy: Option<SendableDocument>,
+  /// The child indices leading from the root down to the node selected from.
+  scope_path: Vec<usize>,
+  selector: Selector,
+  first_only: bool,
+  root: Waiting,
+}
+
+impl Task for SelectTask {
+  type Output = Vec<Vec<usize>>;
+  type JsValue = Either<Option<NodeRepr>, Vec<NodeRepr>>;
+
+  fn compute(&mut self) -> napi::Result<Self::Output> {
+    let root = self
+      .copy
+      .take()
+      .expect("a task is computed once")
+      .into_node();
+    // The scope is found in a copy of the whole tree, so that selectors still see its
+    // ancestors and siblings.
+    let scope = follow(&root, &self.scope_path).expect("the copy has the same shape");
+    let matches = if self.first_only {
+      scope.select_compiled(&self.selector).into_iter().collect()
+    } else {
+      scope.select_all_compiled(&self.selector)
+    };
+    Ok(paths_from(&root, &matches))
+  }
+
+  fn resolve(&mut self, _env: Env, paths: Self::Output) -> napi::Result<Self::JsValue> {
+    let root = self.root.take();
+    // The tree may have changed while the task ran. Matches no longer there are left out.
+    let nodes = paths.iter().filter_map(|path| follow(root.as_ref()?, path));
+    Ok(if self.first_only {
+      Either::A(nodes.map(NodeRepr).next())
+    } else {
+      Either::B(nodes.map(NodeRepr).collect())
+    })
+  }
+
+  fn finally(self, _env: Env) -> napi::Result<()> {
+    self.root.take();
+    Ok(())
+  }
+}
+
+/// The child indices leading from `root` down to `node`.
+fn path_from(root: &DomNode, node: &DomNode) -> Vec<usize> {
+  let mut path = Vec::new();
+  let mut current = node.clone();
+  while let Some(parent) = current.parent_node() {
+    let index = parent
+      .0
+      .children
+      .borrow()
+      .iter()
+      .position(|child| Rc::ptr_eq(child, &current.0))
+      .unwrap_or_default();
+    path.push(index);
+    current = parent;
+  }
+  debug_assert!(current.is_same_node(root));
+  path.reverse();
+  path
+}
+
+/// The child indices leading from `root` down to each of `nodes`. The position of every
+/// node among its siblings is found in one pass, rather than searching a long list of
+/// siblings once for each match in it.
+fn paths_from(root: &DomNode, nodes: &[DomNode]) -> Vec<Vec<usize>> {
+  if nodes.is_empty() {
+    return Vec::new();
+  }
+  let mut indices = HashMap::new();
+  let mut stack = vec![root.0.clone()];
+  while let Some(node) = stack.pop() {
+    for (index, child) in node.children.borrow().iter().enumerate() {
+      indices.insert(Rc::as_ptr(child), index);
+      stack.push(child.clone());
+    }
+  }
+  nodes
+    .iter()
+    .map(|node| {
+      let mut path = Vec::new();
+      let mut current = node.clone();
+      while let Some(parent) = current.parent_node() {
+        path.push(indices[&Rc::as_ptr(&current.0)]);
+        current = parent;
+      }
+      path.reverse();
+      path
+    })
+    .collect()
+}
+
+fn follow(root: &DomNode, path: &[usize]) -> Option<DomNode> {
+  path.iter().try_fold(root.clone(), |node, &index| {
+    node.0.children.borrow().get(index).cloned().map(DomNode)
+  })
+}
+
+thread_local! {
+  static WAITING: RefCell<HashMap<u64, DomNode>> = RefCell::new(HashMap::new());
+  static NEXT_WAITING: RefCell<u64> = const { RefCell::new(0) };
+}
+
+/// A node kept on the main thread for a task to use once it is done, as nodes cannot
+/// go to the thread pool themselves.
+struct Waiting(u64);
+
+impl Waiting {
+  fn new(node: DomNode) -> Self {
+    let id = NEXT_WAITING.with(|next| {
+      let mut next = next.borrow_mut();
+      *next += 1;
+      *next
+    });
+    WAITING.with(|waiting| waiting.borrow_mut().insert(id, node));
+    Waiting(id)
+  }
+
+  /// The node, which is no longer kept once taken.
+  fn take(&self) -> Option<DomNode> {
+    WAITING.with(|waiting| waiting.borrow_mut().remove(&self.0))
+  }
+}
+
+#[napi]
+impl NodeRepr {
+  /// Returns the HTML serialization of the node like `outerHtml`, serializing a copy on
+  /// the thread pool. The copy is made on the calling thread, which takes about as long
+  /// as serializing, so this does not keep a large tree from blocking the event loop.
+  #[napi(js_name = "outerHtmlAsync")]
+  pub fn outer_html_async(
+    &self,
+    env: Env,
+    options: Option<SerializeOptions>,
+  ) -> napi::Result<AsyncTask<OuterHtmlTask>> {
+    let options = SerializeOptions::resolve(&env, options)?;
+    Ok(AsyncTask::new(OuterHtmlTask {
+      copy: Some(self.0.to_sendable()),
+      options,
+    }))
+  }
+
+  /// Selects the first element that matches the selectors like `select`, matching a
+  /// copy of the whole tree on the thread pool. The copy is made on the calling thread.
+  /// Invalid selectors throw right away.
+  #[napi(js_name = "selectAsync", ts_return_type = "Promise<NodeRepr | null>")]
+  pub fn select_async(&self, env: Env, selectors: String) -> napi::Result<AsyncTask<SelectTask>> {
+    self.select_task(&env, &selectors, true)
+  }
+
+  /// Selects all elements that match the selectors like `selectAll`, matching a copy of
+  /// the whole tree on the thread pool. The copy is made on the calling thread. Invalid
+  /// selectors throw right away.
+  #[napi(
+    js_name = "selectAllAsync",
+    ts_return_type = "Promise<Array<NodeRepr>>"
+  )]
+  pub fn select_all_async(
+    &self,
+    env: Env,
+    selectors: String,
+  ) -> napi::Result<AsyncTask<SelectTask>> {
+    self.select_task(&env, &selectors, false)
+  }
+
+  fn select_task(
+    &self,
+    env: &Env,
+    selectors: &str,
+    first_only: bool,
+  ) -> napi::Result<AsyncTask<SelectTask>> {
+    let selector = Selector::parse(selectors).map_err(|e| syntax_error(env, e))?;
+    let root = self.0.get_root_node();
+    Ok(AsyncTask::new(SelectTask {
+      copy: Some(root.to_sendable()),
+      scope_path: path_from(&root, &self.0),
+      selector,
+      first_only,
+      root: Waiting::new(root),
+    }))
+  }
+}
@@ -0,0 +1,267 @@
+use domparser::{DomNode, XPathError, XPathNode, XPathValue};
+use napi::bindgen_prelude::Either;
+use napi::Env;
+
+use crate::error::{dom_exception, type_error};
+use crate::node_repr::NodeRepr;
+
+pub(crate) const ANY_TYPE: u32 = 0;
+const NUMBER_TYPE: u32 = 1;
+const STRING_TYPE: u32 = 2;
+const BOOLEAN_TYPE: u32 = 3;
+const UNORDERED_NODE_ITERATOR_TYPE: u32 = 4;
+const ORDERED_NODE_ITERATOR_TYPE: u32 = 5;
+const UNORDERED_NODE_SNAPSHOT_TYPE: u32 = 6;
+const ORDERED_NODE_SNAPSHOT_TYPE: u32 = 7;
+const ANY_UNORDERED_NODE_TYPE: u32 = 8;
+const FIRST_ORDERED_NODE_TYPE: u32 = 9;
+
+/// The result of `evaluate()`, converted to the requested result type.
+///
+/// Node results are always snapshots, so iterators are never invalidated by later
+/// changes to the document.
+#[napi(js_name = "XPathResult")]
+pub struct XPathResultRepr {
+  result_type: u32,
+  value: XPathValue,
+  next: usize,
+}
+
+impl XPathResultRepr {
+  pub(crate) fn new(env: &Env, value: XPathValue, requested: u32) -> napi::Result<Self> {
+    let (result_type, value) = match requested {
+      ANY_TYPE => match value {
+        XPathValue::NodeSet(_) => (UNORDERED_NODE_ITERATOR_TYPE, value),
+        XPathValue::Number(_) => (NUMBER_TYPE, value),
+        XPathValue::String(_) => (STRING_TYPE, value),
+        XPathValue::Boolean(_) => (BOOLEAN_TYPE, value),
+      },
+      NUMBER_TYPE => (requested, XPathValue::Number(value.to_number())),
+      STRING_TYPE => (requested, XPathValue::String(value.to_string_value())),
+      BOOLEAN_TYPE => (requested, XPathValue::Boolean(value.to_boolean())),
+      UNORDERED_NODE_ITERATOR_TYPE..=FIRST_ORDERED_NODE_TYPE => match value {
+        XPathValue::NodeSet(_) => (requested, value),
+        _ => {
+          return Err(type_error(
+            env,
+            "The result of the expression cannot be converted to a node-set",
+          ))
+        }
+      },
+      _ => return Err(type_error(env, "Unknown XPathResult type")),
+    };
+    Ok(XPathResultRepr {
+      result_type,
+      value,
+      next: 0,
+    })
+  }
+
+  fn nodes(&self, env: &Env, types: &[u32]) -> napi::Result<&[XPathNode]> {
+    match &self.value {
+      XPathValue::NodeSet(nodes) if types.contains(&self.result_type) => Ok(nodes),
+      _ => Err(type_error(env, "The result is not of the requested type")),
+    }
+  }
+}
+
+#[napi]
+impl XPathResultRepr {
+  /// The type of the result, one of the `XPathResult` constants.
+  #[napi(getter)]
+  pub fn result_type(&self) -> u32 {
+    self.result_type
+  }
+
+  /// The value of a `NUMBER_TYPE` result.
+  #[napi(getter)]
+  pub fn number_value(&self, env: Env) -> napi::Result<f64> {
+    match self.value {
+      XPathValue::Number(number) if self.result_type == NUMBER_TYPE => Ok(number),
+      _ => Err(type_error(&env, "The result is not a number")),
+    }
+  }
+
+  /// The value of a `STRING_TYPE` result.
+  #[napi(getter)]
+  pub fn string_value(&self, env: Env) -> napi::Result<String> {
+    match &self.value {
+      XPathValue::String(string) if self.result_type == STRING_TYPE => Ok(string.clone()),
+      _ => Err(type_error(&env, "The result is not a string")),
+    }
+  }
+
+  /// The value of a `BOOLEAN_TYPE` result.
+  #[napi(getter)]
+  pub fn boolean_value(&self, env: Env) -> napi::Result<bool> {
+    match self.value {
+      XPathValue::Boolean(boolean) if self.result_type == BOOLEAN_TYPE => Ok(boolean),
+      _ => Err(type_error(&env, "The result is not a boolean")),
+    }
+  }
+
+  /// The node of an `ANY_UNORDERED_NODE_TYPE` or `FIRST_ORDERED_NODE_TYPE` result.
+  #[napi(getter)]
+  pub fn single_node_value(&self, env: Env) -> napi::Result<Option<Either<NodeRepr, AttrRepr>>> {
+    let nodes = self.nodes(&env, &[ANY_UNORDERED_NODE_TYPE, FIRST_ORDERED_NODE_TYPE])?;
+    Ok(nodes.first().map(to_js))
+  }
+
+  /// The number of nodes in a snapshot result.
+  #[napi(getter)]
+  pub fn snapshot_length(&self, env: Env) -> napi::Result<u32> {
+    let nodes = self.nodes(
+      &env,
+      &[UNORDERED_NODE_SNAPSHOT_TYPE, ORDERED_NODE_SNAPSHOT_TYPE],
+    )?;
+    Ok(nodes.len() as u32)
+  }
+
+  /// Always false: iterators walk a snapshot taken when the expression was evaluated.
+  #[napi(getter)]
+  pub fn invalid_iterator_state(&self) -> bool {
+    false
+  }
+
+  /// Returns the node at `index` in a snapshot result, or null if out of range.
+  #[napi(js_name = "snapshotItem")]
+  pub fn snapshot_item(
+    &self,
+    env: Env,
+    index: u32,
+  ) -> napi::Result<Option<Either<NodeRepr, AttrRepr>>> {
+    let nodes = self.nodes(
+      &env,
+      &[UNORDERED_NODE_SNAPSHOT_TYPE, ORDERED_NODE_SNAPSHOT_TYPE],
+    )?;
+    Ok(nodes.get(index as usize).map(to_js))
+  }
+
+  /// Returns the next node of an iterator result, or null once it is exhausted.
+  #[napi(js_name = "iterateNext")]
+  pub fn iterate_next(&mut self, env: Env) -> napi::Result<Option<Either<NodeRepr, AttrRepr>>> {
+    let nodes = self.nodes(
+      &env,
+      &[UNORDERED_NODE_ITERATOR_TYPE, ORDERED_NODE_ITERATOR_TYPE],
+    )?;
+    let node = nodes.get(self.next).map(to_js);
+    if node.is_some() {
+      self.next += 1;
+    }
+    Ok(node)
+  }
+}
+
+/// An attribute selected by an XPath expression.
+#[napi(js_name = "Attr")]
+pub struct AttrRepr {
+  element: DomNode,
+  node: XPathNode,
+}
+
+impl AttrRepr {
+  fn parts(&self) -> (Option<String>, String, String) {
+    match &self.node {
+      XPathNode::Attribute(_, name) => (
+        name
+          .prefix
+          .as_ref()
+          .map(|prefix| prefix.to_string())
+          .filter(|prefix| !prefix.is_empty()),
+        name.local.to_string(),
+        name.ns.to_string(),
+      ),
+      XPathNode::Node(_) => (None, String::new(), String::new()),
+    }
+  }
+}
+
+#[napi]
+impl AttrRepr {
+  /// Always 2, `Node.ATTRIBUTE_NODE`.
+  #[napi(getter)]
+  pub fn node_type(&self) -> i32 {
+    2
+  }
+
+  /// The qualified name of the attribute.
+  #[napi(getter)]
+  pub fn name(&self) -> String {
+    match self.parts() {
+      (Some(prefix), local, _) => format!("{}:{}", prefix, local),
+      (None, local, _) => local,
+    }
+  }
+
+  /// The qualified name of the attribute.
+  #[napi(getter)]
+  pub fn node_name(&self) -> String {
+    self.name()
+  }
+
+  /// The local part of the attribute name.
+  #[napi(getter)]
+  pub fn local_name(&self) -> String {
+    self.parts().1
+  }
+
+  /// The namespace URI of the attribute, or null if it is not in a namespace.
+  #[napi(getter, js_name = "namespaceURI")]
+  pub fn namespace_uri(&self) -> Option<String> {
+    Some(self.parts().2).filter(|ns| !ns.is_empty())
+  }
+
+  /// The namespace prefix of the attribute, or null if it has none.
+  #[napi(getter)]
+  pub fn prefix(&self) -> Option<String> {
+    self.parts().0
+  }
+
+  /// The value of the attribute.
+  #[napi(getter)]
+  pub fn value(&self) -> String {
+    self.node.string_value()
+  }
+
+  /// The value of the attribute.
+  #[napi(getter)]
+  pub fn node_value(&self) -> String {
+    self.value()
+  }
+
+  /// The value of the attribute.
+  #[napi(getter)]
+  pub fn text_content(&self) -> String {
+    self.value()
+  }
+
+  /// The element the attribute belongs to.
+  #[napi(getter)]
+  pub fn owner_element(&self) -> NodeRepr {
+    NodeRepr(self.element.clone())
+  }
+}
+
+fn to_js(node: &XPathNode) -> Either<NodeRepr, AttrRepr> {
+  match node {
+    XPathNode::Node(node) => Either::A(NodeRepr(node.clone())),
+    XPathNode::Attribute(element, _) => Either::B(AttrRepr {
+      element: element.clone(),
+      node: node.clone(),
+    }),
+  }
+}
+
+/// Converts an XPath error into the exception browsers throw from `document.evaluate`.
+pub(crate) fn xpath_error(env: &Env, error: XPathError) -> napi::Error {
+  match &error {
+    XPathError::Syntax { position, .. } => dom_exception(
+      env,
+      format!("Invalid XPath expression: {}", error),
+      "SyntaxError",
+      Some(*position),
+    ),
+    XPathError::Namespace { .. } => dom_exception(env, error.to_string(), "NamespaceError", None),
+    XPathError::Type { message } => type_error(env, message),
+  }
+}
@@ -1,4 +1,6 @@
-import { NodeRepr as NativeNodeRepr } from './index'
+import { NodeRepr as NativeNodeRepr, ParseOptions, SerializeOptions, XPathResult as NativeXPathResult } from './index'
+
+export { Attr, DocumentParser, Location, ParseError, ParseLimits, ParseOptions, ParseResult, Selector, SelectorMatch, SerializeOptions, SourceCodeLocation, parseAsync, parseFragment, parseWithDiagnostics, parseXml } from './index'
 
 export interface DOMTokenList {
   add(...tokens: string[]): void
@@ -15,8 +17,52 @@ export interface DOMTokenList {
 export class NodeRepr extends NativeNodeRepr {
   get classList(): DOMTokenList
   get dataset(): Record<string, string>
+  /**
+   * Writes the HTML serialization of the node in chunks to a writable stream, or to a
+   * function called with each chunk, without building the whole string first. The
+   * next chunk is written once the stream has drained, or once a promise the function
+   * returns has settled. Resolves when everything is written.
+   */
+  serializeTo(
+    target: NodeJS.WritableStream | ((chunk: Buffer) => unknown),
+    options?: SerializeOptions,
+  ): Promise<void>
 }
 
 export class DOMParser {
-  parseFromString(string: string, mimeType: string): NodeRepr
+  parseFromString(string: string, mimeType: string, options?: ParseOptions): NodeRepr
+}
+
+export class XMLSerializer {
+  /** Serializes a node as well-formed XML, declaring the namespaces its elements are in. */
+  serializeToString(node: NativeNodeRepr): string
 }
+
+export declare class XPathResult extends NativeXPathResult {
+  static readonly ANY_TYPE: 0
+  static readonly NUMBER_TYPE: 1
+  static readonly STRING_TYPE: 2
+  static readonly BOOLEAN_TYPE: 3
+  static readonly UNORDERED_NODE_ITERATOR_TYPE: 4
+  static readonly ORDERED_NODE_ITERATOR_TYPE: 5
+  static readonly UNORDERED_NODE_SNAPSHOT_TYPE: 6
+  static readonly ORDERED_NODE_SNAPSHOT_TYPE: 7
+  static readonly ANY_UNORDERED_NODE_TYPE: 8
+  static readonly FIRST_ORDERED_NODE_TYPE: 9
+  readonly ANY_TYPE: 0
+  readonly NUMBER_TYPE: 1
+  readonly STRING_TYPE: 2
+  readonly BOOLEAN_TYPE: 3
+  readonly UNORDERED_NODE_ITERATOR_TYPE: 4
+  readonly ORDERED_NODE_ITERATOR_TYPE: 5
+  readonly UNORDERED_NODE_SNAPSHOT_TYPE: 6
+  readonly ORDERED_NODE_SNAPSHOT_TYPE: 7
+  readonly ANY_UNORDERED_NODE_TYPE: 8
+  readonly FIRST_ORDERED_NODE_TYPE: 9
+}
+
+/** Parses a document from a readable stream, or any async iterable of strings and Buffers. */
+export function parseStream(
+  stream: AsyncIterable<string | Buffer>,
+  options?: ParseOptions,
+): Promise<NodeRepr>
@@ -1,4 +1,5 @@
-const { parse, NodeRepr } = require('./index.js');
+const { once } = require('node:events');
+const { parse, parseAsync, parseFragment, parseWithDiagnostics, parseXml, DocumentParser, NodeRepr, Selector, XPathResult } = require('./index.js');
 
 // Implement classList and dataset wrappers
 Object.defineProperty(NodeRepr.prototype, 'classList', {
@@ -83,18 +84,84 @@ Object.defineProperty(NodeRepr.prototype, 'dataset', {
   configurable: true
 });
 
+// Writes the HTML serialization of the node in chunks to a writable stream, or to a
+// function called with each chunk as a Buffer
+NodeRepr.prototype.serializeTo = async function (target, options) {
+  const chunks = this._htmlChunks(options);
+  // A stream is not written to again until it has drained, and a function not called
+  // again until a promise it returns settles, so that chunks are only written as fast
+  // as they are consumed.
+  const write = typeof target === 'function'
+    ? target
+    : async (chunk) => {
+      if (!target.write(chunk)) {
+        await once(target, 'drain');
+      }
+    };
+  for (let chunk = chunks.next(); chunk !== null; chunk = chunks.next()) {
+    await write(chunk);
+  }
+};
+
+// XPathResult type constants, available on the class and its instances as in browsers
+const xpathResultTypes = {
+  ANY_TYPE: 0,
+  NUMBER_TYPE: 1,
+  STRING_TYPE: 2,
+  BOOLEAN_TYPE: 3,
+  UNORDERED_NODE_ITERATOR_TYPE: 4,
+  ORDERED_NODE_ITERATOR_TYPE: 5,
+  UNORDERED_NODE_SNAPSHOT_TYPE: 6,
+  ORDERED_NODE_SNAPSHOT_TYPE: 7,
+  ANY_UNORDERED_NODE_TYPE: 8,
+  FIRST_ORDERED_NODE_TYPE: 9
+};
+for (const [name, value] of Object.entries(xpathResultTypes)) {
+  Object.defineProperty(XPathResult, name, { value, enumerable: true });
+  Object.defineProperty(XPathResult.prototype, name, { value, enumerable: true });
+}
+
+const XML_MIME_TYPES = ['text/xml', 'application/xml', 'application/xhtml+xml', 'image/svg+xml'];
+
 class DOMParser {
-  parseFromString(string, mimeType) {
+  parseFromString(string, mimeType, options) {
     if (mimeType === 'text/html') {
-      return parse(string);
+      return parse(string, options);
+    }
+    if (XML_MIME_TYPES.includes(mimeType)) {
+      return parseXml(string, mimeType, options);
     }
     throw new Error(`Unsupported mime type: ${mimeType}`);
   }
 }
 
+class XMLSerializer {
+  serializeToString(node) {
+    return node.outerXml();
+  }
+}
+
+// Parses a document from a readable stream, or any async iterable of strings and Buffers
+async function parseStream(stream, options) {
+  const parser = new DocumentParser(options);
+  for await (const chunk of stream) {
+    parser.feed(chunk);
+  }
+  return parser.finish();
+}
+
 module.exports = {
   NodeRepr,
   DOMParser,
-  parse
+  DocumentParser,
+  Selector,
+  XMLSerializer,
+  XPathResult,
+  parse,
+  parseAsync,
+  parseFragment,
+  parseStream,
+  parseWithDiagnostics,
+  parseXml
 };
 
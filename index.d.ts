@@ -1,5 +1,58 @@
 /* auto-generated by NAPI-RS */
 /* eslint-disable */
+/** An attribute selected by an XPath expression. */
+export declare class Attr {
+  /** Always 2, `Node.ATTRIBUTE_NODE`. */
+  get nodeType(): number
+  /** The qualified name of the attribute. */
+  get name(): string
+  /** The qualified name of the attribute. */
+  get nodeName(): string
+  /** The local part of the attribute name. */
+  get localName(): string
+  /** The namespace URI of the attribute, or null if it is not in a namespace. */
+  get namespaceURI(): string | null
+  /** The namespace prefix of the attribute, or null if it has none. */
+  get prefix(): string | null
+  /** The value of the attribute. */
+  get value(): string
+  /** The value of the attribute. */
+  get nodeValue(): string
+  /** The value of the attribute. */
+  get textContent(): string
+  /** The element the attribute belongs to. */
+  get ownerElement(): NodeRepr
+}
+
+/** Parses a document fed in chunks, such as the chunks of a readable stream. */
+export declare class DocumentParser {
+  /** Creates a parser. `options.transportCharset` applies to `Buffer` chunks. */
+  constructor(options?: ParseOptions | undefined | null)
+  /**
+   * Parses the next chunk of the document. `Buffer` chunks are decoded with the
+   * encoding sniffed from the first 1024 bytes.
+   */
+  feed(chunk: string | Buffer): void
+  /**
+   * Parses the rest of the input and returns the document, throwing a `RangeError`
+   * if it exceeded `options.limits`. The parser cannot be fed afterwards.
+   */
+  finish(): NodeRepr
+}
+
+/**
+ * The HTML serialization of a node, handed to JavaScript a chunk at a time as it asks
+ * for the next one.
+ */
+export declare class HtmlChunks {
+  /**
+   * Returns the next `Buffer` of about 64 KiB, ending between characters, or `null`
+   * once everything is written. An exception thrown by the escape function stops the
+   * serialization and is rethrown.
+   */
+  next(): Buffer | null
+}
+
 export declare class NodeRepr {
   /** Inserts a set of Node objects or DOMString objects after the last child of the Element. */
   append(newChild: NodeRepr): void
@@ -159,10 +212,30 @@ export declare class NodeRepr {
   lookupPrefix(namespace: string): string | null
   /** Compares the position of the current node against another node in any other document. */
   compareDocumentPosition(other: NodeRepr): number
+  /**
+   * Where this node came from in the parsed source, for documents parsed with
+   * `sourceCodeLocationInfo`, or null.
+   */
+  get sourceCodeLocation(): SourceCodeLocation | null
+  /** The name of the encoding the document was decoded from, such as "UTF-8". */
+  get characterSet(): string
+  /** The MIME type of the document, such as "text/html" or "image/svg+xml". */
+  get contentType(): string
+  /**
+   * The rendering mode of the document: "BackCompat" in quirks mode, "CSS1Compat"
+   * otherwise.
+   */
+  get compatMode(): string
+  /** The quirks mode of the document, from its doctype unless the parse forced one. */
+  get quirksMode(): 'quirks' | 'limited-quirks' | 'no-quirks'
   /** Selects the first element that matches the specified selector string. */
   select(selectors: string): NodeRepr | null
   /** Selects all elements that match the specified selector string. */
   selectAll(selectors: string): Array<NodeRepr>
+  /** Selects the first element that matches the precompiled selector. */
+  selectCompiled(selector: Selector): NodeRepr | null
+  /** Selects all elements that match the precompiled selector. */
+  selectAllCompiled(selector: Selector): Array<NodeRepr>
   /** Returns the value of a specified attribute on the element. */
   getAttribute(name: string): string | null
   /** Returns the attribute names of the element as an Array of strings. */
@@ -171,10 +244,44 @@ export declare class NodeRepr {
   hasAttributes(): boolean
   /** Returns a boolean value indicating whether the current Node has any child nodes. */
   hasChildNodes(): boolean
-  /** Returns the HTML serialization of the element and its descendants. */
-  outerHtml(): string
-  /** Returns the HTML serialization of the element's descendants. */
-  innerHtml(): string
+  /**
+   * Returns the HTML serialization of the element and its descendants, formatted or
+   * minified as `options` ask.
+   */
+  outerHtml(options?: SerializeOptions | undefined | null): string
+  /**
+   * Returns the HTML serialization of the element and its descendants as chunks,
+   * formatted or minified as `options` ask, each written when it is asked for.
+   */
+  _htmlChunks(options?: SerializeOptions | undefined | null): HtmlChunks
+  /**
+   * Returns the XML serialization of the node and its descendants, declaring the
+   * namespaces they are in.
+   */
+  outerXml(): string
+  /**
+   * Returns the HTML serialization of the node like `outerHtml`, serializing a copy on
+   * the thread pool. The copy is made on the calling thread, which takes about as long
+   * as serializing, so this does not keep a large tree from blocking the event loop.
+   */
+  outerHtmlAsync(options?: SerializeOptions | undefined | null): Promise<string>
+  /**
+   * Selects the first element that matches the selectors like `select`, matching a
+   * copy of the whole tree on the thread pool. The copy is made on the calling thread.
+   * Invalid selectors throw right away.
+   */
+  selectAsync(selectors: string): Promise<NodeRepr | null>
+  /**
+   * Selects all elements that match the selectors like `selectAll`, matching a copy of
+   * the whole tree on the thread pool. The copy is made on the calling thread. Invalid
+   * selectors throw right away.
+   */
+  selectAllAsync(selectors: string): Promise<Array<NodeRepr>>
+  /**
+   * Returns the HTML serialization of the element's descendants, formatted or
+   * minified as `options` ask.
+   */
+  innerHtml(options?: SerializeOptions | undefined | null): string
   /** Returns the text content of the node and its descendants. */
   text(): string
   /** Returns the first Element within the document that matches the specified selector, or group of selectors. */
@@ -209,8 +316,18 @@ export declare class NodeRepr {
   get documentElement(): NodeRepr | null
   /** Returns a boolean value indicating whether the element would be selected by the specified selector string. */
   matches(selectors: string): boolean
+  /** Returns a boolean value indicating whether the element would be selected by the precompiled selector. */
+  matchesCompiled(selector: Selector): boolean
   /** Returns the closest ancestor of the current element (or the current element itself) which matches the selectors given in parameter. */
   closest(selectors: string): NodeRepr | null
+  /** Returns the closest ancestor of the current element (or the current element itself) which matches the precompiled selector. */
+  closestCompiled(selector: Selector): NodeRepr | null
+  /**
+   * Evaluates an XPath 1.0 expression against `contextNode`, or this node if it is
+   * omitted. `resolver` maps namespace prefixes to URIs, either as a function or as an
+   * object with a `lookupNamespaceURI` method.
+   */
+  evaluate(expression: string, contextNode?: NodeRepr | null, resolver?: ((prefix: string) => string | null) | { lookupNamespaceURI(prefix: string): string | null } | null, type?: number, result?: XPathResult | null): XPathResult
   /** The node object, cann't be instantiated in javascript. So call the constructor will throw an error. */
   constructor(): void
   /** Clone this node to a new instance, not clone its descendants. */
@@ -225,5 +342,208 @@ export declare class NodeRepr {
   cloneNode(deep?: boolean | undefined | null): NodeRepr
 }
 
-/** Parse string input to a html tree, return the root node. */
-export declare function parse(html: string): NodeRepr
+/** A selector list parsed once, which can be reused to query many nodes and documents. */
+export declare class Selector {
+  /**
+   * Parses the selector list, throwing a `SyntaxError` if it is not valid.
+   *
+   * `namespaces` maps prefixes usable as `prefix|name` to namespace URIs, like CSS
+   * `@namespace` rules; the empty prefix sets the default namespace.
+   */
+  constructor(selectors: string, namespaces?: Record<string, string> | undefined | null)
+  /** The specificity `[a, b, c]` of each selector in the list, in source order. */
+  get specificities(): Array<[number, number, number]>
+  /**
+   * Returns the selector in the list that `node` matches with the highest specificity,
+   * the first one winning ties, or null if none matches. As in a style sheet, `:scope`
+   * matches the root element.
+   */
+  matchDetails(node: NodeRepr): SelectorMatch | null
+}
+
+/**
+ * The result of `evaluate()`, converted to the requested result type.
+ *
+ * Node results are always snapshots, so iterators are never invalidated by later
+ * changes to the document.
+ */
+export declare class XPathResult {
+  /** The type of the result, one of the `XPathResult` constants. */
+  get resultType(): number
+  /** The value of a `NUMBER_TYPE` result. */
+  get numberValue(): number
+  /** The value of a `STRING_TYPE` result. */
+  get stringValue(): string
+  /** The value of a `BOOLEAN_TYPE` result. */
+  get booleanValue(): boolean
+  /** The node of an `ANY_UNORDERED_NODE_TYPE` or `FIRST_ORDERED_NODE_TYPE` result. */
+  get singleNodeValue(): NodeRepr | Attr | null
+  /** The number of nodes in a snapshot result. */
+  get snapshotLength(): number
+  /** Always false: iterators walk a snapshot taken when the expression was evaluated. */
+  get invalidIteratorState(): boolean
+  /** Returns the node at `index` in a snapshot result, or null if out of range. */
+  snapshotItem(index: number): NodeRepr | Attr | null
+  /** Returns the next node of an iterator result, or null once it is exhausted. */
+  iterateNext(): NodeRepr | Attr | null
+}
+
+/**
+ * Parse string input to a html tree, return the root node.
+ * A `Buffer` is decoded with the encoding sniffed from its bytes.
+ */
+export declare function parse(html: string | Buffer, options?: ParseOptions | undefined | null): NodeRepr
+
+/**
+ * Parse input like `parse` on the thread pool, so that large documents do not block
+ * the event loop. Resolves to the root node.
+ */
+export declare function parseAsync(html: string | Buffer, options?: ParseOptions | undefined | null): Promise<NodeRepr>
+
+/**
+ * Parse string input as the content of `context`, an element or a tag name, the
+ * way `innerHTML` reads it. Returns a document fragment holding the nodes.
+ */
+export declare function parseFragment(html: string, context: string | NodeRepr, options?: ParseOptions | undefined | null): NodeRepr
+
+/**
+ * Parse an XML document of the given MIME type, "application/xml" by default. A
+ * document that is not well-formed becomes a `<parsererror>` document. Of `options`,
+ * only `limits` applies.
+ */
+export declare function parseXml(xml: string, mimeType?: string | undefined | null, options?: ParseOptions | undefined | null): NodeRepr
+
+/**
+ * Parse string input like `parse`, also returning the parse errors with their
+ * line and column.
+ */
+export declare function parseWithDiagnostics(html: string, options?: ParseOptions | undefined | null): ParseResult
+
+/**
+ * A span of the parsed source. Lines and columns are 1-based, and offsets index
+ * into the parsed string.
+ */
+export interface Location {
+  startLine: number
+  startCol: number
+  startOffset: number
+  endLine: number
+  endCol: number
+  endOffset: number
+}
+
+/** A parse error reported by the HTML tokenizer or tree builder. */
+export interface ParseError {
+  message: string
+  /** 1-based line where the parser was when it detected the error. */
+  line: number
+  /** 1-based column where the parser was when it detected the error. */
+  column: number
+}
+
+/** Options for `parse` and `DOMParser.parseFromString`. */
+export interface ParseOptions {
+  /**
+   * Parse as if scripting were enabled, which makes `<noscript>` content raw text.
+   * Defaults to true.
+   */
+  scriptingEnabled?: boolean
+  /** Parse the document as the `srcdoc` of an `iframe`. Defaults to false. */
+  iframeSrcdoc?: boolean
+  /**
+   * Forces the document into this quirks mode, whatever its doctype says. The tree
+   * builder also starts in it, which decides how an `iframe` `srcdoc` without a
+   * doctype is built; other documents are still built as their doctype asks.
+   */
+  quirksMode?: 'quirks' | 'limited-quirks' | 'no-quirks'
+  /**
+   * Report every parse error the spec describes, at some cost in speed. Defaults to
+   * false.
+   */
+  exactErrors?: boolean
+  /**
+   * Record where each node came from in the input, for `sourceCodeLocation`.
+   * Defaults to false.
+   */
+  sourceCodeLocationInfo?: boolean
+  /**
+   * The charset of the `Content-Type` header a `Buffer` was served with. A byte
+   * order mark still takes precedence.
+   */
+  transportCharset?: string
+  /**
+   * Limits for untrusted input, which also apply to markup later parsed into the
+   * document. Exceeding one throws a `RangeError`.
+   */
+  limits?: ParseLimits
+}
+
+/** Limits on what a parse may produce. Every limit is off unless set. */
+export interface ParseLimits {
+  /** The most ancestors a node may have, counting the document. */
+  maxDepth?: number
+  /** The most nodes a single parse may create. */
+  maxNodes?: number
+  /** The most attributes an element may have. */
+  maxAttributes?: number
+  /** The longest input, in bytes, that is parsed at all. */
+  maxInputSize?: number
+}
+
+/** A parsed document with the errors found while parsing it. */
+export interface ParseResult {
+  document: NodeRepr
+  errors: Array<ParseError>
+}
+
+/** Where a node came from in the parsed source, in the shape parse5 uses. */
+export interface SourceCodeLocation {
+  startLine: number
+  startCol: number
+  startOffset: number
+  endLine: number
+  endCol: number
+  endOffset: number
+  /** The start tag of an element. */
+  startTag?: Location
+  /** The end tag of an element, if it was closed by one rather than implied. */
+  endTag?: Location
+}
+
+/** The selector of a list that a node matched. */
+export interface SelectorMatch {
+  /** Position of the selector in the list. */
+  index: number
+  /** The selector as written. */
+  selector: string
+  specificity: [number, number, number]
+}
+
+/** Options for `outerHtml` and `innerHtml`. By default the tree is written as it is. */
+export interface SerializeOptions {
+  /**
+   * Put each child of an element holding only block-level elements on a line of its
+   * own, indented by this many spaces per level.
+   */
+  indent?: number
+  /**
+   * Collapse runs of whitespace in text, and drop whitespace between block-level
+   * elements. Whitespace in `<pre>`, `<textarea>`, scripts and styles is kept.
+   */
+  collapseWhitespace?: boolean
+  /** Leave out end tags the parser would imply, such as `</li>` and `</p>`. */
+  omitOptionalTags?: boolean
+  /** Leave out comments. */
+  removeComments?: boolean
+  /** Write attribute values without quotes when nothing in them needs quoting. */
+  unquotedAttributes?: boolean
+  /** Write empty and boolean attributes, such as `disabled="disabled"`, by name alone. */
+  shortenBooleanAttributes?: boolean
+  /**
+   * How text and attribute values are escaped. `"minimal"`, the default, escapes only
+   * what HTML needs; `"ascii"` also writes every non-ASCII character as a numeric
+   * reference, and `"named"` those with a named reference by name. A function is
+   * called with each text or attribute value instead, and returns it escaped.
+   */
+  characterReferences?: 'minimal' | 'ascii' | 'named' | ((text: string, inAttribute: boolean) => string)
+}
@@ -572,5 +572,14 @@ if (!nativeBinding) {
 }
 
 module.exports = nativeBinding
+module.exports.Attr = nativeBinding.Attr
+module.exports.DocumentParser = nativeBinding.DocumentParser
+module.exports.HtmlChunks = nativeBinding.HtmlChunks
 module.exports.NodeRepr = nativeBinding.NodeRepr
+module.exports.Selector = nativeBinding.Selector
+module.exports.XPathResult = nativeBinding.XPathResult
 module.exports.parse = nativeBinding.parse
+module.exports.parseAsync = nativeBinding.parseAsync
+module.exports.parseFragment = nativeBinding.parseFragment
+module.exports.parseWithDiagnostics = nativeBinding.parseWithDiagnostics
+module.exports.parseXml = nativeBinding.parseXml
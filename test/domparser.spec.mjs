@@ -1,6 +1,7 @@
 import test from 'node:test'
 import assert from 'node:assert/strict'
-import { DOMParser } from '../domparser.js'
+import { Readable, Writable } from 'node:stream'
+import { DOMParser, DocumentParser, XMLSerializer, parse, parseAsync, parseFragment, parseStream, parseWithDiagnostics, parseXml } from '../domparser.js'
 
 test('DOMParser should parse html string', () => {
   const parser = new DOMParser()
@@ -15,12 +16,238 @@ test('DOMParser should throw on unsupported mime type', () => {
   const parser = new DOMParser()
   assert.throws(
     () => {
-      parser.parseFromString('<div></div>', 'text/xml')
+      parser.parseFromString('<div></div>', 'text/plain')
     },
-    { message: 'Unsupported mime type: text/xml' },
+    { message: 'Unsupported mime type: text/plain' },
   )
 })
 
+test('DOMParser should parse xml', () => {
+  const parser = new DOMParser()
+  const svg = parser.parseFromString(
+    '<?xml version="1.0"?>\n<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><linearGradient id="g"/><use xlink:href="#g"/></svg>',
+    'image/svg+xml',
+  )
+  assert.strictEqual(svg.contentType, 'image/svg+xml')
+  assert.strictEqual(svg.childNodes.length, 1)
+  assert.strictEqual(svg.documentElement.namespaceURI, 'http://www.w3.org/2000/svg')
+  assert.strictEqual(svg.querySelector('linearGradient').tagName, 'linearGradient')
+  assert.strictEqual(svg.querySelector('use').getAttributeNS('http://www.w3.org/1999/xlink', 'href'), '#g')
+
+  const xhtml = parser.parseFromString('<html xmlns="http://www.w3.org/1999/xhtml"><body><P/></body></html>', 'application/xhtml+xml')
+  assert.strictEqual(xhtml.querySelector('p'), null)
+  assert.strictEqual(xhtml.getElementsByTagName('P')[0].tagName, 'P')
+
+  const feed = parseXml('<rss><channel><dc:title xmlns:dc="http://purl.org/dc/elements/1.1/">News</dc:title></channel></rss>')
+  assert.strictEqual(feed.contentType, 'application/xml')
+  assert.strictEqual(feed.querySelector('channel').firstChild.tagName, 'dc:title')
+  assert.throws(() => parseXml('<a/>', 'text/html'), TypeError)
+})
+
+test('DOMParser reports malformed xml as a parsererror document', () => {
+  const doc = new DOMParser().parseFromString('<a>\n  <b></a>', 'text/xml')
+  const root = doc.documentElement
+  assert.strictEqual(doc.childNodes.length, 1)
+  assert.strictEqual(root.tagName, 'parsererror')
+  assert.strictEqual(root.namespaceURI, 'http://www.mozilla.org/newlayout/xml/parsererror.xml')
+  assert.match(root.textContent, /Line Number 2, Column 9/)
+  assert.strictEqual(root.querySelector('sourcetext').textContent, '  <b></a>\n--------^')
+  assert.strictEqual(parseXml('<a><b>').documentElement.tagName, 'parsererror')
+  assert.strictEqual(parseXml('<a/><b/>').documentElement.tagName, 'parsererror')
+})
+
+test('parse options', () => {
+  const html = '<p>Hi</p><noscript><img src="a.png"></noscript>'
+  const parser = new DOMParser()
+
+  assert.strictEqual(parse(html).querySelector('noscript > img'), null)
+  assert.strictEqual(parse(html, {}).querySelector('noscript').textContent, '<img src="a.png">')
+  assert.ok(parse(html, { scriptingEnabled: false }).querySelector('noscript > img'))
+  assert.ok(
+    parser.parseFromString(html, 'text/html', { scriptingEnabled: false }).querySelector('noscript > img'),
+  )
+
+  // Without a doctype, only a non-srcdoc document is in quirks mode, where a table
+  // does not close an open paragraph.
+  const table = '<p><table></table>'
+  assert.ok(parse(table).querySelector('p > table'))
+  assert.strictEqual(parse(table, { iframeSrcdoc: true }).querySelector('p > table'), null)
+  assert.ok(parse(table, { iframeSrcdoc: true, quirksMode: 'quirks' }).querySelector('p > table'))
+  assert.throws(() => parse(table, { quirksMode: 'standards' }), TypeError)
+  assert.ok(parse('<p>a</b>', { exactErrors: true }).querySelector('p'))
+})
+
+test('parse limits reject hostile input', () => {
+  const deep = '<div>'.repeat(10000)
+  assert.throws(() => parse(deep, { limits: { maxDepth: 256 } }), {
+    name: 'RangeError',
+    message: 'Nodes are nested more than 256 deep',
+  })
+  assert.throws(() => parse('<p>a</p>'.repeat(100), { limits: { maxNodes: 50 } }), RangeError)
+  assert.throws(() => parse('<p a b c>', { limits: { maxAttributes: 2 } }), RangeError)
+  assert.throws(() => parse(Buffer.from('<p>hello</p>'), { limits: { maxInputSize: 8 } }), RangeError)
+  // The size is that of the input as given, which grows as Latin-1 is decoded.
+  const latin1 = Buffer.from(`<meta charset="windows-1252"><p>${'\xe9'.repeat(100)}</p>`, 'latin1')
+  const decoded = parse(latin1, { limits: { maxInputSize: latin1.length } })
+  assert.equal(decoded.querySelector('p').textContent, 'é'.repeat(100))
+
+  // Markup parsed into the document later is held to the same limits.
+  const doc = parse('<div id="a"><p>keep</p></div>', { limits: { maxDepth: 6 } })
+  const div = doc.getElementById('a')
+  div.innerHTML = '<b><i>ok</i></b>'
+  assert.strictEqual(div.innerHTML, '<b><i>ok</i></b>')
+  assert.throws(() => { div.innerHTML = '<b><i><u>deep</u></i></b>' }, RangeError)
+  assert.throws(() => div.insertAdjacentHTML('beforeend', '<b><i><u>deep</u></i></b>'), RangeError)
+  assert.strictEqual(div.innerHTML, '<b><i>ok</i></b>')
+})
+
+test('parse limits apply to streams, fragments, located parses and xml', async () => {
+  const parser = new DocumentParser({ limits: { maxNodes: 100 } })
+  for (let i = 0; i < 1000; i++) {
+    parser.feed('<p>spam</p>')
+  }
+  assert.throws(() => parser.finish(), { name: 'RangeError', message: 'Input has more than 100 nodes' })
+  await assert.rejects(parseStream(Readable.from(['<div>'.repeat(50)]), { limits: { maxDepth: 20 } }), RangeError)
+
+  assert.strictEqual(parseFragment('<b><i>ok</i></b>', 'div', { limits: { maxDepth: 3 } }).childNodes.length, 1)
+  assert.throws(() => parseFragment('<b><i><u>deep</u></i></b>', 'div', { limits: { maxDepth: 3 } }), RangeError)
+  assert.throws(() => parseFragment('<p a b c>', 'div', { limits: { maxAttributes: 2 } }), RangeError)
+
+  const located = { sourceCodeLocationInfo: true, limits: { maxDepth: 256 } }
+  assert.throws(() => parse('<div>'.repeat(1000), located), RangeError)
+
+  const feed = `<feed>${'<entry/>'.repeat(1000)}</feed>`
+  assert.throws(() => parseXml(feed, 'application/xml', { limits: { maxNodes: 100 } }), { name: 'RangeError', message: 'Input has more than 100 nodes' })
+  assert.throws(() => new DOMParser().parseFromString('<a>'.repeat(1000), 'application/xml', { limits: { maxDepth: 64 } }), RangeError)
+  assert.throws(() => parseXml('<a b="1" c="2" d="3"/>', 'text/xml', { limits: { maxAttributes: 2 } }), RangeError)
+  assert.throws(() => parseXml('<a/>', undefined, { limits: { maxInputSize: 2 } }), RangeError)
+  assert.equal(parseXml(feed, 'application/xml', { limits: { maxNodes: 1001 } }).querySelectorAll('entry').length, 1000)
+})
+
+test('parseWithDiagnostics reports errors with line and column', () => {
+  const { document, errors } = parseWithDiagnostics('<!DOCTYPE html>\n<div>\r\n  <p>a</b>\n</div>')
+  assert.strictEqual(document.querySelector('p').textContent, 'a\n')
+  assert.deepStrictEqual(errors.map(({ line, column }) => [line, column]), [[3, 10]])
+  assert.strictEqual(typeof errors[0].message, 'string')
+
+  assert.deepStrictEqual(parseWithDiagnostics('<!DOCTYPE html><p>ok</p>').errors, [])
+
+  const missing = parseWithDiagnostics('<p>a')
+  assert.deepStrictEqual(missing.errors.map(({ line, column }) => [line, column]), [[1, 3]])
+
+  const exact = parseWithDiagnostics('<p>a', { exactErrors: true })
+  assert.notStrictEqual(exact.errors[0].message, missing.errors[0].message)
+})
+
+test('sourceCodeLocation', () => {
+  const html = '<!DOCTYPE html>\n<div id=a>x &amp; é<p>one<p>two</div><!-- c -->\r\n<b>b<i>i</b>'
+  const doc = parse(html, { sourceCodeLocationInfo: true })
+  const source = (location) => html.slice(location.startOffset, location.endOffset)
+
+  const div = doc.querySelector('div').sourceCodeLocation
+  assert.strictEqual(source(div), '<div id=a>x &amp; é<p>one<p>two</div>')
+  assert.strictEqual(source(div.startTag), '<div id=a>')
+  assert.strictEqual(source(div.endTag), '</div>')
+  assert.deepStrictEqual([div.startLine, div.startCol, div.endLine, div.endCol], [2, 1, 2, 38])
+
+  const [first, second] = doc.querySelectorAll('p')
+  assert.strictEqual(source(first.sourceCodeLocation), '<p>one')
+  assert.strictEqual(first.sourceCodeLocation.endTag, undefined)
+  assert.strictEqual(source(second.firstChild.sourceCodeLocation), 'two')
+  assert.strictEqual(source(doc.querySelector('div').firstChild.sourceCodeLocation), 'x &amp; é')
+  assert.strictEqual(source(doc.firstChild.sourceCodeLocation), '<!DOCTYPE html>')
+
+  const comment = doc.querySelector('div').nextSibling
+  assert.strictEqual(source(comment.sourceCodeLocation), '<!-- c -->')
+  assert.strictEqual(source(comment.nextSibling.sourceCodeLocation), '\r\n')
+  assert.strictEqual(source(doc.querySelector('b').sourceCodeLocation), '<b>b<i>i</b>')
+  assert.strictEqual(doc.querySelector('b').sourceCodeLocation.startLine, 3)
+
+  assert.strictEqual(doc.querySelector('head').sourceCodeLocation, null)
+  const withHead = '<html><head><title>t</title></head><body></body></html>'
+  const head = parse(withHead, { sourceCodeLocationInfo: true }).querySelector('head').sourceCodeLocation
+  assert.strictEqual(withHead.slice(head.endTag.startOffset, head.endTag.endOffset), '</head>')
+  assert.strictEqual(parse(html).querySelector('div').sourceCodeLocation, null)
+  assert.strictEqual(doc.createElement('div').sourceCodeLocation, null)
+})
+
+test('parse sniffs the encoding of a Buffer', () => {
+  const bytes = (...parts) => Buffer.concat(parts.map((part) => Buffer.from(part)))
+  const gbk = [0xd6, 0xd0, 0xce, 0xc4]
+  const sniff = (input, options) => {
+    const doc = parse(input, options)
+    return [doc.characterSet, doc.querySelector('p').textContent]
+  }
+
+  assert.deepStrictEqual(sniff(bytes([0xef, 0xbb, 0xbf], '<p>é')), ['UTF-8', 'é'])
+  assert.deepStrictEqual(sniff(bytes([0xff, 0xfe], Buffer.from('<p>é', 'utf16le'))), ['UTF-16LE', 'é'])
+  assert.deepStrictEqual(sniff(bytes('<meta charset="shift_jis"><p>', [0x82, 0xb1, 0x82, 0xf1])), ['Shift_JIS', 'こん'])
+  assert.deepStrictEqual(
+    sniff(bytes('<meta http-equiv=Content-Type content="text/html; charset=gbk"><p>', gbk)),
+    ['GBK', '中文'],
+  )
+  assert.deepStrictEqual(sniff(bytes('<meta charset=utf-8><p>', gbk), { transportCharset: 'gbk' }), ['GBK', '中文'])
+  assert.deepStrictEqual(sniff(bytes('<meta charset=utf-16><p>é')), ['UTF-8', 'é'])
+  assert.deepStrictEqual(
+    sniff(bytes('<!-- <meta charset=gbk> --><p>caf', [0xe9], ' au lait, un th', [0xe9])),
+    ['windows-1252', 'café au lait, un thé'],
+  )
+  assert.strictEqual(parse('<p>é</p>').characterSet, 'UTF-8')
+})
+
+test('DocumentParser parses chunks', async () => {
+  const parser = new DocumentParser()
+  parser.feed('<ul><li>one')
+  parser.feed('<li>t')
+  parser.feed('wo</ul>')
+  const doc = parser.finish()
+  assert.deepStrictEqual(doc.querySelectorAll('li').map((li) => li.textContent), ['one', 'two'])
+  assert.throws(() => parser.feed('<p>'), { name: 'InvalidStateError' })
+  assert.throws(() => parser.finish(), { name: 'InvalidStateError' })
+
+  // A character split between Buffer chunks is decoded once both halves arrive.
+  const bytes = Buffer.concat([Buffer.from('<meta charset=gbk><p>'), Buffer.from([0xd6, 0xd0, 0xce, 0xc4])])
+  const chunks = [...bytes].map((byte) => Buffer.from([byte]))
+  const streamed = await parseStream(Readable.from(chunks))
+  assert.strictEqual(streamed.characterSet, 'GBK')
+  assert.strictEqual(streamed.querySelector('p').textContent, '中文')
+
+  const located = new DocumentParser({ sourceCodeLocationInfo: true })
+  located.feed('<p>a\r')
+  located.feed('\n<b>b</b>')
+  const b = located.finish().querySelector('b').sourceCodeLocation
+  assert.deepStrictEqual([b.startLine, b.startCol, b.startOffset], [2, 1, 6])
+})
+
+test('parseFragment parses in the context element', () => {
+  const cells = parseFragment('<td>a<td>b', 'tr')
+  assert.strictEqual(cells.nodeType, 11)
+  assert.deepStrictEqual(cells.childNodes.map((node) => node.nodeName), ['TD', 'TD'])
+
+  const options = parseFragment('<option>a<option>b', 'select')
+  assert.deepStrictEqual(options.childNodes.map((node) => node.textContent), ['a', 'b'])
+  assert.strictEqual(parseFragment('<td>a</td>', 'template').firstChild.nodeName, 'TD')
+  assert.strictEqual(parseFragment('<b>a</b>', 'title').textContent, '<b>a</b>')
+
+  const doc = parse('<form><svg></svg></form>')
+  const circle = parseFragment('<circle r="1"/>', doc.querySelector('svg')).firstChild
+  assert.strictEqual(circle.namespaceURI, 'http://www.w3.org/2000/svg')
+  const input = parseFragment('<input>', doc.querySelector('form')).firstChild
+  assert.strictEqual(input.nodeName, 'INPUT')
+})
+
+test('innerHTML and insertAdjacentHTML parse in the right context', () => {
+  const doc = parse('<table><tr id="row"><td>old</td></tr></table>')
+  const row = doc.getElementById('row')
+  row.innerHTML = '<td>a</td><td>b</td>'
+  assert.strictEqual(row.outerHTML, '<tr id="row"><td>a</td><td>b</td></tr>')
+  row.insertAdjacentHTML('afterend', '<tr><td>c</td></tr>')
+  assert.strictEqual(row.nextSibling.outerHTML, '<tr><td>c</td></tr>')
+  row.outerHTML = '<tr><td>d</td></tr>'
+  assert.strictEqual(doc.querySelector('tbody').innerHTML, '<tr><td>d</td></tr><tr><td>c</td></tr>')
+  assert.strictEqual(row.parentNode, null)
+})
+
 test('should support innerHTML setter', () => {
   const parser = new DOMParser()
   const doc = parser.parseFromString('<div></div>', 'text/html')
@@ -162,3 +389,244 @@ test('should support documentElement', () => {
   assert.ok(doc.documentElement)
   assert.strictEqual(doc.documentElement.tagName, 'HTML')
 })
+
+test('deeply nested documents do not overflow the stack', () => {
+  const depth = 100000
+  const doc = parse(`${'<span>'.repeat(depth)}<b id="leaf">leaf</b>`)
+  const copy = doc.cloneNode(true)
+  assert.equal(copy.getElementsByTagName('span').length, depth)
+  assert.equal(copy.getElementById('leaf').textContent, 'leaf')
+  assert.equal(copy.querySelector('b').id, 'leaf')
+  assert.equal(copy.querySelectorAll('span').length, depth)
+  assert.equal(copy.textContent, 'leaf')
+  copy.normalize()
+  assert.ok(copy.outerHTML.endsWith(`<b id="leaf">leaf</b>${'</span>'.repeat(depth)}</body></html>`))
+})
+
+test('quirks mode comes from the doctype unless forced', () => {
+  const quirks = parse('<p id="Main" class="Intro">Hello</p>')
+  assert.equal(quirks.compatMode, 'BackCompat')
+  assert.equal(quirks.quirksMode, 'quirks')
+  assert.equal(quirks.querySelector('.intro #main'), null)
+  assert.equal(quirks.querySelector('p.intro#main').textContent, 'Hello')
+  assert.equal(quirks.getElementsByClassName('INTRO').length, 1)
+
+  const standards = parse('<!DOCTYPE html><p id="Main" class="Intro">Hello</p>')
+  assert.equal(standards.compatMode, 'CSS1Compat')
+  assert.equal(standards.quirksMode, 'no-quirks')
+  assert.equal(standards.querySelector('.intro'), null)
+  assert.equal(standards.getElementsByClassName('intro').length, 0)
+
+  const limited = parse('<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN"><p>')
+  assert.equal(limited.quirksMode, 'limited-quirks')
+  assert.equal(limited.compatMode, 'CSS1Compat')
+
+  const srcdoc = parse('<p>', { iframeSrcdoc: true, quirksMode: 'quirks' })
+  assert.equal(srcdoc.compatMode, 'BackCompat')
+
+  // A forced mode wins over the doctype, or the lack of one.
+  const forced = parse('<!DOCTYPE html><p id="Main" class="Intro">Hello</p>', { quirksMode: 'quirks' })
+  assert.equal(forced.quirksMode, 'quirks')
+  assert.equal(forced.querySelector('.intro').textContent, 'Hello')
+  assert.equal(parse('<p>', { quirksMode: 'no-quirks' }).compatMode, 'CSS1Compat')
+  assert.equal(parse('<p>', { quirksMode: 'limited-quirks', sourceCodeLocationInfo: true }).quirksMode, 'limited-quirks')
+  assert.equal(parseWithDiagnostics('<!DOCTYPE html>', { quirksMode: 'quirks' }).document.compatMode, 'BackCompat')
+  assert.equal(parse('<p>', { sourceCodeLocationInfo: true }).compatMode, 'BackCompat')
+  assert.equal(parse(Buffer.from('<!DOCTYPE html><p>')).compatMode, 'CSS1Compat')
+  assert.equal(parseXml('<root/>').compatMode, 'CSS1Compat')
+
+  // A table may be a child of a paragraph in quirks mode, but closes it otherwise.
+  quirks.body.innerHTML = '<p>a<table></table>'
+  assert.notEqual(quirks.body.querySelector('p > table'), null)
+  standards.body.innerHTML = '<p>a<table></table>'
+  assert.equal(standards.body.querySelector('p > table'), null)
+})
+
+test('parseAsync parses on the thread pool', async () => {
+  const doc = await parseAsync('<!DOCTYPE html><ul><li>one<li>two</ul><!-- note -->', { sourceCodeLocationInfo: true })
+  assert.equal(doc.compatMode, 'CSS1Compat')
+  assert.deepEqual(doc.selectAll('li').map((li) => li.textContent), ['one', 'two'])
+  assert.equal(doc.select('ul').sourceCodeLocation.startOffset, 15)
+
+  const bytes = await parseAsync(Buffer.from('<meta charset=gbk><p>\xc4\xe3\xba\xc3</p>', 'latin1'))
+  assert.equal(bytes.characterSet, 'GBK')
+  assert.equal(bytes.select('p').textContent, '你好')
+
+  await assert.rejects(parseAsync('<div>'.repeat(10), { limits: { maxDepth: 4 } }), RangeError)
+})
+
+test('outerHtmlAsync and selectAsync work on a copy of the tree', async () => {
+  const doc = parse('<p id="Main" class="Intro">a<!-- c --><b>b</b></p><p>c</p>')
+  const p = doc.select('p')
+  const copy = p.cloneNode(true)
+  assert.equal(await copy.outerHtmlAsync(), p.outerHtml())
+  assert.equal(await doc.body.outerHtmlAsync(), doc.body.outerHtml())
+
+  // Leaf nodes are copied with buffers of their own too.
+  const comment = p.childNodes[1]
+  assert.equal(await comment.outerHtmlAsync(), '<!-- c -->')
+  assert.equal(comment.data, ' c ')
+  const doctype = parse('<!DOCTYPE html><p>').firstChild
+  assert.equal(await doctype.outerHtmlAsync(), '<!DOCTYPE html>')
+
+  const first = await doc.selectAsync('.intro')
+  assert.ok(first.isSameNode(p))
+  const all = await doc.selectAllAsync('p')
+  assert.equal(all.length, 2)
+  assert.ok(all[1].isSameNode(doc.selectAll('p')[1]))
+  assert.equal(await doc.selectAsync('table'), null)
+  assert.throws(() => doc.selectAsync('p['), { name: 'SyntaxError' })
+
+  // Selectors see the ancestors of the node selected from, and :scope is that node.
+  const list = parse('<div class="menu"><ul><li>a</li><li><ul><li>b</li></ul></li></ul></div>').select('ul')
+  assert.deepEqual((await list.selectAllAsync('.menu li')).map((li) => li.textContent), ['a', 'b', 'b'])
+  assert.deepEqual((await list.selectAllAsync(':scope > li')).map((li) => li.textContent), ['a', 'b'])
+  assert.ok((await list.selectAsync(':scope li li')).isSameNode(list.select('li li')))
+})
+
+test('serializer options format and minify html', () => {
+  const doc = parse('<!DOCTYPE html><html><head><title>T</title></head><body><ul>\n  <li>One   two</li>\n  <li>Three</li>\n</ul><pre>  keep\n  this</pre></body></html>')
+  assert.equal(doc.outerHtml(), doc.outerHTML)
+  assert.equal(doc.outerHtml({}), doc.outerHTML)
+
+  const ul = doc.select('ul')
+  assert.equal(ul.outerHtml({ indent: 2 }), '<ul>\n  <li>One   two</li>\n  <li>Three</li>\n</ul>')
+  assert.equal(ul.outerHtml({ collapseWhitespace: true }), '<ul><li>One two</li><li>Three</li></ul>')
+  assert.equal(ul.outerHtml({ collapseWhitespace: true, omitOptionalTags: true }), '<ul><li>One two<li>Three</ul>')
+  assert.equal(doc.select('pre').outerHtml({ collapseWhitespace: true }), '<pre>  keep\n  this</pre>')
+  assert.equal(
+    doc.outerHtml({ indent: 2 }),
+    '<!DOCTYPE html>\n<html>\n  <head>\n    <title>T</title>\n  </head>\n  <body>\n    <ul>\n      <li>One   two</li>\n      <li>Three</li>\n    </ul>\n    <pre>  keep\n  this</pre>\n  </body>\n</html>',
+  )
+
+  const form = parse('<p>a<!-- note --><input disabled="disabled" value="" type="text" hidden="until-found" title="a b"></p>').select('p')
+  assert.equal(
+    form.innerHtml({ removeComments: true, unquotedAttributes: true, shortenBooleanAttributes: true }),
+    'a<input disabled value type=text hidden=until-found title="a b">',
+  )
+  assert.equal(form.innerHtml({ shortenBooleanAttributes: true }), 'a<!-- note --><input disabled value type="text" hidden="until-found" title="a b">')
+
+  const deep = parse('<div>'.repeat(40)).select('body > div')
+  const lines = deep.outerHtml({ indent: 4000 }).split('\n')
+  assert.equal(lines.length, 79)
+  assert.equal(lines[39], `${' '.repeat(39 * 4000)}<div></div>`)
+  assert.equal(lines[78], '</div>')
+})
+
+test('XMLSerializer writes well-formed xml', () => {
+  const serializer = new XMLSerializer()
+  const doc = parse('<!DOCTYPE html><title>A &amp; B</title><script>if (a < b) {}</script><p title="x < &quot;y&quot;">Hi<br><img src="a.png"><!-- a -- b- --></p><svg viewBox="0 0 1 1"><use xlink:href="#a"/><circle r="1"></circle></svg>')
+  const xhtml = serializer.serializeToString(doc)
+  assert.equal(
+    xhtml,
+    '<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"><head><title>A &amp; B</title>' +
+      '<script><![CDATA[if (a < b) {}]]></script></head><body>' +
+      '<p title="x &lt; &quot;y&quot;">Hi<br /><img src="a.png" /><!-- a - - b- --></p>' +
+      '<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 1 1">' +
+      '<use xmlns:xlink="http://www.w3.org/1999/xlink" xlink:href="#a"/><circle r="1"/></svg></body></html>',
+  )
+  const reparsed = parseXml(xhtml, 'application/xhtml+xml')
+  assert.equal(reparsed.documentElement.localName, 'html')
+  assert.equal(reparsed.querySelector('p').getAttribute('title'), 'x < "y"')
+
+  assert.equal(serializer.serializeToString(doc.select('svg circle')), '<circle xmlns="http://www.w3.org/2000/svg" r="1"/>')
+  assert.equal(doc.select('p').outerXml(), serializer.serializeToString(doc.select('p')))
+
+  const feed = parseXml('<feed xmlns="urn:feed" xmlns:media="urn:media"><media:thumbnail url="a&amp;b"/><entry/></feed>')
+  assert.equal(
+    serializer.serializeToString(feed),
+    '<feed xmlns="urn:feed"><media:thumbnail xmlns:media="urn:media" url="a&amp;b"/><entry/></feed>',
+  )
+})
+
+test('serializeTo writes chunks to a function or a writable stream', async () => {
+  const doc = parse(`<ul>${'<li class="item">Café &amp; crème</li>'.repeat(5000)}</ul>`)
+  const chunks = []
+  await doc.serializeTo((chunk) => chunks.push(chunk))
+  assert.ok(chunks.length > 1)
+  assert.ok(chunks.every((chunk) => Buffer.isBuffer(chunk) && chunk.toString() === new TextDecoder().decode(chunk)))
+  assert.equal(Buffer.concat(chunks).toString(), doc.outerHtml())
+
+  const written = []
+  const stream = new Writable({
+    write(chunk, _encoding, callback) {
+      written.push(chunk)
+      callback()
+    },
+  })
+  const ul = doc.select('ul')
+  await ul.serializeTo(stream, { omitOptionalTags: true })
+  stream.end()
+  await new Promise((resolve) => stream.on('finish', resolve))
+  assert.equal(Buffer.concat(written).toString(), ul.outerHtml({ omitOptionalTags: true }))
+
+  let calls = 0
+  await assert.rejects(doc.serializeTo(() => {
+    calls++
+    throw new Error('disk full')
+  }), { message: 'disk full' })
+  assert.equal(calls, 1)
+})
+
+test('serializeTo waits for a slow stream to drain', async () => {
+  const doc = parse(`<ul>${'<li class="item">Café &amp; crème</li>'.repeat(20000)}</ul>`)
+  const written = []
+  let buffered = 0
+  const slow = new Writable({
+    highWaterMark: 1024,
+    write(chunk, _encoding, callback) {
+      buffered = Math.max(buffered, slow.writableLength)
+      written.push(chunk)
+      setTimeout(callback, 1)
+    },
+  })
+  await doc.serializeTo(slow)
+  assert.ok(written.length > 10)
+  assert.ok(buffered < 2 * 64 * 1024, `${buffered} bytes were buffered`)
+  assert.equal(Buffer.concat(written).toString(), doc.outerHtml())
+
+  const paced = []
+  await doc.serializeTo(async (chunk) => {
+    await new Promise((resolve) => setTimeout(resolve, 1))
+    paced.push(chunk)
+  })
+  assert.equal(Buffer.concat(paced).toString(), doc.outerHtml())
+})
+
+test('characterReferences chooses how text is escaped', async () => {
+  const p = parse('<p title="café &quot;ñ&quot;">Crème&nbsp;brûlée &amp; 😀 &lt;3</p>').select('p')
+  assert.equal(p.outerHtml(), '<p title="café &quot;ñ&quot;">Crème&nbsp;brûlée &amp; 😀 &lt;3</p>')
+  assert.equal(p.outerHtml({ characterReferences: 'minimal' }), p.outerHtml())
+  assert.equal(
+    p.outerHtml({ characterReferences: 'ascii' }),
+    '<p title="caf&#233; &quot;&#241;&quot;">Cr&#232;me&nbsp;br&#251;l&#233;e &amp; &#128512; &lt;3</p>',
+  )
+  assert.equal(
+    p.outerHtml({ characterReferences: 'named' }),
+    '<p title="caf&eacute; &quot;&ntilde;&quot;">Cr&egrave;me&nbsp;br&ucirc;l&eacute;e &amp; 😀 &lt;3</p>',
+  )
+  assert.equal(await p.outerHtmlAsync({ characterReferences: 'ascii' }), p.outerHtml({ characterReferences: 'ascii' }))
+
+  const seen = []
+  const upper = p.innerHtml({
+    characterReferences: (text, inAttribute) => {
+      seen.push([text, inAttribute])
+      return text.toUpperCase().replaceAll('&', '&amp;').replaceAll('<', '&lt;')
+    },
+  })
+  assert.equal(upper, 'CRÈME\u00a0BRÛLÉE &amp; 😀 &lt;3')
+  assert.deepEqual(seen, [['Crème\u00a0brûlée & 😀 <3', false]])
+  assert.equal(
+    parse('<a href="?a=1&amp;b=2">x</a>').select('a').outerHtml({ characterReferences: (text) => text.replaceAll('&', '&#38;') }),
+    '<a href="?a=1&#38;b=2">x</a>',
+  )
+  const spaced = (text, inAttribute) => (inAttribute ? text.replaceAll('-', ' > ') : text)
+  assert.equal(
+    parse('<a title="a-b" class="c">x</a>').select('a').outerHtml({ characterReferences: spaced, unquotedAttributes: true }),
+    '<a title="a > b" class=c>x</a>',
+  )
+
+  assert.throws(() => p.outerHtml({ characterReferences: 'html4' }), TypeError)
+  assert.throws(() => p.outerHtmlAsync({ characterReferences: (text) => text }), TypeError)
+  assert.throws(() => p.outerHtml({ characterReferences: () => { throw new Error('boom') } }), { message: 'boom' })
+})
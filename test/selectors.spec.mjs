@@ -0,0 +1,485 @@
+import test from 'node:test';
+import assert from 'node:assert/strict';
+import fs from 'node:fs';
+import pkg from '../domparser.js';
+const { DOMParser, Selector } = pkg;
+
+function parse(html) {
+  return new DOMParser().parseFromString(html, 'text/html');
+}
+
+test('compound selectors', () => {
+  const doc = parse(`
+    <div id="x" class="a b">1</div>
+    <div class="a">2</div>
+    <span class="a b">3</span>
+  `);
+
+  assert.strictEqual(doc.querySelectorAll('div.a').length, 2);
+  assert.strictEqual(doc.querySelectorAll('div.a.b').length, 1);
+  assert.strictEqual(doc.querySelector('div.a.b#x').textContent, '1');
+  assert.strictEqual(doc.querySelectorAll('.a.b').length, 2);
+  assert.strictEqual(doc.querySelectorAll('*.b').length, 2);
+  assert.strictEqual(doc.querySelector('span#x'), null);
+  assert.strictEqual(doc.querySelector('#x').matches('.b.a'), true);
+});
+
+test('id selectors must start like an identifier', () => {
+  const doc = parse('<p id="123">1</p><p id="-1">2</p><p id="-a">3</p>');
+
+  assert.strictEqual(doc.querySelector('#\\31 23').textContent, '1');
+  assert.strictEqual(doc.querySelector('#-\\31').textContent, '2');
+  assert.strictEqual(doc.querySelector('#-a').textContent, '3');
+  for (const selector of ['#123', '#-1', 'p#1a', '#']) {
+    assert.throws(() => doc.querySelector(selector), { name: 'SyntaxError' }, selector);
+  }
+});
+
+test('attribute selectors', () => {
+  const doc = parse(`
+    <a id="one" href="/files/report.pdf" rel="nofollow noopener" lang="en-US">1</a>
+    <a id="two" href="https://example.com/" hreflang="en">2</a>
+    <meta property="og:title" content="Hello">
+    <meta property="twitter:card">
+    <input disabled>
+  `);
+
+  const ids = (selector) => doc.querySelectorAll(selector).map((n) => n.id);
+
+  assert.deepStrictEqual(ids('a[href]'), ['one', 'two']);
+  assert.deepStrictEqual(ids('a[href="/files/report.pdf"]'), ['one']);
+  assert.deepStrictEqual(ids("a[href='https://example.com/']"), ['two']);
+  assert.deepStrictEqual(ids('a[rel~=noopener]'), ['one']);
+  assert.deepStrictEqual(ids('a[rel~=noop]'), []);
+  assert.deepStrictEqual(ids('a[lang|=en]'), ['one']);
+  assert.deepStrictEqual(ids('a[hreflang|="en"]'), ['two']);
+  assert.deepStrictEqual(ids('a[href^="https:"]'), ['two']);
+  assert.deepStrictEqual(ids('a[href$=".pdf"]'), ['one']);
+  assert.deepStrictEqual(ids('a[href*=example]'), ['two']);
+  assert.deepStrictEqual(ids('a[href^=""]'), []);
+  assert.strictEqual(doc.querySelectorAll('meta[property^="og:"]').length, 1);
+  assert.strictEqual(doc.querySelectorAll('[ disabled ]').length, 1);
+});
+
+test('attribute value case-sensitivity', () => {
+  const doc = parse(`
+    <a id="one" href="/files/REPORT.PDF" rel="NoFollow" data-kind="Guide">1</a>
+    <input id="two" type="TEXT" lang="EN-us">
+    <svg><a id="three" type="TEXT"></a></svg>
+  `);
+
+  const ids = (selector) => doc.querySelectorAll(selector).map((n) => n.id);
+
+  assert.deepStrictEqual(ids('a[href$=".pdf"]'), []);
+  assert.deepStrictEqual(ids('a[href$=".pdf" i]'), ['one']);
+  assert.deepStrictEqual(ids('a[href$=".pdf"I]'), ['one']);
+  assert.deepStrictEqual(ids('[data-kind=guide]'), []);
+  assert.deepStrictEqual(ids('[data-kind=guide i]'), ['one']);
+  assert.deepStrictEqual(ids('[rel~=nofollow]'), ['one']);
+  assert.deepStrictEqual(ids('[type=text]'), ['two']);
+  assert.deepStrictEqual(ids('[type=text i]'), ['two', 'three']);
+  assert.deepStrictEqual(ids('[type=text s]'), []);
+  assert.deepStrictEqual(ids('[lang|=en]'), ['two']);
+  assert.throws(() => doc.querySelector('[type=text x]'), (error) => error.name === 'SyntaxError' && error.position === 11);
+  assert.throws(() => doc.querySelector('[type i]'), (error) => error.name === 'SyntaxError');
+});
+
+test('combinators', () => {
+  const doc = parse(`
+    <ul id="list">
+      <li id="a">a</li>
+      <li id="b" class="x">b</li>
+      <li id="c">c <span>inner</span></li>
+      <li id="d">d</li>
+    </ul>
+  `);
+
+  const ids = (selector) => doc.querySelectorAll(selector).map((n) => n.id);
+
+  assert.deepStrictEqual(ids('ul > li'), ['a', 'b', 'c', 'd']);
+  assert.deepStrictEqual(ids('ul>li.x'), ['b']);
+  assert.deepStrictEqual(ids('li.x + li'), ['c']);
+  assert.deepStrictEqual(ids('li.x ~ li'), ['c', 'd']);
+  assert.deepStrictEqual(ids('#a ~ li + li'), ['c', 'd']);
+  assert.strictEqual(doc.querySelectorAll('ul > span').length, 0);
+  assert.strictEqual(doc.querySelectorAll('ul span').length, 1);
+  assert.strictEqual(doc.querySelectorAll('body>*').length, 1);
+});
+
+test('selector lists', () => {
+  const doc = parse('<h1>t</h1><p class="lead">p</p><div><p>q</p></div>');
+
+  const nodes = doc.querySelectorAll('p.lead, h1, div > p');
+  assert.deepStrictEqual(
+    nodes.map((n) => n.textContent),
+    ['t', 'p', 'q'],
+  );
+  assert.strictEqual(doc.querySelector('h2, div p').textContent, 'q');
+});
+
+test('matches, closest, select and selectAll share the grammar', () => {
+  const doc = parse(
+    '<section class="card"><div class="body"><a href="/x" class="link">x</a></div></section>',
+  );
+  const link = doc.select('section.card > div.body > a[href].link');
+
+  assert.ok(link);
+  assert.strictEqual(link.matches('.card a[href^="/"]'), true);
+  assert.strictEqual(link.matches('.card > a'), false);
+  assert.strictEqual(link.closest('div.body, section').className, 'body');
+  assert.strictEqual(link.closest('section[class~=card]').tagName, 'SECTION');
+  assert.strictEqual(doc.selectAll('div > a, section > div').length, 2);
+});
+
+test('invalid selectors throw a SyntaxError', () => {
+  const doc = parse('<div class="a"></div>');
+  const div = doc.querySelector('div');
+  const syntaxError = (position) => (error) => {
+    assert.ok(error instanceof DOMException);
+    assert.strictEqual(error.name, 'SyntaxError');
+    assert.strictEqual(error.position, position);
+    return true;
+  };
+
+  assert.throws(() => doc.querySelector('div['), syntaxError(4));
+  assert.throws(() => doc.querySelectorAll('a >> b'), syntaxError(3));
+  assert.throws(() => doc.select('p, '), syntaxError(3));
+  assert.throws(() => doc.selectAll('#'), syntaxError(1));
+  assert.throws(() => div.matches('div,'), syntaxError(4));
+  assert.throws(() => div.closest(''), syntaxError(0));
+  assert.throws(() => new Selector('::before'), syntaxError(1));
+});
+
+test('structural pseudo-classes', () => {
+  const doc = parse(`
+    <table>
+      <tr><td>a1</td><td>a2</td><td>a3</td></tr>
+      <tr><td>b1</td><td>b2</td><td>b3</td></tr>
+      <tr><td>c1</td><td>c2</td><td>c3</td></tr>
+    </table>
+    <ul><li>only</li></ul>
+    <p id="empty"><!-- comment --></p>
+    <p id="text"> </p>
+  `);
+
+  const texts = (selector) => doc.querySelectorAll(selector).map((n) => n.textContent);
+
+  assert.deepStrictEqual(texts('tr > td:nth-child(3)'), ['a3', 'b3', 'c3']);
+  assert.deepStrictEqual(texts('tr:nth-child(odd) td:first-child'), ['a1', 'c1']);
+  assert.deepStrictEqual(texts('tr:nth-child(even) td:last-child'), ['b3']);
+  assert.deepStrictEqual(texts('tr:first-child td:nth-child(-n+2)'), ['a1', 'a2']);
+  assert.deepStrictEqual(texts('tr:last-child td:nth-last-child(1)'), ['c3']);
+  assert.deepStrictEqual(texts('tr:nth-child(2n + 1) > td:nth-child( 2n )'), ['a2', 'c2']);
+  assert.deepStrictEqual(texts('td:nth-of-type(2):nth-last-of-type(2)'), ['a2', 'b2', 'c2']);
+  // Coefficients too large for 32 bits saturate without overflowing.
+  assert.deepStrictEqual(texts('tr:first-child td:nth-child(n-99999999999)'), ['a1', 'a2', 'a3']);
+  assert.deepStrictEqual(texts('td:nth-child(-n-99999999999)'), []);
+  assert.deepStrictEqual(texts('tr:first-child td:nth-child(-n+99999999999)'), ['a1', 'a2', 'a3']);
+  assert.deepStrictEqual(texts('li:only-child'), ['only']);
+  assert.deepStrictEqual(texts('li:only-of-type, td:first-of-type:last-of-type'), ['only']);
+  assert.deepStrictEqual(doc.querySelectorAll('p:empty').map((n) => n.id), ['empty']);
+  assert.strictEqual(doc.querySelector(':root').tagName, 'HTML');
+  assert.strictEqual(doc.querySelector('body:root'), null);
+});
+
+test(':nth-child with of selector', () => {
+  const doc = parse(`
+    <ul>
+      <li class="x">1</li>
+      <li>2</li>
+      <li class="x">3</li>
+      <li class="x">4</li>
+    </ul>
+  `);
+
+  const texts = (selector) => doc.querySelectorAll(selector).map((n) => n.textContent);
+
+  assert.deepStrictEqual(texts('li:nth-child(2 of .x)'), ['3']);
+  assert.deepStrictEqual(texts('li:nth-last-child(1 of .x)'), ['4']);
+  assert.deepStrictEqual(texts('li:nth-child(odd of li.x)'), ['1', '4']);
+  assert.deepStrictEqual(texts('li:nth-child(2)'), ['2']);
+  assert.deepStrictEqual(texts('li:NTH-CHILD(EVEN)'), ['2', '4']);
+});
+
+test(':nth-child and :nth-of-type over a long list', () => {
+  const items = Array.from({ length: 20000 }, (_, i) => `<li${i % 2 ? ' class="x"' : ''}>${i + 1}</li>`);
+  const doc = parse(`<ul><p>0</p>${items.join('')}</ul>`);
+  const texts = (selector) => doc.querySelectorAll(selector).map((n) => n.textContent);
+
+  assert.deepStrictEqual(texts('li:nth-child(-n+3)'), ['1', '2']);
+  assert.deepStrictEqual(texts('li:nth-last-child(-n+2)'), ['19999', '20000']);
+  assert.deepStrictEqual(texts('li:first-of-type, li:last-of-type'), ['1', '20000']);
+  assert.deepStrictEqual(texts('li:first-child, li:last-child'), ['20000']);
+  assert.deepStrictEqual(texts('li:nth-child(3 of .x)'), ['6']);
+  assert.deepStrictEqual(texts('li:nth-last-child(2 of .x)'), ['19998']);
+  assert.strictEqual(texts('li:nth-child(5000n+1)').length, 4);
+});
+
+test('invalid An+B expressions are syntax errors', () => {
+  const doc = parse('<p>1</p>');
+
+  assert.throws(() => doc.querySelector('p:nth-child(x)'), { name: 'SyntaxError' });
+  assert.throws(() => doc.querySelector('p:nth-child(2n+)'), { name: 'SyntaxError' });
+  assert.throws(() => doc.querySelector('p:nth-child(+ 1)'), { name: 'SyntaxError' });
+  assert.throws(() => doc.querySelector('p:nth-child(1'), { name: 'SyntaxError' });
+  assert.strictEqual(doc.querySelector('p:first-child').textContent, '1');
+});
+
+test('deeply nested selectors are syntax errors', () => {
+  const doc = parse('<p>1</p>');
+  const nest = (name, depth) => `:${name}(`.repeat(depth) + 'p' + ')'.repeat(depth);
+
+  assert.strictEqual(doc.querySelector(nest('is', 8)).textContent, '1');
+  for (const name of ['is', 'where', 'not', 'has']) {
+    assert.throws(() => doc.querySelector(nest(name, 100000)), {
+      name: 'SyntaxError',
+      message: /nested too deeply/,
+    });
+  }
+  assert.throws(() => doc.querySelector('p' + ':nth-child(1 of p'.repeat(1000) + ')'.repeat(1000)), {
+    name: 'SyntaxError',
+  });
+});
+
+test(':is(), :where() and :not()', () => {
+  const doc = parse(`
+    <article><h2 id="a">a</h2></article>
+    <section><h2 id="b">b</h2><h3 id="c">c</h3></section>
+    <aside><h2 id="d">d</h2></aside>
+  `);
+
+  const ids = (selector) => doc.querySelectorAll(selector).map((n) => n.id);
+
+  assert.deepStrictEqual(ids(':is(article, section) h2'), ['a', 'b']);
+  assert.deepStrictEqual(ids(':where(section) :is(h2, h3)'), ['b', 'c']);
+  assert.deepStrictEqual(ids('h2:not(#a, aside > *)'), ['b']);
+  assert.deepStrictEqual(ids('h2:not(:is(article *))'), ['b', 'd']);
+  // Invalid arguments are dropped from forgiving lists.
+  assert.deepStrictEqual(ids(':is(section, :bogus, ::before, [x="]"]) > h3'), ['c']);
+  assert.deepStrictEqual(ids(':where(:bogus) h2'), []);
+  assert.throws(() => doc.querySelector('h2:not(:bogus)'), { name: 'SyntaxError' });
+});
+
+test(':has() with relative selectors', () => {
+  const doc = parse(`
+    <div id="card1" class="card"><img src="a.png"><p>text</p></div>
+    <div id="card2" class="card"><p><img src="b.png"></p></div>
+    <div id="card3" class="card"><p>no image</p></div>
+    <h2 id="t1">Title</h2><p class="x">after</p>
+    <h2 id="t2">Other</h2><span></span><p class="x">later</p>
+  `);
+
+  const ids = (selector) => doc.querySelectorAll(selector).map((n) => n.id);
+
+  assert.deepStrictEqual(ids('.card:has(img)'), ['card1', 'card2']);
+  assert.deepStrictEqual(ids('.card:has(> img)'), ['card1']);
+  assert.deepStrictEqual(ids('.card:has(p img)'), ['card2']);
+  assert.deepStrictEqual(ids('.card:not(:has(img))'), ['card3']);
+  assert.deepStrictEqual(ids('h2:has(+ .x)'), ['t1']);
+  assert.deepStrictEqual(ids('h2:has(~ .x)'), ['t1', 't2']);
+  assert.deepStrictEqual(ids('div:has(> p, > img[src$=".png"])'), ['card1', 'card2', 'card3']);
+  assert.throws(() => doc.querySelector('div:has()'), { name: 'SyntaxError' });
+});
+
+test(':scope binds to the queried node', () => {
+  const doc = parse(`
+    <div id="outer">
+      <div id="inner"><p id="deep">x</p></div>
+      <p id="shallow">y</p>
+    </div>
+  `);
+  const outer = doc.getElementById('outer');
+  const inner = doc.getElementById('inner');
+  const ids = (nodes) => nodes.map((n) => n.id);
+
+  assert.deepStrictEqual(ids(outer.querySelectorAll(':scope > p')), ['shallow']);
+  assert.deepStrictEqual(ids(outer.querySelectorAll(':scope p')), ['deep', 'shallow']);
+  assert.deepStrictEqual(ids(inner.querySelectorAll(':scope > p')), ['deep']);
+  assert.strictEqual(outer.querySelector(':scope'), null);
+  assert.strictEqual(inner.matches(':scope'), true);
+  assert.strictEqual(doc.querySelector(':scope > html').tagName, 'HTML');
+  assert.strictEqual(doc.getElementById('deep').closest(':scope').id, 'deep');
+});
+
+test('precompiled selectors', () => {
+  const selector = new Selector('tr > td:nth-child(2)');
+  const first = parse('<table><tr><td>a</td><td>b</td></tr></table>');
+  const second = parse('<table><tr><td>c</td><td>d</td></tr><tr><td>e</td><td>f</td></tr></table>');
+
+  assert.strictEqual(first.selectCompiled(selector).textContent, 'b');
+  assert.deepStrictEqual(
+    second.selectAllCompiled(selector).map((n) => n.textContent),
+    ['d', 'f'],
+  );
+  const cell = second.selectCompiled(selector);
+  assert.strictEqual(cell.matchesCompiled(selector), true);
+  assert.strictEqual(cell.closestCompiled(new Selector('table')).tagName, 'TABLE');
+  assert.strictEqual(cell.closestCompiled(new Selector('ul')), null);
+  assert.throws(() => new Selector('div['));
+});
+
+test('case-sensitivity of foreign elements and attributes', () => {
+  const doc = parse(`
+    <DIV Title="x"></DIV>
+    <svg viewBox="0 0 10 10">
+      <linearGradient id="g"></linearGradient>
+      <foreignObject><p>html inside</p></foreignObject>
+    </svg>
+  `);
+
+  assert.strictEqual(doc.querySelectorAll('div').length, 1);
+  assert.strictEqual(doc.querySelectorAll('DIV[TITLE]').length, 1);
+  assert.strictEqual(doc.querySelectorAll('linearGradient').length, 1);
+  assert.strictEqual(doc.querySelectorAll('lineargradient').length, 0);
+  assert.strictEqual(doc.querySelectorAll('foreignObject > P').length, 1);
+  assert.strictEqual(doc.querySelectorAll('foreignobject').length, 0);
+  assert.strictEqual(doc.querySelectorAll('svg[viewBox]').length, 1);
+  assert.strictEqual(doc.querySelectorAll('svg[viewbox]').length, 0);
+
+  assert.strictEqual(doc.getElementsByTagName('DIV').length, 1);
+  assert.strictEqual(doc.getElementsByTagName('linearGradient').length, 1);
+  assert.strictEqual(doc.getElementsByTagName('LINEARGRADIENT').length, 0);
+  assert.strictEqual(doc.getElementsByTagName('foreignObject')[0].getElementsByTagName('p').length, 1);
+});
+
+test('namespace prefixes', () => {
+  const html = fs.readFileSync(new URL('./svg_ns.html', import.meta.url), 'utf8');
+  const doc = parse(html);
+
+  assert.strictEqual(doc.querySelectorAll('svg|circle').length, 1);
+  assert.strictEqual(doc.querySelectorAll('svg|*').length, 2);
+  assert.strictEqual(doc.querySelectorAll('html|circle').length, 0);
+  assert.strictEqual(doc.querySelectorAll('*|circle').length, 1);
+  assert.strictEqual(doc.querySelectorAll('|circle').length, 0);
+  assert.strictEqual(doc.querySelectorAll('html|p').length, 1);
+  assert.strictEqual(doc.querySelectorAll('circle[|stroke-width="3"]').length, 1);
+  assert.strictEqual(doc.querySelectorAll('circle[svg|fill]').length, 0);
+
+  const custom = new Selector('s|circle, h|h1', {
+    s: 'http://www.w3.org/2000/svg',
+    h: 'http://www.w3.org/1999/xhtml',
+  });
+  assert.deepStrictEqual(doc.selectAllCompiled(custom).map((n) => n.localName), ['h1', 'circle']);
+
+  const svgDefault = new Selector('[r]', { '': 'http://www.w3.org/2000/svg' });
+  assert.strictEqual(doc.selectAllCompiled(svgDefault).length, 1);
+  assert.strictEqual(doc.selectAllCompiled(new Selector('*', { '': 'http://www.w3.org/2000/svg' })).length, 2);
+
+  assert.throws(
+    () => doc.querySelector('foo|circle'),
+    (error) => error.name === 'SyntaxError' && error.position === 0,
+  );
+  assert.throws(() => doc.querySelector('svg|'), (error) => error.name === 'SyntaxError');
+
+  const link = parse('<svg><a xlink:href="#top"></a></svg>').querySelector('a');
+  assert.strictEqual(link.matches('[xlink|href="#top"]'), true);
+  assert.strictEqual(link.matches('[*|href]'), true);
+  assert.strictEqual(link.matches('[|href]'), false);
+  assert.strictEqual(link.matches('[href|="#top"]'), false);
+
+  // Every attribute the name matches is compared, not only the first.
+  const both = parse('<svg><a href="#plain" xlink:href="#top"></a></svg>').querySelector('a');
+  assert.strictEqual(both.matches('[*|href="#plain"]'), true);
+  assert.strictEqual(both.matches('[*|href="#top"]'), true);
+  assert.strictEqual(both.matches('[*|href="#other"]'), false);
+});
+
+test('form pseudo-classes', () => {
+  const doc = parse(`
+    <form id="f">
+      <input id="name" required>
+      <input id="email" type="email" readonly>
+      <input id="agree" type="checkbox" checked>
+      <input id="hidden" type="hidden" required>
+      <fieldset id="outer" disabled>
+        <legend><input id="in-legend"></legend>
+        <textarea id="notes"></textarea>
+      </fieldset>
+      <select id="size">
+        <option id="s">S</option>
+        <optgroup id="g" disabled><option id="m">M</option></optgroup>
+      </select>
+      <select id="multi" multiple><option id="a" selected>A</option><option id="b">B</option></select>
+      <button id="reset" type="reset"></button>
+      <button id="go"></button>
+      <input id="go2" type="submit">
+    </form>
+    <input id="remote" type="submit" form="f">
+    <div id="editable" contenteditable><span id="inner"></span></div>
+  `);
+
+  const ids = (selector) => doc.querySelectorAll(selector).map((n) => n.id);
+
+  assert.deepStrictEqual(ids(':checked'), ['agree', 's', 'a']);
+  assert.deepStrictEqual(ids(':disabled'), ['outer', 'notes', 'g', 'm']);
+  assert.deepStrictEqual(ids('input:enabled'), ['name', 'email', 'agree', 'hidden', 'in-legend', 'go2', 'remote']);
+  assert.deepStrictEqual(ids(':required'), ['name']);
+  assert.deepStrictEqual(ids('input:optional').includes('hidden'), true);
+  assert.deepStrictEqual(ids(':read-write'), ['name', 'in-legend', 'editable', 'inner']);
+  assert.strictEqual(doc.querySelector('#email').matches(':read-only'), true);
+  assert.strictEqual(doc.querySelector('#notes').matches(':read-only'), true);
+  assert.deepStrictEqual(ids(':default'), ['agree', 'a', 'go']);
+
+  const owners = parse(`
+    <input id="first" type="submit" form="g">
+    <form id="g"><button id="second"></button></form>
+    <form id="plain"><input id="elsewhere" type="submit" form="g"><button id="own"></button></form>
+  `);
+  assert.deepStrictEqual(owners.querySelectorAll(':default').map((n) => n.id), ['first', 'own']);
+});
+
+test('link, defined, :lang() and :dir()', () => {
+  const doc = parse(`
+    <div id="root" lang="en-US">
+      <a id="link" href="/x">x</a>
+      <a id="anchor" name="top">top</a>
+      <p id="de" lang="de-Latn-DE"><span id="inherit">Hallo</span></p>
+      <my-widget id="custom"></my-widget>
+      <svg><font-face id="svg-child"></font-face></svg>
+      <div id="rtl" dir="rtl"><span id="rtl-child"></span></div>
+      <div id="auto" dir="auto">  123 שלום</div>
+      <bdi id="bdi">abc</bdi>
+    </div>
+  `);
+
+  const ids = (selector) => doc.querySelectorAll(selector).map((n) => n.id);
+
+  assert.deepStrictEqual(ids(':any-link'), ['link']);
+  assert.deepStrictEqual(ids(':link'), ['link']);
+  assert.deepStrictEqual(ids(':not(:defined)'), ['custom']);
+  assert.deepStrictEqual(ids('p:lang(de), span:lang(de)'), ['de', 'inherit']);
+  assert.deepStrictEqual(ids('[id]:lang("*-DE")'), ['de', 'inherit']);
+  assert.deepStrictEqual(ids('p:lang(de-DE)'), ['de']);
+  assert.deepStrictEqual(ids('p:lang(en, fr)'), []);
+  assert.strictEqual(doc.querySelector('#link').matches(':lang(EN)'), true);
+  assert.deepStrictEqual(ids('[id]:dir(rtl)'), ['rtl', 'rtl-child', 'auto']);
+  assert.strictEqual(doc.querySelector('#bdi').matches(':dir(ltr)'), true);
+  assert.strictEqual(doc.querySelector('#root').matches(':dir(foo)'), false);
+});
+
+test('specificity and match details', () => {
+  const doc = parse('<div id="main"><p class="lead intro" title="x">text</p></div>');
+  const p = doc.querySelector('p');
+
+  const selector = new Selector('p, div > p.lead , #main p, :where(#main) p, p:is(.a, [title]), :not(#x) p, div:has(> p.lead), p:nth-child(1 of .lead.intro)');
+  assert.deepStrictEqual(selector.specificities, [
+    [0, 0, 1],
+    [0, 1, 2],
+    [1, 0, 1],
+    [0, 0, 1],
+    [0, 1, 1],
+    [1, 0, 1],
+    [0, 1, 2],
+    [0, 3, 1],
+  ]);
+  assert.deepStrictEqual(selector.matchDetails(p), { index: 2, selector: '#main p', specificity: [1, 0, 1] });
+  assert.deepStrictEqual(new Selector('p, div > p.lead').matchDetails(p).selector, 'div > p.lead');
+  assert.deepStrictEqual(new Selector('.lead, .intro').matchDetails(p).index, 0);
+  assert.strictEqual(new Selector('span').matchDetails(p), null);
+
+  const scoped = new Selector(':scope, :scope p, :scope > body > div');
+  assert.deepStrictEqual(scoped.matchDetails(p), { index: 1, selector: ':scope p', specificity: [0, 1, 1] });
+  assert.strictEqual(scoped.matchDetails(doc.querySelector('div')).index, 2);
+  assert.strictEqual(scoped.matchDetails(doc.documentElement).index, 0);
+  assert.strictEqual(new Selector(':scope').matchDetails(p), null);
+});
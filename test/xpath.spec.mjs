@@ -0,0 +1,181 @@
+import test from 'node:test';
+import assert from 'node:assert/strict';
+import pkg from '../domparser.js';
+const { DOMParser, XPathResult } = pkg;
+
+function parse(html) {
+  return new DOMParser().parseFromString(html, 'text/html');
+}
+
+function snapshot(doc, expression, context = doc) {
+  const result = doc.evaluate(expression, context, null, XPathResult.ORDERED_NODE_SNAPSHOT_TYPE, null);
+  const nodes = [];
+  for (let i = 0; i < result.snapshotLength; i++) {
+    nodes.push(result.snapshotItem(i));
+  }
+  return nodes;
+}
+
+const html = `
+  <div id="main" lang="en-GB">
+    <h1 class="title">Products</h1>
+    <ul>
+      <li class="item" data-price="10"><a href="/a">Apple</a></li>
+      <li class="item sale" data-price="5"><a href="/b">Banana</a></li>
+      <li class="item" data-price="20"><a href="https://example.com/c">Cherry</a></li>
+    </ul>
+    <!-- footer -->
+    <p>  Total:
+      <b>3</b>  </p>
+  </div>
+`;
+
+test('location paths and abbreviations', () => {
+  const doc = parse(html);
+
+  assert.deepStrictEqual(snapshot(doc, '//li/a').map(a => a.textContent), ['Apple', 'Banana', 'Cherry']);
+  assert.strictEqual(snapshot(doc, '/html/body/div/ul/li').length, 3);
+  assert.strictEqual(snapshot(doc, '//ul/li[2]/a')[0].textContent, 'Banana');
+  assert.strictEqual(snapshot(doc, '(//a)[last()]')[0].textContent, 'Cherry');
+  assert.strictEqual(snapshot(doc, '//a[.="Banana"]/../@data-price')[0].value, '5');
+  assert.strictEqual(snapshot(doc, '//comment()')[0].nodeValue, ' footer ');
+  assert.strictEqual(snapshot(doc, '//p/text()').length, 2);
+  assert.strictEqual(snapshot(doc, '//DIV').length, 1);
+
+  const ul = doc.querySelector('ul');
+  assert.strictEqual(snapshot(doc, 'li', ul).length, 3);
+  assert.strictEqual(snapshot(doc, './/a', ul).length, 3);
+  assert.strictEqual(snapshot(doc, '..', ul)[0].id, 'main');
+});
+
+test('axes', () => {
+  const doc = parse(html);
+  const banana = doc.querySelector('li.sale');
+
+  assert.deepStrictEqual(snapshot(doc, 'preceding-sibling::li/a', banana).map(a => a.textContent), ['Apple']);
+  assert.deepStrictEqual(snapshot(doc, 'following-sibling::li/a', banana).map(a => a.textContent), ['Cherry']);
+  assert.deepStrictEqual(
+    snapshot(doc, 'ancestor::*', banana).map(n => n.tagName),
+    ['HTML', 'BODY', 'DIV', 'UL']
+  );
+  assert.strictEqual(snapshot(doc, 'ancestor::*[1]', banana)[0].tagName, 'UL');
+  assert.strictEqual(snapshot(doc, 'preceding::*[1]', banana)[0].textContent, 'Apple');
+  assert.strictEqual(snapshot(doc, 'following::b', banana)[0].textContent, '3');
+  assert.strictEqual(snapshot(doc, 'descendant-or-self::li', banana).length, 1);
+  assert.strictEqual(snapshot(doc, 'self::li[@class]', banana).length, 1);
+  assert.strictEqual(snapshot(doc, 'namespace::*', banana).length, 0);
+});
+
+test('predicates and functions', () => {
+  const doc = parse(html);
+
+  assert.strictEqual(snapshot(doc, '//li[@data-price > 8]').length, 2);
+  assert.strictEqual(snapshot(doc, '//li[contains(@class, "sale")]/a')[0].textContent, 'Banana');
+  assert.strictEqual(snapshot(doc, '//a[starts-with(@href, "https:")]')[0].textContent, 'Cherry');
+  assert.strictEqual(snapshot(doc, '//li[position() mod 2 = 1]').length, 2);
+  assert.strictEqual(snapshot(doc, '//li[not(@data-price = 10)]').length, 2);
+  assert.strictEqual(snapshot(doc, 'id("main")/h1')[0].textContent, 'Products');
+  assert.strictEqual(snapshot(doc, '//*[@lang][lang("en")]').length, 1);
+  assert.strictEqual(snapshot(doc, '//h1 | //b | //h1').length, 2);
+
+  const evaluate = (expression, type) => doc.evaluate(expression, doc, null, type, null);
+  assert.strictEqual(evaluate('count(//li)', XPathResult.NUMBER_TYPE).numberValue, 3);
+  assert.strictEqual(evaluate('sum(//li/@data-price) div 7', XPathResult.NUMBER_TYPE).numberValue, 5);
+  assert.strictEqual(evaluate('normalize-space(//p)', XPathResult.STRING_TYPE).stringValue, 'Total: 3');
+  assert.strictEqual(evaluate('concat(name(//h1), "-", local-name(//h1/@class))', XPathResult.STRING_TYPE).stringValue, 'h1-class');
+  assert.strictEqual(evaluate('substring("12345", 1.5, 2.6)', XPathResult.STRING_TYPE).stringValue, '234');
+  assert.strictEqual(evaluate('translate("bar", "abc", "ABC")', XPathResult.STRING_TYPE).stringValue, 'BAr');
+  assert.strictEqual(evaluate('substring-after(//a[1]/@href, "/")', XPathResult.STRING_TYPE).stringValue, 'a');
+  assert.strictEqual(evaluate('string(1 div 0)', XPathResult.STRING_TYPE).stringValue, 'Infinity');
+  assert.strictEqual(evaluate('round(-2.5) + floor(1.7) + ceiling(1.2)', XPathResult.NUMBER_TYPE).numberValue, 1);
+  assert.strictEqual(evaluate('//li', XPathResult.BOOLEAN_TYPE).booleanValue, true);
+  assert.strictEqual(evaluate('//table', XPathResult.BOOLEAN_TYPE).booleanValue, false);
+  assert.strictEqual(evaluate('//a/@href', XPathResult.STRING_TYPE).stringValue, '/a');
+});
+
+test('result types', () => {
+  const doc = parse(html);
+
+  const any = doc.evaluate('count(//a)', doc, null, XPathResult.ANY_TYPE, null);
+  assert.strictEqual(any.resultType, XPathResult.NUMBER_TYPE);
+  assert.strictEqual(any.numberValue, 3);
+  assert.strictEqual(doc.evaluate('"x"', doc).resultType, XPathResult.STRING_TYPE);
+  assert.strictEqual(doc.evaluate('1 = 1', doc).resultType, XPathResult.BOOLEAN_TYPE);
+
+  const iterator = doc.evaluate('//a', doc, null, XPathResult.ANY_TYPE, null);
+  assert.strictEqual(iterator.resultType, XPathResult.UNORDERED_NODE_ITERATOR_TYPE);
+  const texts = [];
+  let node;
+  while ((node = iterator.iterateNext())) {
+    texts.push(node.textContent);
+  }
+  assert.deepStrictEqual(texts, ['Apple', 'Banana', 'Cherry']);
+  assert.strictEqual(iterator.invalidIteratorState, false);
+
+  const first = doc.evaluate('//a', doc, null, XPathResult.FIRST_ORDERED_NODE_TYPE, null);
+  assert.strictEqual(first.singleNodeValue.textContent, 'Apple');
+  assert.strictEqual(doc.evaluate('//table', doc, null, 9, null).singleNodeValue, null);
+
+  const attr = first.singleNodeValue.ownerDocument
+    .evaluate('//a/@href', doc, null, XPathResult.FIRST_ORDERED_NODE_TYPE, null).singleNodeValue;
+  assert.strictEqual(attr.nodeType, 2);
+  assert.strictEqual(attr.name, 'href');
+  assert.strictEqual(attr.ownerElement.textContent, 'Apple');
+
+  assert.strictEqual(XPathResult.ORDERED_NODE_SNAPSHOT_TYPE, 7);
+  assert.strictEqual(first.FIRST_ORDERED_NODE_TYPE, 9);
+  assert.throws(() => first.snapshotLength, TypeError);
+  assert.throws(() => doc.evaluate('1', doc, null, XPathResult.ORDERED_NODE_SNAPSHOT_TYPE, null), TypeError);
+});
+
+test('namespaces', () => {
+  const doc = parse('<svg><circle r="1"/><a xlink:href="#x"></a></svg>');
+
+  assert.strictEqual(snapshot(doc, '//svg/circle').length, 1);
+  assert.strictEqual(snapshot(doc, '//svg:circle').length, 1);
+  assert.strictEqual(snapshot(doc, '//html:body').length, 1);
+  assert.strictEqual(snapshot(doc, '//@xlink:href')[0].value, '#x');
+
+  const resolver = prefix => (prefix === 's' ? 'http://www.w3.org/2000/svg' : null);
+  const result = doc.evaluate('//s:circle', doc, resolver, XPathResult.ORDERED_NODE_SNAPSHOT_TYPE, null);
+  assert.strictEqual(result.snapshotLength, 1);
+  const lookup = { lookupNamespaceURI: resolver };
+  assert.strictEqual(doc.evaluate('count(//s:*)', doc, lookup, XPathResult.NUMBER_TYPE, null).numberValue, 3);
+
+  assert.throws(() => doc.evaluate('//q:circle', doc), error => error.name === 'NamespaceError');
+});
+
+test('invalid expressions throw a SyntaxError', () => {
+  const doc = parse(html);
+
+  for (const [expression, position] of [['//', 2], ['//a[', 4], ['foo(', 0], ['1 +', 3], ['"abc', 0], ['$var', 0], ['a b', 2]]) {
+    assert.throws(
+      () => doc.evaluate(expression, doc),
+      error => error instanceof DOMException && error.name === 'SyntaxError' && error.position === position,
+      expression
+    );
+  }
+  assert.throws(() => doc.evaluate('count(1)', doc), TypeError);
+});
+
+test('deeply nested or very long expressions throw a SyntaxError', () => {
+  const doc = parse(html);
+  const evaluate = (expression) => doc.evaluate(expression, doc, null, XPathResult.NUMBER_TYPE, null).numberValue;
+
+  assert.strictEqual(evaluate(`${'('.repeat(32)}1${')'.repeat(32)}`), 1);
+  assert.strictEqual(evaluate(`1${' + 1'.repeat(1024)}`), 1025);
+  assert.strictEqual(evaluate(`count(//li${'[1]'.repeat(30)})`), 1);
+
+  for (const expression of [
+    `${'('.repeat(100000)}1${')'.repeat(100000)}`,
+    `${'-'.repeat(100000)}1`,
+    `//a${'[a'.repeat(100000)}${']'.repeat(100000)}`,
+    `1${' or 1'.repeat(100000)}`,
+  ]) {
+    assert.throws(
+      () => doc.evaluate(expression, doc),
+      error => error instanceof DOMException && error.name === 'SyntaxError',
+      expression.slice(0, 10)
+    );
+  }
+});